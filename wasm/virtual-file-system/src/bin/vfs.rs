@@ -0,0 +1,421 @@
+// bin/vfs.rs
+//
+// Command line access to the CSV profiler and to workspace snapshots, for
+// use outside the browser:
+//
+//   vfs analyze <csv> [--format json|table]
+//   vfs sql <csv> [--table <name>]
+//   vfs categorical <csv> <column>
+//   vfs pack <dir> <snapshot>
+//   vfs unpack <snapshot> <dir>
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
+
+use virtual_file_system::core::{analyze_distribution, generate_sql_statements, Accuracy, CSVMetadata, CsvError, EntryKind, SupportedFileType, VirtualFileSystem};
+
+const USAGE: &str = "\
+usage: vfs <command> [args]
+
+commands:
+  analyze <csv> [--format json|table]  profile a CSV file
+  sql <csv> [--table <name>]           print the generated CREATE TABLE and INSERT
+  categorical <csv> <column>           show how a column was judged categorical
+  pack <dir> <snapshot>                store a directory as a snapshot, skipping symlinks
+  unpack <snapshot> <dir>              write the files of a snapshot to a directory";
+
+#[derive(Debug, PartialEq)]
+enum Format {
+    Json,
+    Table,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Analyze { csv: PathBuf, format: Format },
+    Sql { csv: PathBuf, table: Option<String> },
+    Categorical { csv: PathBuf, column: String },
+    Pack { dir: PathBuf, snapshot: PathBuf },
+    Unpack { snapshot: PathBuf, dir: PathBuf },
+    Help,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("vfs: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(command, &mut io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("vfs: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut positional = Vec::new();
+    let mut options = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--format" | "--table" => {
+                let value = rest.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                options.push((arg.as_str(), value.clone()));
+            }
+            option if option.starts_with("--") => return Err(format!("unknown option {}", option)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let (name, operands) = positional.split_first().ok_or("missing command")?;
+    let expect = |count: usize| -> Result<(), String> {
+        if operands.len() == count {
+            Ok(())
+        } else {
+            Err(format!("{} takes {} argument(s), got {}", name, count, operands.len()))
+        }
+    };
+    let option = |wanted: &str| -> Result<Option<String>, String> {
+        let mut value = None;
+        for (key, given) in &options {
+            if *key != wanted {
+                return Err(format!("{} does not take {}", name, key));
+            }
+            value = Some(given.clone());
+        }
+        Ok(value)
+    };
+    let no_options = || match options.first() {
+        Some((key, _)) => Err(format!("{} does not take {}", name, key)),
+        None => Ok(()),
+    };
+
+    match name.as_str() {
+        "analyze" => {
+            expect(1)?;
+            let format = match option("--format")?.as_deref() {
+                None | Some("json") => Format::Json,
+                Some("table") => Format::Table,
+                Some(other) => return Err(format!("unknown format {}", other)),
+            };
+            Ok(Command::Analyze { csv: operands[0].clone().into(), format })
+        }
+        "sql" => {
+            expect(1)?;
+            Ok(Command::Sql { csv: operands[0].clone().into(), table: option("--table")? })
+        }
+        "categorical" => {
+            expect(2)?;
+            no_options()?;
+            Ok(Command::Categorical { csv: operands[0].clone().into(), column: operands[1].clone() })
+        }
+        "pack" => {
+            expect(2)?;
+            no_options()?;
+            Ok(Command::Pack { dir: operands[0].clone().into(), snapshot: operands[1].clone().into() })
+        }
+        "unpack" => {
+            expect(2)?;
+            no_options()?;
+            Ok(Command::Unpack { snapshot: operands[0].clone().into(), dir: operands[1].clone().into() })
+        }
+        "help" => Ok(Command::Help),
+        other => Err(format!("unknown command {}", other)),
+    }
+}
+
+fn run(command: Command, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Analyze { csv, format } => {
+            let metadata = CSVMetadata::analyze_reader(fs::File::open(&csv)?)?;
+            match format {
+                Format::Json => writeln!(out, "{}", serde_json::to_string_pretty(&metadata)?)?,
+                Format::Table => print_table(&metadata, out)?,
+            }
+        }
+        Command::Sql { csv, table } => {
+            let metadata = CSVMetadata::analyze_reader(fs::File::open(&csv)?)?;
+            let table = table.unwrap_or_else(|| file_stem(&csv));
+            let statements = generate_sql_statements(&table, &metadata.columns);
+            writeln!(out, "{}\n\n{}", statements.create_table, statements.insert_template)?;
+        }
+        Command::Categorical { csv, column } => {
+            let data = fs::read(&csv)?;
+            let values = column_values(&data, &CSVMetadata::analyze(&data)?, &column)?;
+            writeln!(out, "{:#?}", analyze_distribution(&values))?;
+        }
+        Command::Pack { dir, snapshot } => {
+            let mut vfs = VirtualFileSystem::new();
+            add_directory(&mut vfs, &dir, &dir)?;
+            fs::write(&snapshot, vfs.export_snapshot())?;
+            writeln!(out, "packed {} file(s) into {}", vfs.list_files().len(), snapshot.display())?;
+        }
+        Command::Unpack { snapshot, dir } => {
+            let mut vfs = VirtualFileSystem::new();
+            vfs.import_snapshot(&fs::read(&snapshot)?)?;
+            let count = write_directory(&mut vfs, &dir)?;
+            writeln!(out, "unpacked {} file(s) into {}", count, dir.display())?;
+        }
+        Command::Help => writeln!(out, "{}", USAGE)?,
+    }
+    Ok(())
+}
+
+// `metadata` tells the dialect and the column names, which are generated
+// for files without a header.
+fn column_values(data: &[u8], metadata: &CSVMetadata, column: &str) -> Result<Vec<String>, CsvError> {
+    let mut reader = metadata.dialect.reader(data)?;
+    let index = metadata.column_names.iter()
+        .position(|name| name == column)
+        .ok_or_else(|| CsvError::ColumnNotFound { name: column.to_string() })?;
+
+    let mut values = Vec::new();
+    for record in reader.records() {
+        values.push(record?.get(index).unwrap_or_default().to_string());
+    }
+    Ok(values)
+}
+
+// One line per column, in file order. Estimated unique counts are marked
+// with a "~".
+fn print_table(metadata: &CSVMetadata, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "{} rows, {} columns\n", metadata.row_count, metadata.column_count)?;
+
+    let rows: Vec<[String; 5]> = metadata.column_names.iter()
+        .filter_map(|header| metadata.columns.get(header))
+        .map(|column| [
+            column.name.clone(),
+            match &column.date_stats {
+                Some(stats) => format!("{:?} ({})", column.data_type, stats.format),
+                None => format!("{:?}", column.data_type),
+            },
+            if column.nullable { "yes" } else { "no" }.to_string(),
+            match column.accuracy {
+                Accuracy::Exact => column.unique_count.to_string(),
+                Accuracy::Estimated => format!("~{}", column.unique_count),
+            },
+            column.sample_values.join(", "),
+        ])
+        .collect();
+
+    let titles = ["column", "type", "nullable", "unique", "samples"].map(String::from);
+    let mut widths = titles.clone().map(|title| title.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&titles).chain(&rows) {
+        let cells: Vec<String> = row.iter().zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        writeln!(out, "{}", cells.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+// The file name without its extension, made into a bare SQL identifier:
+// `my data.csv` becomes `my_data`.
+fn file_stem(file: &Path) -> String {
+    let stem: String = file.file_stem()
+        .map(|stem| stem.to_string_lossy().chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect())
+        .unwrap_or_default();
+    match stem.chars().next() {
+        None => "my_table".to_string(),
+        Some(first) if first.is_ascii_digit() => format!("_{}", stem),
+        Some(_) => stem,
+    }
+}
+
+// Adds every file and directory below `dir`, with paths relative to `root`.
+// Symlinks are skipped: they could lead out of the tree, or back up it.
+fn add_directory(vfs: &mut VirtualFileSystem, root: &Path, dir: &Path) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            continue;
+        }
+        let entry_path = entry.path();
+        let relative = entry_path.strip_prefix(root)?.to_string_lossy().replace('\\', "/");
+
+        if file_type.is_dir() {
+            vfs.mkdir(&relative)?;
+            add_directory(vfs, root, &entry_path)?;
+        } else {
+            let file_type = SupportedFileType::from_extension(&relative);
+            vfs.create_file_at(&relative, file_type, fs::read(&entry_path)?)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_directory(vfs: &mut VirtualFileSystem, dir: &Path) -> Result<usize, Box<dyn Error>> {
+    // every path is checked before anything is written
+    let entries = vfs.list_dir_recursive("/")?.into_iter()
+        .map(|entry| Ok((target_path(dir, &entry.path)?, entry)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    fs::create_dir_all(dir)?;
+    let mut count = 0;
+    for (target, entry) in entries {
+        match (entry.kind, entry.file_id) {
+            (EntryKind::File, Some(id)) => {
+                fs::write(target, vfs.get_file_content(&id)?)?;
+                count += 1;
+            }
+            _ => fs::create_dir_all(target)?,
+        }
+    }
+    Ok(count)
+}
+
+// Where the entry at `vfs_path` goes under `dir`. Snapshots are checked on
+// import, but whatever they hold nothing may be written outside `dir`.
+fn target_path(dir: &Path, vfs_path: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(vfs_path.trim_start_matches('/'));
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(format!("refusing to write {} outside {}", vfs_path, dir.display()));
+    }
+    Ok(dir.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(&args("sql data.csv --table pokemon")).unwrap(),
+            Command::Sql { csv: "data.csv".into(), table: Some("pokemon".to_string()) }
+        );
+        assert_eq!(
+            parse_args(&args("analyze --format table data.csv")).unwrap(),
+            Command::Analyze { csv: "data.csv".into(), format: Format::Table }
+        );
+        assert_eq!(parse_args(&args("pack --help")).unwrap(), Command::Help);
+        assert!(parse_args(&args("analyze data.csv --table x")).is_err());
+        assert!(parse_args(&args("categorical data.csv")).is_err());
+        assert!(parse_args(&[]).is_err());
+    }
+
+    #[test]
+    fn test_pack_and_unpack() {
+        let base = std::env::temp_dir().join(format!("vfs-cli-{}", std::process::id()));
+        let source = base.join("source");
+        fs::create_dir_all(source.join("datasets/empty")).unwrap();
+        fs::write(source.join("datasets/a.csv"), "a,b\n1,2").unwrap();
+        fs::write(source.join("notes.txt"), "hello").unwrap();
+
+        let snapshot = base.join("workspace.snap");
+        let mut out = Vec::new();
+        run(Command::Pack { dir: source.clone(), snapshot: snapshot.clone() }, &mut out).unwrap();
+        run(Command::Unpack { snapshot, dir: base.join("copy") }, &mut out).unwrap();
+
+        assert_eq!(fs::read_to_string(base.join("copy/datasets/a.csv")).unwrap(), "a,b\n1,2");
+        assert_eq!(fs::read_to_string(base.join("copy/notes.txt")).unwrap(), "hello");
+        assert!(base.join("copy/datasets/empty").is_dir());
+        assert!(String::from_utf8(out).unwrap().contains("unpacked 2 file(s)"));
+
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pack_skips_symlinks() {
+        let base = std::env::temp_dir().join(format!("vfs-cli-links-{}", std::process::id()));
+        let source = base.join("source");
+        fs::create_dir_all(source.join("data")).unwrap();
+        fs::write(source.join("data/a.csv"), "a\n1").unwrap();
+        fs::write(base.join("outside.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(&source, source.join("data/loop")).unwrap();
+        std::os::unix::fs::symlink(base.join("outside.txt"), source.join("outside.txt")).unwrap();
+
+        let snapshot = base.join("workspace.snap");
+        let mut out = Vec::new();
+        run(Command::Pack { dir: source, snapshot: snapshot.clone() }, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("packed 1 file(s)"));
+
+        let mut vfs = VirtualFileSystem::new();
+        vfs.import_snapshot(&fs::read(snapshot).unwrap()).unwrap();
+        assert!(vfs.exists("/data/a.csv"));
+        assert!(!vfs.exists("/outside.txt") && !vfs.exists("/data/loop"));
+
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn test_table_name_from_file() {
+        assert_eq!(file_stem(Path::new("data/my data.csv")), "my_data");
+        assert_eq!(file_stem(Path::new("2024-sales.csv")), "_2024_sales");
+        assert_eq!(file_stem(Path::new("pokemon.csv")), "pokemon");
+        assert_eq!(file_stem(Path::new("")), "my_table");
+    }
+
+    #[test]
+    fn test_unpack_stays_inside_its_directory() {
+        let dir = Path::new("out");
+        assert_eq!(target_path(dir, "/datasets/a.csv").unwrap(), dir.join("datasets/a.csv"));
+        for path in ["/../escaped.txt", "/a/../../escaped.txt", "/./a.txt", "//../escaped.txt"] {
+            assert!(target_path(dir, path).is_err(), "{}", path);
+        }
+
+        // a forged snapshot is refused before anything is written
+        let base = std::env::temp_dir().join(format!("vfs-cli-escape-{}", std::process::id()));
+        fs::create_dir_all(&base).unwrap();
+        let mut vfs = VirtualFileSystem::new();
+        vfs.create_file_at("/escaped.txt", SupportedFileType::Text, b"x".to_vec()).unwrap();
+        let snapshot = vfs.export_snapshot();
+        let manifest_len = u32::from_le_bytes(snapshot[10..14].try_into().unwrap()) as usize;
+        let manifest = String::from_utf8(snapshot[14..14 + manifest_len].to_vec()).unwrap()
+            .replace("\"/escaped.txt\"", "\"/../escaped.txt\"");
+        let mut forged = snapshot[..10].to_vec();
+        forged.extend_from_slice(&(manifest.len() as u32).to_le_bytes());
+        forged.extend_from_slice(manifest.as_bytes());
+        forged.extend_from_slice(&snapshot[14 + manifest_len..]);
+        fs::write(base.join("forged.snap"), forged).unwrap();
+
+        let mut out = Vec::new();
+        assert!(run(Command::Unpack { snapshot: base.join("forged.snap"), dir: base.join("copy") }, &mut out).is_err());
+        assert!(!base.join("escaped.txt").exists());
+        assert!(!base.join("copy").exists());
+
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn test_analyze_table() {
+        let csv = std::env::temp_dir().join(format!("vfs-cli-{}.csv", std::process::id()));
+        fs::write(&csv, "id,name\n1,a\n2,b").unwrap();
+
+        let mut out = Vec::new();
+        run(Command::Analyze { csv: csv.clone(), format: Format::Table }, &mut out).unwrap();
+        let table = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "2 rows, 2 columns");
+        assert!(lines[2].starts_with("column  type"));
+        assert!(lines[3].starts_with("id      Integer"));
+
+        let mut out = Vec::new();
+        assert!(run(Command::Categorical { csv: csv.clone(), column: "missing".to_string() }, &mut out).is_err());
+        fs::remove_file(csv).unwrap();
+    }
+}
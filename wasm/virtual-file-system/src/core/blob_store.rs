@@ -0,0 +1,587 @@
+// core/blob_store.rs
+//
+// Content addressed storage for file bytes. Blobs are keyed by the SHA-256
+// checksum of their content, so identical uploads share a single copy.
+// Each blob counts the file records that point at it and is dropped when
+// the last reference is released.
+//
+// With a memory budget set, the least recently used blobs are evicted once
+// the resident bytes go over it. An evicted blob keeps its checksum, size
+// and references, only the bytes are gone until the host supplies them
+// again through a `ContentProvider` or `VirtualFileSystem::rehydrate`.
+// A blob the provider fails to take is kept resident, so eviction never
+// drops the only copy of content the host asked to keep. Content that can't
+// be fit under the budget even after evicting everything else is refused
+// with `QuotaExceeded`.
+//
+// Pinned blobs are never evicted and their bytes don't count against the
+// budget. The file system pins the content the storage backend doesn't
+// have yet, which may be the only copy there is, and unpins it once it is
+// saved. Eviction can also be suspended altogether, which transactions do.
+//
+// Blobs can be held compressed, see `core::compression`. The checksum and
+// `size` are always those of the uncompressed content, while the memory
+// budget counts the bytes actually held.
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use sha2::{Sha256, Digest};
+
+use crate::core::compression::{compress_if_smaller, Compression};
+use crate::core::error::{VfsError, VfsResult};
+
+pub fn calculate_checksum(content: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Host hook for evicted content. `store` is offered the bytes of a blob
+/// right before they are dropped from memory, which only happens once it
+/// returns `Ok`; `load` is asked for them when the blob is needed again.
+pub trait ContentProvider {
+    fn store(&mut self, _checksum: &str, _content: &[u8]) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn load(&mut self, checksum: &str) -> Option<Vec<u8>>;
+}
+
+#[derive(Debug, Clone)]
+struct Blob {
+    // None once evicted. Shared so checkpoints don't copy content.
+    data: Option<Rc<[u8]>>,
+    // uncompressed size
+    size: usize,
+    // how `data` is encoded, fixed for the life of the blob
+    compression: Compression,
+    ref_count: usize,
+    last_access: u64,
+}
+
+/// Saved blob state, see `BlobStore::checkpoint`.
+pub struct BlobCheckpoint {
+    blobs: HashMap<String, Blob>,
+}
+
+#[derive(Default)]
+pub struct BlobStore {
+    blobs: HashMap<String, Blob>,
+    // monotonic counter used as the LRU clock
+    tick: u64,
+    resident_bytes: usize,
+    budget: Option<usize>,
+    provider: Option<Box<dyn ContentProvider>>,
+    pinned: HashSet<String>,
+    // whether content added from now on is pinned
+    pin_new: bool,
+    eviction_suspended: bool,
+}
+
+impl fmt::Debug for BlobStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlobStore")
+            .field("blobs", &self.blobs.len())
+            .field("resident_bytes", &self.resident_bytes)
+            .field("budget", &self.budget)
+            .field("provider", &self.provider.is_some())
+            .field("pinned", &self.pinned.len())
+            .finish()
+    }
+}
+
+impl BlobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `content` and takes one reference to it, returning its checksum.
+    /// If the same bytes are already stored the new copy is dropped.
+    /// Fails with `QuotaExceeded`, taking no reference, if new bytes don't
+    /// fit the memory budget.
+    pub fn insert(&mut self, content: Vec<u8>) -> VfsResult<String> {
+        let checksum = calculate_checksum(&content);
+        self.insert_with_checksum(checksum.clone(), content)?;
+        Ok(checksum)
+    }
+
+    /// Same as `insert` for callers that already hashed the content.
+    pub fn insert_with_checksum(&mut self, checksum: String, content: Vec<u8>) -> VfsResult<()> {
+        self.insert_compressed(checksum, content, Compression::None).map(|_| ())
+    }
+
+    /// Same as `insert_with_checksum`, compressing new content with
+    /// `compression` if that makes it smaller. Content that is already
+    /// stored keeps its form. Returns how the blob is stored and the bytes
+    /// it takes.
+    pub fn insert_compressed(&mut self, checksum: String, content: Vec<u8>, compression: Compression) -> VfsResult<(Compression, usize)> {
+        let size = content.len();
+        let compression = self.blobs.get(&checksum).map_or(compression, |blob| blob.compression);
+        // A new reference to evicted content brings the bytes back for free.
+        if self.is_resident(&checksum) {
+            self.insert_stored(checksum.clone(), size, compression, None)?;
+        } else {
+            let (compression, data) = compress_if_smaller(content, compression);
+            self.insert_stored(checksum.clone(), size, compression, Some(data))?;
+        }
+
+        let (compression, data) = self.get_stored(&checksum).expect("just inserted and pinned");
+        Ok((compression, data.len()))
+    }
+
+    /// Takes one reference to a blob given in its stored form, e.g. read
+    /// back from a snapshot. `data` must be `size` bytes of content once
+    /// decompressed with `compression`; `None` registers the blob as
+    /// evicted. Bytes for a blob that is already resident are dropped.
+    pub fn insert_stored(&mut self, checksum: String, size: usize, compression: Compression, data: Option<Vec<u8>>) -> VfsResult<()> {
+        let tick = self.next_tick();
+        let blob = self.blobs.entry(checksum.clone())
+            .or_insert(Blob { data: None, size, compression, ref_count: 0, last_access: tick });
+
+        blob.ref_count += 1;
+        blob.last_access = tick;
+        if let (None, Some(data)) = (&blob.data, data) {
+            let previous = blob.compression;
+            self.resident_bytes += data.len();
+            blob.compression = compression;
+            blob.data = Some(data.into());
+            if self.pin_new {
+                self.pinned.insert(checksum);
+            } else if let Err(error) = self.admit(&checksum, previous) {
+                self.release(&checksum);
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Registers a blob whose bytes are not available yet, e.g. one that
+    /// was evicted when a snapshot was taken. Takes one reference.
+    pub fn insert_evicted(&mut self, checksum: String, size: usize, compression: Compression) {
+        self.insert_stored(checksum, size, compression, None)
+            .expect("registering evicted content takes no memory");
+    }
+
+    /// Takes an extra reference to an existing blob.
+    pub fn retain(&mut self, checksum: &str) -> bool {
+        match self.blobs.get_mut(checksum) {
+            Some(blob) => {
+                blob.ref_count += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops one reference, removing the blob once nothing points at it.
+    pub fn release(&mut self, checksum: &str) {
+        if let Some(blob) = self.blobs.get_mut(checksum) {
+            blob.ref_count = blob.ref_count.saturating_sub(1);
+            if blob.ref_count == 0 {
+                if let Some(data) = self.blobs.remove(checksum).and_then(|blob| blob.data) {
+                    self.resident_bytes -= data.len();
+                }
+                self.pinned.remove(checksum);
+            }
+        }
+    }
+
+    /// Content of a resident blob, decompressed if needed, without touching
+    /// its LRU position.
+    pub fn get(&self, checksum: &str) -> Option<Cow<'_, [u8]>> {
+        let blob = self.blobs.get(checksum)?;
+        let data = blob.data.as_deref()?;
+        Some(match blob.compression {
+            Compression::None => Cow::Borrowed(data),
+            // Stored bytes were either compressed here or checked on import.
+            compression => Cow::Owned(compression.decompress(data, blob.size).expect("stored blobs always decompress")),
+        })
+    }
+
+    /// Resident bytes of a blob as stored, with their compression.
+    pub fn get_stored(&self, checksum: &str) -> Option<(Compression, &[u8])> {
+        let blob = self.blobs.get(checksum)?;
+        Some((blob.compression, blob.data.as_deref()?))
+    }
+
+    /// Bytes of a blob for actual use: marks it as recently used and, if it
+    /// was evicted, asks the content provider for it. Returns `None` when
+    /// the blob is unknown or evicted content could not be recovered, and
+    /// `QuotaExceeded` when recovered content doesn't fit the budget.
+    pub fn load(&mut self, checksum: &str) -> VfsResult<Option<Cow<'_, [u8]>>> {
+        let tick = self.next_tick();
+        let Some(blob) = self.blobs.get_mut(checksum) else { return Ok(None) };
+        blob.last_access = tick;
+
+        if blob.data.is_none() {
+            let Some(content) = self.provider.as_mut().and_then(|provider| provider.load(checksum)) else {
+                return Ok(None);
+            };
+            // Wrong bytes from the provider just leave the blob evicted.
+            if let Err(error @ VfsError::QuotaExceeded { .. }) = self.restore(checksum, content) {
+                return Err(error);
+            }
+        }
+        Ok(self.get(checksum))
+    }
+
+    /// Puts the bytes of an evicted blob back, after checking they match.
+    /// `content` is uncompressed and stored the way the blob was before.
+    /// Fails with `QuotaExceeded` if the bytes don't fit the budget, the
+    /// blob then stays evicted.
+    pub fn restore(&mut self, checksum: &str, content: Vec<u8>) -> VfsResult<()> {
+        let actual = calculate_checksum(&content);
+        if actual != checksum {
+            return Err(VfsError::ChecksumMismatch { expected: checksum.to_string(), actual });
+        }
+
+        let tick = self.next_tick();
+        let Some(blob) = self.blobs.get_mut(checksum) else { return Ok(()) };
+        blob.last_access = tick;
+        if blob.data.is_some() {
+            return Ok(());
+        }
+        let previous = blob.compression;
+        let (compression, data) = compress_if_smaller(content, blob.compression);
+        self.resident_bytes += data.len();
+        blob.compression = compression;
+        blob.data = Some(data.into());
+        self.admit(checksum, previous)
+    }
+
+    pub fn contains(&self, checksum: &str) -> bool {
+        self.blobs.contains_key(checksum)
+    }
+
+    pub fn is_resident(&self, checksum: &str) -> bool {
+        self.blobs.get(checksum).is_some_and(|blob| blob.data.is_some())
+    }
+
+    pub fn ref_count(&self, checksum: &str) -> usize {
+        self.blobs.get(checksum).map_or(0, |blob| blob.ref_count)
+    }
+
+    pub fn len(&self) -> usize {
+        self.blobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blobs.is_empty()
+    }
+
+    /// Every blob as (checksum, size, compression, resident bytes as stored).
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize, Compression, Option<&[u8]>)> {
+        self.blobs.iter().map(|(checksum, blob)| (checksum.as_str(), blob.size, blob.compression, blob.data.as_deref()))
+    }
+
+    /// Bytes actually held in memory across all blobs.
+    pub fn stored_bytes(&self) -> usize {
+        self.resident_bytes
+    }
+
+    pub fn evicted_count(&self) -> usize {
+        self.blobs.values().filter(|blob| blob.data.is_none()).count()
+    }
+
+    pub fn budget(&self) -> Option<usize> {
+        self.budget
+    }
+
+    /// Sets the memory budget in bytes (`None` for unlimited) and evicts
+    /// right away if the store is over it. Fails if the provider did not
+    /// take some of the blobs, which then stay resident.
+    pub fn set_budget(&mut self, budget: Option<usize>) -> VfsResult<()> {
+        self.budget = budget;
+        self.enforce_budget("")
+    }
+
+    pub fn set_provider(&mut self, provider: Option<Box<dyn ContentProvider>>) {
+        self.provider = provider;
+    }
+
+    /// Whether content added from now on is pinned, see `unpin`.
+    pub fn set_pin_new(&mut self, pin: bool) {
+        self.pin_new = pin;
+    }
+
+    pub fn pins_new(&self) -> bool {
+        self.pin_new
+    }
+
+    /// Stops evicting until `resume_eviction`, whatever the budget.
+    pub fn suspend_eviction(&mut self) {
+        self.eviction_suspended = true;
+    }
+
+    /// Evicts again, right away if the store went over budget meanwhile.
+    /// Blobs the provider doesn't take stay resident until the next
+    /// eviction.
+    pub fn resume_eviction(&mut self) {
+        self.eviction_suspended = false;
+        let _ = self.enforce_budget("");
+    }
+
+    /// Pins every resident blob.
+    pub fn pin_resident(&mut self) {
+        let resident = self.blobs.iter().filter(|(_, blob)| blob.data.is_some());
+        self.pinned.extend(resident.map(|(checksum, _)| checksum.clone()));
+    }
+
+    /// Unpins the blobs `unpin` returns true for and evicts if that puts the
+    /// store over budget. Blobs the provider doesn't take stay resident
+    /// until the next eviction.
+    pub fn unpin(&mut self, unpin: impl Fn(&str) -> bool) {
+        let before = self.pinned.len();
+        self.pinned.retain(|checksum| !unpin(checksum));
+        if self.pinned.len() < before {
+            let _ = self.enforce_budget("");
+        }
+    }
+
+    /// Captures every blob and reference count. Content is shared, not copied.
+    pub fn checkpoint(&self) -> BlobCheckpoint {
+        BlobCheckpoint { blobs: self.blobs.clone() }
+    }
+
+    /// Returns to the state of `checkpoint`. Blobs evicted since come back
+    /// resident and are evicted again if the budget requires it. Going back
+    /// can't be refused, so this may leave the store over budget.
+    pub fn restore_checkpoint(&mut self, checkpoint: BlobCheckpoint) {
+        self.blobs = checkpoint.blobs;
+        self.resident_bytes = self.blobs.values()
+            .filter_map(|blob| blob.data.as_ref().map(|data| data.len()))
+            .sum();
+        let blobs = &self.blobs;
+        self.pinned.retain(|checksum| blobs.contains_key(checksum));
+        let _ = self.enforce_budget("");
+    }
+
+    /// Swaps in the blobs and pins of `other`, keeping this store's budget,
+    /// provider and `set_pin_new`. Fails with the eviction error if the new
+    /// blobs can't be brought under the budget; they are swapped in
+    /// regardless.
+    pub fn replace_blobs(&mut self, other: BlobStore) -> VfsResult<()> {
+        self.blobs = other.blobs;
+        self.tick = other.tick;
+        self.resident_bytes = other.resident_bytes;
+        self.pinned = other.pinned;
+        self.enforce_budget("")
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    // Makes room for the bytes just put into blob `checksum`, which was
+    // stored as `previous` before. If they don't fit they are dropped again,
+    // leaving the blob as it was, and `QuotaExceeded` is returned.
+    fn admit(&mut self, checksum: &str, previous: Compression) -> VfsResult<()> {
+        if self.enforce_budget(checksum).is_ok() {
+            return Ok(());
+        }
+        let blob = self.blobs.get_mut(checksum).expect("admitted blobs exist");
+        let requested = blob.data.take().map_or(0, |data| data.len());
+        blob.compression = previous;
+        self.resident_bytes -= requested;
+        let available = self.budget.unwrap_or(usize::MAX).saturating_sub(self.resident_bytes - self.pinned_bytes());
+        Err(VfsError::QuotaExceeded { requested, available })
+    }
+
+    fn pinned_bytes(&self) -> usize {
+        self.pinned.iter()
+            .filter_map(|checksum| self.blobs.get(checksum)?.data.as_ref().map(|data| data.len()))
+            .sum()
+    }
+
+    // Evicts least recently used blobs until the resident bytes fit the
+    // budget. `in_use` is the blob being used right now and is never evicted,
+    // nor are pinned blobs, whose bytes are left out of the count. A blob
+    // that can't be handed to the provider stays resident and the next
+    // candidate is tried. If the store is still over budget at the end the
+    // first such error is returned, or `QuotaExceeded` if there was none.
+    fn enforce_budget(&mut self, in_use: &str) -> VfsResult<()> {
+        let Some(budget) = self.budget.filter(|_| !self.eviction_suspended) else { return Ok(()) };
+        let limit = budget + self.pinned_bytes();
+        if self.resident_bytes <= limit {
+            return Ok(());
+        }
+
+        let mut candidates: Vec<(u64, String)> = self.blobs.iter()
+            .filter(|(checksum, blob)| blob.data.is_some() && checksum.as_str() != in_use && !self.pinned.contains(checksum.as_str()))
+            .map(|(checksum, blob)| (blob.last_access, checksum.clone()))
+            .collect();
+        candidates.sort();
+
+        let mut failure = None;
+        for (_, checksum) in candidates {
+            if self.resident_bytes <= limit {
+                break;
+            }
+            let Some(blob) = self.blobs.get_mut(&checksum) else { continue };
+            let Some(data) = blob.data.as_ref() else { continue };
+            if let Some(provider) = self.provider.as_mut() {
+                let stored = blob.compression.decompress(data, blob.size)
+                    .map_err(Into::into)
+                    .and_then(|content| provider.store(&checksum, &content));
+                if let Err(error) = stored {
+                    failure.get_or_insert(error);
+                    continue;
+                }
+            }
+            if let Some(data) = blob.data.take() {
+                self.resident_bytes -= data.len();
+            }
+        }
+        if self.resident_bytes > limit {
+            let requested = self.resident_bytes - self.pinned_bytes();
+            return Err(failure.unwrap_or(VfsError::QuotaExceeded { requested, available: budget }));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use crate::core::error::VfsError;
+    use std::rc::Rc;
+
+    #[derive(Default, Clone)]
+    struct MemoryProvider {
+        stored: Rc<RefCell<HashMap<String, Vec<u8>>>>,
+    }
+
+    impl ContentProvider for MemoryProvider {
+        fn store(&mut self, checksum: &str, content: &[u8]) -> VfsResult<()> {
+            self.stored.borrow_mut().insert(checksum.to_string(), content.to_vec());
+            Ok(())
+        }
+
+        fn load(&mut self, checksum: &str) -> Option<Vec<u8>> {
+            self.stored.borrow().get(checksum).cloned()
+        }
+    }
+
+    #[test]
+    fn test_identical_content_is_shared() {
+        let mut store = BlobStore::new();
+        let a = store.insert(vec![1, 2, 3]).unwrap();
+        let b = store.insert(vec![1, 2, 3]).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.ref_count(&a), 2);
+        assert_eq!(store.stored_bytes(), 3);
+    }
+
+    #[test]
+    fn test_release_drops_unreferenced_blobs() {
+        let mut store = BlobStore::new();
+        let checksum = store.insert(vec![1, 2, 3]).unwrap();
+        assert!(store.retain(&checksum));
+
+        store.release(&checksum);
+        assert_eq!(store.get(&checksum).as_deref(), Some(&[1u8, 2, 3][..]));
+
+        store.release(&checksum);
+        assert!(!store.contains(&checksum));
+        assert!(store.is_empty());
+        assert!(!store.retain(&checksum));
+        assert_eq!(store.stored_bytes(), 0);
+    }
+
+    #[test]
+    fn test_budget_evicts_least_recently_used() {
+        let mut store = BlobStore::new();
+        store.set_budget(Some(8)).unwrap();
+        let a = store.insert(vec![1; 4]).unwrap();
+        let b = store.insert(vec![2; 4]).unwrap();
+        assert!(store.load(&a).unwrap().is_some());
+
+        // a was used after b, so b goes first
+        let c = store.insert(vec![3; 4]).unwrap();
+        assert!(store.is_resident(&a));
+        assert!(!store.is_resident(&b));
+        assert!(store.is_resident(&c));
+        assert_eq!(store.stored_bytes(), 8);
+        assert_eq!(store.evicted_count(), 1);
+
+        // without a provider evicted content stays gone until restored
+        assert!(store.load(&b).unwrap().is_none());
+        assert!(store.restore(&b, vec![9; 4]).is_err());
+        store.restore(&b, vec![2; 4]).unwrap();
+        assert!(store.is_resident(&b));
+        assert_eq!(store.stored_bytes(), 8);
+    }
+
+    #[test]
+    fn test_provider_round_trip() {
+        let provider = MemoryProvider::default();
+        let mut store = BlobStore::new();
+        store.set_provider(Some(Box::new(provider.clone())));
+        store.set_budget(Some(4)).unwrap();
+
+        let a = store.insert(vec![1; 4]).unwrap();
+        store.insert(vec![2; 4]).unwrap();
+        assert!(provider.stored.borrow().contains_key(&a));
+        assert!(!store.is_resident(&a));
+
+        assert_eq!(store.load(&a).unwrap().as_deref(), Some(&[1u8; 4][..]));
+        assert_eq!(store.evicted_count(), 1);
+    }
+
+    struct FailingProvider;
+
+    impl ContentProvider for FailingProvider {
+        fn store(&mut self, _checksum: &str, _content: &[u8]) -> VfsResult<()> {
+            Err(VfsError::Storage { reason: "disk full".to_string() })
+        }
+
+        fn load(&mut self, _checksum: &str) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_failed_store_keeps_blob_resident() {
+        let mut store = BlobStore::new();
+        store.set_provider(Some(Box::new(FailingProvider)));
+        let a = store.insert(vec![1; 4]).unwrap();
+        let b = store.insert(vec![2; 4]).unwrap();
+
+        assert!(matches!(store.set_budget(Some(4)), Err(VfsError::Storage { .. })));
+        assert!(store.is_resident(&a) && store.is_resident(&b));
+        assert_eq!(store.evicted_count(), 0);
+        assert_eq!(store.stored_bytes(), 8);
+
+        // nothing can make room for more content
+        let error = store.insert(vec![3; 4]).unwrap_err();
+        assert!(matches!(error, VfsError::QuotaExceeded { requested: 4, available: 0 }), "{:?}", error);
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.stored_bytes(), 8);
+        assert_eq!(store.load(&a).unwrap().as_deref(), Some(&[1u8; 4][..]));
+    }
+
+    #[test]
+    fn test_content_over_budget_is_refused() {
+        let provider = MemoryProvider::default();
+        let mut store = BlobStore::new();
+        store.set_provider(Some(Box::new(provider.clone())));
+        store.set_budget(Some(4)).unwrap();
+        let a = store.insert(vec![1; 4]).unwrap();
+
+        let error = store.insert(vec![2; 6]).unwrap_err();
+        assert!(matches!(error, VfsError::QuotaExceeded { requested: 6, available: 4 }), "{:?}", error);
+        assert_eq!(store.len(), 1);
+        assert!(!store.is_resident(&a));
+
+        // evicted content that no longer fits stays evicted
+        store.set_budget(Some(2)).unwrap();
+        assert!(matches!(store.load(&a), Err(VfsError::QuotaExceeded { requested: 4, available: 2 })));
+        assert!(!store.is_resident(&a));
+        assert_eq!(store.stored_bytes(), 0);
+        assert_eq!(store.ref_count(&a), 1);
+    }
+}
@@ -0,0 +1,65 @@
+// core/clock.rs
+//
+// Wall clock access. `std::time::SystemTime` panics on
+// wasm32-unknown-unknown, so the browser build asks JS for the time.
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Milliseconds since the Unix epoch.
+#[cfg(target_arch = "wasm32")]
+pub fn now_millis() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+/// Milliseconds since the Unix epoch.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_millis() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Time source for timestamps on files and versions. Swap in a
+/// `ManualClock` to make tests deterministic.
+pub trait Clock {
+    fn now_millis(&self) -> u64;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        now_millis()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so
+/// a test can keep one and hand the other to the file system.
+#[derive(Debug, Default, Clone)]
+pub struct ManualClock {
+    now: Rc<Cell<u64>>,
+}
+
+impl ManualClock {
+    pub fn new(start: u64) -> Self {
+        ManualClock { now: Rc::new(Cell::new(start)) }
+    }
+
+    pub fn set(&self, millis: u64) {
+        self.now.set(millis);
+    }
+
+    pub fn advance(&self, millis: u64) {
+        self.now.set(self.now.get() + millis);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.now.get()
+    }
+}
@@ -0,0 +1,259 @@
+// core/compression.rs
+//
+// Transparent compression of file content. Each blob is held either raw or
+// compressed with one `Compression` codec, decided once when the content is
+// first stored, from the file system's `CompressionPolicy`. Checksums,
+// `size` and everything read back always refer to the uncompressed bytes,
+// so identical content is still shared whatever it is stored as;
+// `stored_size` on the record is what the content takes in memory.
+use std::io::{self, Read, Write};
+use serde::{Serialize, Deserialize};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+use crate::core::error::VfsResult;
+use crate::core::file_system::{SupportedFileType, VirtualFileSystem};
+use crate::core::versioning::FileVersion;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// Best ratio, a good fit for CSV and JSON that sit around.
+    Deflate,
+    /// Much faster both ways for a somewhat lower ratio.
+    Lz4,
+}
+
+/// Which content gets compressed, see `VirtualFileSystem::set_compression_policy`.
+/// The default compresses nothing.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct CompressionPolicy {
+    pub codec: Compression,
+    /// Types compressed whatever their size.
+    #[serde(default)]
+    pub file_types: Vec<SupportedFileType>,
+    /// Content of at least this many bytes is compressed too, unless its
+    /// type is a binary format that is compressed already.
+    #[serde(default)]
+    pub min_size: Option<usize>,
+}
+
+impl Compression {
+    pub fn compress(self, content: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => content.to_vec(),
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(content)
+                    .and_then(|_| encoder.finish())
+                    .expect("writing to a Vec cannot fail")
+            }
+            Compression::Lz4 => lz4_flex::block::compress(content),
+        }
+    }
+
+    /// Reverses `compress`. `size` is the length of the original content.
+    /// It may come from an untrusted record, so a size `data` could never
+    /// decompress to is refused before anything is allocated for it.
+    pub fn decompress(self, data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+        if size > data.len().saturating_mul(self.max_ratio()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "content size out of proportion to the compressed data"));
+        }
+        let content = match self {
+            Compression::None => data.to_vec(),
+            Compression::Deflate => {
+                let mut content = Vec::new();
+                DeflateDecoder::new(data).take(size as u64 + 1).read_to_end(&mut content)?;
+                content
+            }
+            Compression::Lz4 => lz4_flex::block::decompress(data, size)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        };
+        if content.len() != size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "decompressed content has the wrong size"));
+        }
+        Ok(content)
+    }
+
+    // Upper bound of original to compressed length: deflate can't do
+    // better than 1032:1, an LZ4 block than 255:1.
+    fn max_ratio(self) -> usize {
+        match self {
+            Compression::None => 1,
+            Compression::Deflate => 1032,
+            Compression::Lz4 => 255,
+        }
+    }
+}
+
+impl CompressionPolicy {
+    pub fn new(codec: Compression) -> Self {
+        CompressionPolicy { codec, file_types: Vec::new(), min_size: None }
+    }
+
+    pub fn file_types(mut self, file_types: Vec<SupportedFileType>) -> Self {
+        self.file_types = file_types;
+        self
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = Some(min_size);
+        self
+    }
+
+    /// Codec for new content of this type and size.
+    pub fn codec_for(&self, file_type: &SupportedFileType, size: usize) -> Compression {
+        let listed = self.file_types.contains(file_type);
+        let large = !file_type.is_binary() && self.min_size.is_some_and(|min| size >= min);
+        if listed || large {
+            self.codec
+        } else {
+            Compression::None
+        }
+    }
+}
+
+impl VirtualFileSystem {
+    /// Sets how content stored from now on is compressed. Content already
+    /// stored keeps its current form.
+    pub fn set_compression_policy(&mut self, policy: CompressionPolicy) {
+        self.compression = policy;
+        self.storage.mark_settings_changed();
+    }
+
+    pub fn compression_policy(&self) -> &CompressionPolicy {
+        &self.compression
+    }
+
+    // Stores the content of `version`, a new version of a file of
+    // `file_type`, and records on it how the content ended up stored.
+    pub(crate) fn store_content(&mut self, version: &mut FileVersion, content: Vec<u8>, file_type: &SupportedFileType, encrypt: bool) -> VfsResult<()> {
+        let codec = self.compression.codec_for(file_type, content.len());
+        if encrypt {
+            return self.store_sealed(version, content, codec);
+        }
+
+        let (compression, stored_size) = self.blobs.insert_compressed(version.checksum.clone(), content, codec)?;
+        version.compression = compression;
+        version.stored_size = stored_size;
+        version.encrypted_blob = None;
+        Ok(())
+    }
+}
+
+// Compresses `content`, falling back to keeping it raw when that wouldn't
+// save anything.
+pub(crate) fn compress_if_smaller(content: Vec<u8>, compression: Compression) -> (Compression, Vec<u8>) {
+    if compression == Compression::None {
+        return (compression, content);
+    }
+    let data = compression.compress(&content);
+    if data.len() < content.len() {
+        (compression, data)
+    } else {
+        (Compression::None, content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{calculate_checksum, ContentState};
+
+    fn csv(rows: usize) -> Vec<u8> {
+        let mut content = b"id,name,type\n".to_vec();
+        for row in 0..rows {
+            content.extend_from_slice(format!("{},pokemon {},grass\n", row, row % 10).as_bytes());
+        }
+        content
+    }
+
+    #[test]
+    fn test_codecs_round_trip() {
+        let content = csv(200);
+        for codec in [Compression::None, Compression::Deflate, Compression::Lz4] {
+            let data = codec.compress(&content);
+            assert_eq!(codec.decompress(&data, content.len()).unwrap(), content);
+            if codec != Compression::None {
+                assert!(data.len() * 4 < content.len());
+                assert!(codec.decompress(&data[..data.len() / 2], content.len()).is_err());
+                assert!(codec.decompress(&data, content.len() + 1).is_err());
+                assert!(codec.decompress(&data, usize::MAX / 4).is_err());
+            }
+        }
+
+        // the most compressible content there is still fits the bounds
+        let zeros = vec![0; 1 << 20];
+        for codec in [Compression::Deflate, Compression::Lz4] {
+            assert_eq!(codec.decompress(&codec.compress(&zeros), zeros.len()).unwrap(), zeros);
+        }
+    }
+
+    #[test]
+    fn test_policy_by_type_and_size() {
+        let policy = CompressionPolicy::new(Compression::Lz4)
+            .file_types(vec![SupportedFileType::CSV])
+            .min_size(1_000);
+        assert_eq!(policy.codec_for(&SupportedFileType::CSV, 10), Compression::Lz4);
+        assert_eq!(policy.codec_for(&SupportedFileType::Text, 10), Compression::None);
+        assert_eq!(policy.codec_for(&SupportedFileType::Text, 1_000), Compression::Lz4);
+        assert_eq!(policy.codec_for(&SupportedFileType::Image, 1_000_000), Compression::None);
+        assert_eq!(CompressionPolicy::default().codec_for(&SupportedFileType::CSV, 1_000_000), Compression::None);
+    }
+
+    #[test]
+    fn test_content_is_compressed_transparently() {
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_compression_policy(CompressionPolicy::new(Compression::Deflate).file_types(vec![SupportedFileType::CSV]));
+        let content = csv(500);
+
+        let id = vfs.create_file_at("/a.csv", SupportedFileType::CSV, content.clone()).unwrap();
+        let stored_bytes = vfs.storage_stats().stored_bytes;
+        let file = vfs.read_file(&id).unwrap();
+        assert_eq!(file.size, content.len());
+        assert_eq!(file.checksum, calculate_checksum(&content));
+        assert_eq!(file.compression, Compression::Deflate);
+        assert!(file.stored_size * 5 < file.size);
+        assert_eq!(stored_bytes, file.stored_size);
+
+        assert_eq!(vfs.get_file_content(&id).unwrap(), content);
+        assert_eq!(vfs.read_range(&id, 13, 19).unwrap(), b"0,pokemon 0,grass\n1"[..].to_vec());
+
+        // the same bytes under a type that isn't compressed still share the blob
+        let copy = vfs.create_file_at("/a.txt", SupportedFileType::Text, content.clone()).unwrap();
+        assert_eq!(vfs.read_file(&copy).unwrap().compression, Compression::Deflate);
+        assert_eq!(vfs.storage_stats().blob_count, 1);
+
+        // content that doesn't shrink is kept raw
+        let id = vfs.create_file_at("/b.csv", SupportedFileType::CSV, vec![7]).unwrap();
+        let file = vfs.read_file(&id).unwrap();
+        assert_eq!((file.compression, file.stored_size), (Compression::None, 1));
+    }
+
+    #[test]
+    fn test_versions_snapshots_and_eviction_keep_compression() {
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_compression_policy(CompressionPolicy::new(Compression::Lz4).min_size(100));
+        let id = vfs.create_file_at("/a.csv", SupportedFileType::CSV, csv(10)).unwrap();
+        vfs.append(&id, b"10,pokemon 0,grass\n").unwrap();
+        vfs.revert_to_version(&id, 1, None).unwrap();
+        let file = vfs.read_file(&id).unwrap().clone();
+        assert_eq!(file.compression, Compression::Lz4);
+        assert_eq!(file.stored_size, file.versions[0].stored_size);
+
+        let mut restored = VirtualFileSystem::new();
+        restored.import_snapshot(&vfs.export_snapshot()).unwrap();
+        assert_eq!(restored.compression_policy(), vfs.compression_policy());
+        assert_eq!(restored.storage_stats().stored_bytes, vfs.storage_stats().stored_bytes);
+        assert_eq!(restored.read_version(&id, 2).unwrap(), [csv(10), b"10,pokemon 0,grass\n".to_vec()].concat());
+
+        // the budget counts compressed bytes and rehydration takes the raw ones
+        restored.set_memory_budget(Some(0)).unwrap();
+        assert_eq!(restored.content_state(&id).unwrap(), ContentState::Evicted);
+        restored.set_memory_budget(Some(file.stored_size)).unwrap();
+        restored.rehydrate(&id, csv(10)).unwrap();
+        assert_eq!(restored.get_file_content(&id).unwrap(), csv(10));
+        assert_eq!(restored.storage_stats().stored_bytes, file.stored_size);
+    }
+}
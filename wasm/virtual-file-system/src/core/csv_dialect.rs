@@ -0,0 +1,450 @@
+// core/csv_dialect.rs
+//
+// How a CSV file is written: delimiter, quote character, how quotes are
+// escaped inside quoted fields, line terminator and text encoding.
+// `CsvDialect::sniff` works it out from the start of the file, so
+// semicolon-separated European exports, TSVs, pipe-delimited files and
+// UTF-16 Excel dumps are read like plain comma-separated UTF-8. Callers
+// that know better can sniff, change a field and pass the dialect on.
+//
+// Content in another encoding is transcoded to UTF-8 as it is read (see
+// `DecodeReader`), so the CSV reader itself only ever sees UTF-8.
+use std::io::{self, Read};
+use serde::{Serialize, Deserialize};
+use csv::{ReaderBuilder, Terminator};
+
+use crate::core::error::CsvError;
+
+/// Bytes of the file looked at by `CsvDialect::sniff`.
+pub const SNIFF_BYTES: usize = 64 * 1024;
+const SNIFF_LINES: usize = 50;
+const DELIMITERS: [char; 5] = [',', ';', '\t', '|', ':'];
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    /// UTF-8 starting with a byte order mark, as Excel writes it.
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    /// The usual encoding of text that isn't valid UTF-8, e.g. older
+    /// Excel exports on Windows.
+    Windows1252,
+}
+
+/// How a quote character is written inside a quoted field.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum EscapeStyle {
+    /// `"say ""hi"""`, the RFC 4180 way.
+    #[default]
+    Doubled,
+    /// `"say \"hi\""`
+    Backslash,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum LineTerminator {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+/// The default is RFC 4180: comma, double quotes, UTF-8.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct CsvDialect {
+    pub delimiter: char,
+    pub quote: char,
+    #[serde(default)]
+    pub escape: EscapeStyle,
+    #[serde(default)]
+    pub line_terminator: LineTerminator,
+    #[serde(default)]
+    pub encoding: TextEncoding,
+    /// Whether the first row is a header. `sniff` leaves it `None` for
+    /// `CSVMetadata::analyze` to work out.
+    #[serde(default)]
+    pub has_header: Option<bool>,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        CsvDialect {
+            delimiter: ',',
+            quote: '"',
+            escape: EscapeStyle::Doubled,
+            line_terminator: LineTerminator::Lf,
+            encoding: TextEncoding::Utf8,
+            has_header: None,
+        }
+    }
+}
+
+impl CsvDialect {
+    /// Works out the dialect from the start of a file; only the first
+    /// `SNIFF_BYTES` are looked at. Anything that can't be told is left
+    /// at its default.
+    pub fn sniff(data: &[u8]) -> Self {
+        let sample = &data[..data.len().min(SNIFF_BYTES)];
+        let encoding = sniff_encoding(sample);
+
+        let mut text = String::new();
+        // decoding from memory only fails on malformed UTF-8, read lossily below
+        if DecodeReader::new(sample, encoding).read_to_string(&mut text).is_err() {
+            let mut bytes = Vec::new();
+            let _ = DecodeReader::new(sample, encoding).read_to_end(&mut bytes);
+            text = String::from_utf8_lossy(&bytes).into_owned();
+        }
+
+        let mut lines: Vec<&str> = text.split(['\n', '\r']).filter(|line| !line.is_empty()).collect();
+        if data.len() > sample.len() {
+            // most likely cut short
+            lines.pop();
+        }
+        lines.truncate(SNIFF_LINES);
+
+        let mut quote = '"';
+        let mut delimiter = sniff_delimiter(&lines, quote);
+        if count_quoted_fields(&lines, delimiter, '\'') > 0 && count_quoted_fields(&lines, delimiter, '"') == 0 {
+            quote = '\'';
+            delimiter = sniff_delimiter(&lines, quote);
+        }
+
+        CsvDialect {
+            delimiter,
+            quote,
+            escape: sniff_escape(&text, quote),
+            line_terminator: sniff_line_terminator(&text),
+            encoding,
+            has_header: None,
+        }
+    }
+
+    /// A reader builder set up for this dialect, for content that is UTF-8
+    /// already; see `reader` for content in any encoding.
+    pub fn reader_builder(&self) -> Result<ReaderBuilder, CsvError> {
+        let mut builder = ReaderBuilder::new();
+        builder
+            .delimiter(ascii(self.delimiter, "delimiter")?)
+            .quote(ascii(self.quote, "quote")?)
+            .double_quote(self.escape == EscapeStyle::Doubled)
+            .escape((self.escape == EscapeStyle::Backslash).then_some(b'\\'))
+            .terminator(match self.line_terminator {
+                LineTerminator::Cr => Terminator::Any(b'\r'),
+                // handles \n, \r\n and \r alike
+                LineTerminator::Lf | LineTerminator::CrLf => Terminator::CRLF,
+            });
+        Ok(builder)
+    }
+
+    /// A CSV reader over content in this dialect's encoding, taking the
+    /// first row as a header unless `has_header` says otherwise.
+    pub fn reader<R: Read>(&self, data: R) -> Result<csv::Reader<DecodeReader<R>>, CsvError> {
+        Ok(self.reader_builder()?
+            .has_headers(self.has_header.unwrap_or(true))
+            .from_reader(DecodeReader::new(data, self.encoding)))
+    }
+}
+
+fn ascii(value: char, field: &str) -> Result<u8, CsvError> {
+    u8::try_from(value).ok()
+        .filter(u8::is_ascii)
+        .ok_or_else(|| CsvError::InvalidDialect { reason: format!("the {} must be an ASCII character, got {:?}", field, value) })
+}
+
+fn sniff_encoding(sample: &[u8]) -> TextEncoding {
+    if sample.starts_with(UTF8_BOM) {
+        return TextEncoding::Utf8Bom;
+    }
+    match sample {
+        [0xFF, 0xFE, ..] => return TextEncoding::Utf16Le,
+        [0xFE, 0xFF, ..] => return TextEncoding::Utf16Be,
+        _ => {}
+    }
+
+    // Mostly-ASCII UTF-16 without a BOM has a zero in every other byte.
+    let pairs = sample.len() / 2;
+    if pairs > 0 {
+        let even_zeros = sample.iter().step_by(2).filter(|&&byte| byte == 0).count();
+        let odd_zeros = sample.iter().skip(1).step_by(2).filter(|&&byte| byte == 0).count();
+        if odd_zeros * 2 > pairs && even_zeros * 10 < pairs {
+            return TextEncoding::Utf16Le;
+        }
+        if even_zeros * 2 > pairs && odd_zeros * 10 < pairs {
+            return TextEncoding::Utf16Be;
+        }
+    }
+
+    match std::str::from_utf8(sample) {
+        Ok(_) => TextEncoding::Utf8,
+        // only cut short in the middle of a character
+        Err(error) if error.error_len().is_none() => TextEncoding::Utf8,
+        Err(_) => TextEncoding::Windows1252,
+    }
+}
+
+// The candidate that splits the lines into the same number of fields most
+// consistently, ties going to the one that makes more fields and then to
+// the more common delimiter.
+fn sniff_delimiter(lines: &[&str], quote: char) -> char {
+    let mut best = (CsvDialect::default().delimiter, 0.0, 0);
+    for candidate in DELIMITERS {
+        let counts: Vec<usize> = lines.iter().map(|line| count_fields(line, candidate, quote)).collect();
+        let Some(mode) = mode(&counts).filter(|&mode| mode >= 2) else { continue };
+        let consistency = counts.iter().filter(|&&count| count == mode).count() as f64 / counts.len() as f64;
+        if consistency > best.1 || (consistency == best.1 && mode > best.2) {
+            best = (candidate, consistency, mode);
+        }
+    }
+    best.0
+}
+
+// Most common value, the largest on ties.
+fn mode(counts: &[usize]) -> Option<usize> {
+    let mut sorted = counts.to_vec();
+    sorted.sort_unstable();
+    sorted.chunk_by(|a, b| a == b)
+        .max_by_key(|run| (run.len(), run[0]))
+        .map(|run| run[0])
+}
+
+fn count_fields(line: &str, delimiter: char, quote: char) -> usize {
+    let mut fields = 1;
+    let mut quoted = false;
+    for c in line.chars() {
+        if c == quote {
+            quoted = !quoted;
+        } else if c == delimiter && !quoted {
+            fields += 1;
+        }
+    }
+    fields
+}
+
+fn count_quoted_fields(lines: &[&str], delimiter: char, quote: char) -> usize {
+    lines.iter()
+        .flat_map(|line| line.split(delimiter))
+        .map(str::trim)
+        .filter(|field| field.len() >= 2 && field.starts_with(quote) && field.ends_with(quote))
+        .count()
+}
+
+// Empty quoted fields count as doubled quotes too, so backslashes have to
+// be the more common.
+fn sniff_escape(text: &str, quote: char) -> EscapeStyle {
+    let chars: Vec<char> = text.chars().collect();
+    let (mut backslashed, mut doubled) = (0, 0);
+    let mut index = 0;
+    while index + 1 < chars.len() {
+        match (chars[index], chars[index + 1]) {
+            ('\\', next) if next == quote => backslashed += 1,
+            (current, next) if current == quote && next == quote => doubled += 1,
+            _ => {
+                index += 1;
+                continue;
+            }
+        }
+        index += 2;
+    }
+    if backslashed > doubled { EscapeStyle::Backslash } else { EscapeStyle::Doubled }
+}
+
+fn sniff_line_terminator(text: &str) -> LineTerminator {
+    match text.find(['\n', '\r']).map(|index| &text[index..]) {
+        Some(rest) if rest.starts_with("\r\n") => LineTerminator::CrLf,
+        Some(rest) if rest.starts_with('\r') => LineTerminator::Cr,
+        _ => LineTerminator::Lf,
+    }
+}
+
+/// Reads text in a `TextEncoding` as UTF-8, dropping a byte order mark.
+/// Invalid UTF-16 becomes U+FFFD; UTF-8 is passed through as it is.
+#[derive(Debug)]
+pub struct DecodeReader<R> {
+    inner: R,
+    encoding: TextEncoding,
+    // read but not decoded yet, e.g. half a UTF-16 code unit
+    raw: Vec<u8>,
+    decoded: Vec<u8>,
+    position: usize,
+    at_start: bool,
+    eof: bool,
+}
+
+impl<R: Read> DecodeReader<R> {
+    pub fn new(inner: R, encoding: TextEncoding) -> Self {
+        DecodeReader {
+            inner,
+            encoding,
+            raw: Vec::new(),
+            decoded: Vec::new(),
+            position: 0,
+            at_start: true,
+            eof: false,
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut chunk = [0; 8 * 1024];
+        let read = loop {
+            match self.inner.read(&mut chunk) {
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result?,
+            }
+        };
+        self.eof = read == 0;
+        self.raw.extend_from_slice(&chunk[..read]);
+        self.decoded.clear();
+        self.position = 0;
+
+        if self.at_start {
+            let bom: &[u8] = match self.encoding {
+                TextEncoding::Utf8 | TextEncoding::Utf8Bom => UTF8_BOM,
+                TextEncoding::Utf16Le => &[0xFF, 0xFE],
+                TextEncoding::Utf16Be => &[0xFE, 0xFF],
+                TextEncoding::Windows1252 => &[],
+            };
+            if self.raw.len() < bom.len() && !self.eof {
+                return Ok(());
+            }
+            if self.raw.starts_with(bom) {
+                self.raw.drain(..bom.len());
+            }
+            self.at_start = false;
+        }
+
+        match self.encoding {
+            TextEncoding::Utf8 | TextEncoding::Utf8Bom => std::mem::swap(&mut self.decoded, &mut self.raw),
+            TextEncoding::Windows1252 => {
+                let text: String = self.raw.drain(..).map(windows_1252).collect();
+                self.decoded = text.into_bytes();
+            }
+            TextEncoding::Utf16Le | TextEncoding::Utf16Be => {
+                let unit = |pair: &[u8]| match self.encoding {
+                    TextEncoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                };
+                let mut units: Vec<u16> = self.raw.chunks_exact(2).map(unit).collect();
+                let mut keep = self.raw.len() % 2;
+                // a surrogate pair split between reads
+                if !self.eof && units.last().is_some_and(|unit| (0xD800..0xDC00).contains(unit)) {
+                    units.pop();
+                    keep += 2;
+                }
+                let text: String = char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect();
+                self.decoded = text.into_bytes();
+                self.raw.drain(..self.raw.len() - keep);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.decoded.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+
+        let len = buf.len().min(self.decoded.len() - self.position);
+        buf[..len].copy_from_slice(&self.decoded[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+// 0x80-0x9F are where Windows-1252 differs from Latin-1; the five unused
+// bytes keep their Latin-1 meaning.
+fn windows_1252(byte: u8) -> char {
+    const HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+        '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+    ];
+    match byte {
+        0x80..=0x9F => HIGH[(byte - 0x80) as usize],
+        _ => byte as char,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hands out one byte per read.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.0.len().min(buf.len()).min(1);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        bytes
+    }
+
+    #[test]
+    fn test_sniff_delimiters() {
+        let european = CsvDialect::sniff(b"name;price;stock\nApfel;1,50;10\nBirne;2,25;7\n");
+        assert_eq!((european.delimiter, european.line_terminator), (';', LineTerminator::Lf));
+
+        let tsv = CsvDialect::sniff(b"a\tb\tc\r\n1\t\"x, y\"\t3\r\n");
+        assert_eq!((tsv.delimiter, tsv.line_terminator), ('\t', LineTerminator::CrLf));
+
+        let piped = CsvDialect::sniff(b"id|note\n1|\"a|b\"\n2|c\n");
+        assert_eq!(piped.delimiter, '|');
+
+        let quoted = CsvDialect::sniff(b"'id','note'\n'1','say \\'hi\\''\n");
+        assert_eq!((quoted.delimiter, quoted.quote, quoted.escape), (',', '\'', EscapeStyle::Backslash));
+
+        assert_eq!(CsvDialect::sniff(b"single column\nvalue\n"), CsvDialect::default());
+        assert_eq!(CsvDialect::sniff(b""), CsvDialect::default());
+    }
+
+    #[test]
+    fn test_sniff_and_decode_encodings() {
+        let text = "name;city\nJosé;Zürich\n😀;Köln\n";
+        let data = utf16le(text);
+        let dialect = CsvDialect::sniff(&data);
+        assert_eq!((dialect.encoding, dialect.delimiter), (TextEncoding::Utf16Le, ';'));
+
+        // one byte at a time, so code units and surrogate pairs are split
+        let mut decoded = String::new();
+        DecodeReader::new(Trickle(&data), dialect.encoding)
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, text);
+
+        let without_bom = &data[2..];
+        assert_eq!(CsvDialect::sniff(without_bom).encoding, TextEncoding::Utf16Le);
+
+        let mut bom = UTF8_BOM.to_vec();
+        bom.extend_from_slice("a,b\n1,2".as_bytes());
+        assert_eq!(CsvDialect::sniff(&bom).encoding, TextEncoding::Utf8Bom);
+
+        let ansi = b"name;price\ncaf\xE9;5 \x80\n";
+        let dialect = CsvDialect::sniff(ansi);
+        assert_eq!(dialect.encoding, TextEncoding::Windows1252);
+        let mut reader = dialect.reader(&ansi[..]).unwrap();
+        let row = reader.records().next().unwrap().unwrap();
+        assert_eq!((&row[0], &row[1]), ("café", "5 €"));
+    }
+
+    #[test]
+    fn test_invalid_dialect() {
+        let dialect = CsvDialect { delimiter: '§', ..CsvDialect::default() };
+        assert_eq!(dialect.reader(&b""[..]).unwrap_err().code(), "CSV_INVALID_DIALECT");
+    }
+}
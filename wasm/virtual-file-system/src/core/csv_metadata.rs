@@ -1,22 +1,64 @@
 // core/csv_metadata.rs
-use std::collections::{HashMap, HashSet};
+//
+// CSV profiling in a single streaming pass. Every row is read, so
+// `row_count`, the inferred types and `nullable` are exact, while memory
+// stays bounded whatever the size of the file: values are counted exactly
+// up to `MAX_TRACKED_VALUES` distinct ones per column, past which
+// `unique_count` is estimated from the smallest value hashes (a KMV
+// sketch), and `sample_rows`/`sample_values` are reservoir samples of the
+// whole file. `accuracy` says whether anything had to be estimated.
+// Numeric stats are exact from running moments, except for the median and
+// quartiles of columns with too many distinct values, which come from a
+// `QUANTILE_SAMPLE` reservoir.
+//
+// The dialect (delimiter, quoting, encoding) is sniffed from the first
+// `SNIFF_BYTES` unless the caller passes one, see `core::csv_dialect`.
+// Whether the first row is a header is decided by comparing it with the
+// rows after it, see `detect_header`; without one, columns are named
+// `column_1`, `column_2`, and so on. Date and time columns, and the
+// format they are written in, are recognised by `core::temporal`.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Read};
 use serde::{Serialize, Deserialize};
-use csv::{ReaderBuilder, StringRecord};
+use csv::StringRecord;
 
-use crate::core::types::{DataType, ColumnMetadata, SqlStatements, NumericStats, StringStats};
+use crate::core::csv_dialect::{CsvDialect, DecodeReader, SNIFF_BYTES};
+use crate::core::error::CsvError;
+use crate::core::types::{Accuracy, DataType, ColumnMetadata, NumericStats, SqlStatements, StringStats};
 use crate::core::sql_conversion::generate_sql_statements;
-use crate::core::statistical_methods::{analyze_distribution, quick_frequency_check};
+use crate::core::statistical_methods::{analyze_frequencies, weighted_quantile, Moments};
+use crate::core::temporal::TemporalCandidates;
 
-const ENUM_RATIO_THRESHOLD: f64 = 0.1;
 const MIN_ROWS_FOR_ENUM: usize = 10;
+const SAMPLE_ROWS: usize = 5;
+const SAMPLE_VALUES: usize = 5;
+// Distinct values counted exactly per column before switching to the estimate.
+const MAX_TRACKED_VALUES: usize = 10_000;
+// Hashes kept for the estimate, its error is around 1 / sqrt(KMV_SIZE).
+const KMV_SIZE: usize = 1024;
+// Numbers sampled per column for the median and quartiles, once there are
+// too many distinct values to take them from the counts.
+const QUANTILE_SAMPLE: usize = 4096;
+// Fixed so the same file always gets the same samples.
+const SAMPLING_SEED: u64 = 0x5EED;
+// Rows the first one is compared against to decide if it is a header.
+const HEADER_SAMPLE_ROWS: usize = 20;
 
 #[derive(Debug)]
 struct ColumnAnalysis {
     could_be_integer: bool,
     could_be_float: bool,
     could_be_boolean: bool,
-    could_be_enum: bool,
-    unique_values: HashSet<String>,
+    temporal: TemporalCandidates,
+    // None once a value isn't a finite number
+    numbers: Option<NumberSummary>,
+    min_length: usize,
+    max_length: usize,
+    total_length: usize,
+    distinct: DistinctCounter,
+    samples: Reservoir<String>,
     total_values: usize,
     non_empty_values: usize,
 }
@@ -27,108 +69,425 @@ impl ColumnAnalysis {
             could_be_integer: true,
             could_be_float: true,
             could_be_boolean: true,
-            could_be_enum: true,
-            unique_values: HashSet::new(),
+            temporal: TemporalCandidates::new(),
+            numbers: Some(NumberSummary::new()),
+            min_length: usize::MAX,
+            max_length: 0,
+            total_length: 0,
+            distinct: DistinctCounter::default(),
+            samples: Reservoir::new(SAMPLE_VALUES),
             total_values: 0,
             non_empty_values: 0,
         }
     }
+
+    fn add(&mut self, value: &str, rng: &mut SplitMix64) {
+        self.total_values += 1;
+        if value.trim().is_empty() {
+            return;
+        }
+        self.non_empty_values += 1;
+
+        let length = value.chars().count();
+        self.min_length = self.min_length.min(length);
+        self.max_length = self.max_length.max(length);
+        self.total_length += length;
+
+        let number = value.trim().parse::<f64>().ok();
+        self.could_be_integer = self.could_be_integer && could_be_integer(value);
+        self.could_be_float = self.could_be_float && number.is_some();
+        match (self.numbers.as_mut(), number) {
+            (Some(numbers), Some(number)) if number.is_finite() => numbers.add(number, rng),
+            _ => self.numbers = None,
+        }
+        self.could_be_boolean = self.could_be_boolean && could_be_boolean(value);
+        self.temporal.add(value);
+
+        // sample distinct values, as far as they can still be told apart
+        if self.distinct.add(value) && !self.samples.items.iter().any(|sample| sample == value) {
+            self.samples.offer(rng, || value.to_string());
+        }
+    }
+}
+
+#[derive(Debug)]
+struct NumberSummary {
+    moments: Moments,
+    min: f64,
+    max: f64,
+    zero_count: usize,
+    negative_count: usize,
+    sample: Reservoir<f64>,
+}
+
+impl NumberSummary {
+    fn new() -> Self {
+        NumberSummary {
+            moments: Moments::default(),
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            zero_count: 0,
+            negative_count: 0,
+            sample: Reservoir::new(QUANTILE_SAMPLE),
+        }
+    }
+
+    fn add(&mut self, number: f64, rng: &mut SplitMix64) {
+        self.moments.add(number);
+        self.min = self.min.min(number);
+        self.max = self.max.max(number);
+        if number == 0.0 {
+            self.zero_count += 1;
+        } else if number < 0.0 {
+            self.negative_count += 1;
+        }
+        self.sample.offer(rng, || number);
+    }
+}
+
+/// Counts distinct values exactly while there are few enough of them,
+/// then estimates from the `KMV_SIZE` smallest value hashes.
+#[derive(Debug)]
+struct DistinctCounter {
+    // value -> occurrences, None once past MAX_TRACKED_VALUES
+    counts: Option<HashMap<String, usize>>,
+    smallest: BTreeSet<u64>,
+}
+
+impl Default for DistinctCounter {
+    fn default() -> Self {
+        DistinctCounter { counts: Some(HashMap::new()), smallest: BTreeSet::new() }
+    }
+}
+
+impl DistinctCounter {
+    // Returns false if the value has certainly been seen before.
+    fn add(&mut self, value: &str) -> bool {
+        let Some(counts) = self.counts.as_mut() else {
+            self.add_hash(hash_value(value));
+            return true;
+        };
+
+        let new = match counts.get_mut(value) {
+            Some(count) => {
+                *count += 1;
+                false
+            }
+            None => {
+                counts.insert(value.to_string(), 1);
+                true
+            }
+        };
+        if counts.len() > MAX_TRACKED_VALUES {
+            for value in self.counts.take().unwrap_or_default().keys() {
+                self.add_hash(hash_value(value));
+            }
+        }
+        new
+    }
+
+    fn add_hash(&mut self, hash: u64) {
+        if self.smallest.len() < KMV_SIZE || self.smallest.last().is_some_and(|&largest| hash < largest) {
+            self.smallest.insert(hash);
+            if self.smallest.len() > KMV_SIZE {
+                self.smallest.pop_last();
+            }
+        }
+    }
+
+    fn count(&self) -> (usize, Accuracy) {
+        if let Some(counts) = &self.counts {
+            return (counts.len(), Accuracy::Exact);
+        }
+        match self.smallest.last() {
+            // with k hashes spread uniformly, the k-th smallest sits at about k / n of the range
+            Some(&largest) if self.smallest.len() == KMV_SIZE => {
+                let estimate = (KMV_SIZE - 1) as f64 * (u64::MAX as f64 / largest.max(1) as f64);
+                (estimate.round() as usize, Accuracy::Estimated)
+            }
+            _ => (self.smallest.len(), Accuracy::Estimated),
+        }
+    }
+}
+
+fn hash_value(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A uniform sample of at most `capacity` of the items offered.
+#[derive(Debug)]
+struct Reservoir<T> {
+    items: Vec<T>,
+    seen: usize,
+    capacity: usize,
+}
+
+impl<T> Reservoir<T> {
+    fn new(capacity: usize) -> Self {
+        Reservoir { items: Vec::with_capacity(capacity), seen: 0, capacity }
+    }
+
+    // Algorithm R: the n-th item replaces a random one with probability
+    // capacity / n. `item` is only built when it is kept.
+    fn offer(&mut self, rng: &mut SplitMix64, item: impl FnOnce() -> T) {
+        self.seen += 1;
+        if self.items.len() < self.capacity {
+            self.items.push(item());
+            return;
+        }
+        let slot = rng.below(self.seen);
+        if slot < self.capacity {
+            self.items[slot] = item();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CSVMetadata {
+    /// Data rows, not counting the header.
     pub row_count: usize,
     pub column_count: usize,
+    /// In file order; `column_1`, `column_2`… when there is no header.
+    #[serde(default)]
+    pub column_names: Vec<String>,
+    /// Whether the first row was taken as a header, and how sure the
+    /// detection was of that, from 0.5 (a guess) to 1.0 (every telling
+    /// column agreed, or the dialect said so).
+    #[serde(default = "default_has_header")]
+    pub has_header: bool,
+    #[serde(default)]
+    pub header_confidence: f64,
     pub columns: HashMap<String, ColumnMetadata>,
+    /// A sample of the whole file, in file order.
     pub sample_rows: Vec<Vec<String>>,
     pub sql_statements: Option<SqlStatements>,
+    /// `Estimated` if any column's is; `row_count` is always exact.
+    #[serde(default)]
+    pub accuracy: Accuracy,
+    /// The dialect the file was read with.
+    #[serde(default)]
+    pub dialect: CsvDialect,
 }
 
 impl CSVMetadata {
-    pub fn analyze(csv_data: &[u8]) -> Result<Self, String> {
-        let mut reader = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(csv_data);
+    pub fn analyze(csv_data: &[u8]) -> Result<Self, CsvError> {
+        Self::analyze_with_dialect(csv_data, CsvDialect::sniff(csv_data))
+    }
+
+    pub fn analyze_with_dialect(csv_data: &[u8], dialect: CsvDialect) -> Result<Self, CsvError> {
+        Self::analyze_reader_with_dialect(csv_data, dialect)
+    }
+
+    /// Profiles CSV as it is read, see the module docs. Use this rather
+    /// than `analyze` to profile a file that isn't in memory.
+    pub fn analyze_reader(mut csv_data: impl Read) -> Result<Self, CsvError> {
+        let mut head = Vec::new();
+        (&mut csv_data).take(SNIFF_BYTES as u64 + 1).read_to_end(&mut head)
+            .map_err(csv::Error::from)?;
+        let dialect = CsvDialect::sniff(&head);
+        Self::analyze_reader_with_dialect(Cursor::new(head).chain(csv_data), dialect)
+    }
+
+    pub fn analyze_reader_with_dialect(csv_data: impl Read, mut dialect: CsvDialect) -> Result<Self, CsvError> {
+        let mut reader = dialect.reader_builder()?
+            .has_headers(false)
+            .from_reader(DecodeReader::new(csv_data, dialect.encoding));
         
-        let headers = reader.headers()
-            .map_err(|e| format!("Failed to read headers: {}", e))?
-            .clone();
+        let mut first_row = StringRecord::new();
+        if !reader.read_record(&mut first_row)? {
+            return Err(CsvError::Empty);
+        }
+        let mut head_rows = Vec::new();
+        let mut record = StringRecord::new();
+        while head_rows.len() < HEADER_SAMPLE_ROWS && reader.read_record(&mut record)? {
+            head_rows.push(record.clone());
+        }
         
-        let mut column_values: HashMap<String, Vec<String>> = headers.iter()
-            .map(|h| (h.to_string(), Vec::new()))
-            .collect();
+        let (has_header, header_confidence) = match dialect.has_header {
+            Some(has_header) => (has_header, 1.0),
+            None => detect_header(&first_row, &head_rows),
+        };
+        dialect.has_header = Some(has_header);
+        let headers: Vec<String> = if has_header {
+            first_row.iter().map(String::from).collect()
+        } else {
+            (1..=first_row.len()).map(|index| format!("column_{}", index)).collect()
+        };
         
+        let mut analyses: Vec<ColumnAnalysis> = headers.iter()
+            .map(|_| ColumnAnalysis::new())
+            .collect();
+        let mut rng = SplitMix64(SAMPLING_SEED);
+        let mut sample_rows = Reservoir::new(SAMPLE_ROWS);
         let mut row_count = 0;
-        let mut sample_rows = Vec::with_capacity(5);
         
-        let mut record = StringRecord::new();
-        while row_count < 1000 && reader.read_record(&mut record)
-            .map_err(|e| e.to_string())? 
-        {
+        let mut add_row = |record: &StringRecord| {
+            sample_rows.offer(&mut rng, || (row_count, record.iter().map(String::from).collect::<Vec<String>>()));
             row_count += 1;
             
-            if sample_rows.len() < 5 {
-                sample_rows.push(record.iter().map(String::from).collect());
-            }
-            
-            for (i, value) in record.iter().enumerate() {
-                let col_name = headers.get(i).unwrap().to_string();
-                column_values.get_mut(&col_name).unwrap().push(value.to_string());
+            for (analysis, value) in analyses.iter_mut().zip(record.iter()) {
+                analysis.add(value, &mut rng);
             }
+        };
+        if !has_header {
+            add_row(&first_row);
+        }
+        head_rows.iter().for_each(&mut add_row);
+        while reader.read_record(&mut record)? {
+            add_row(&record);
         }
         
         let mut columns = HashMap::new();
-        for (col_name, values) in column_values {
-            let analysis = analyze_column(values.iter());
-            let data_type = determine_best_type(&analysis);
-            let unique_count = analysis.unique_values.len();
-            let sample_values: Vec<String> = analysis.unique_values
-                .into_iter()
-                .take(5)
-                .collect();
+        for (col_name, analysis) in headers.iter().zip(analyses) {
+            let (data_type, date_stats) = match analysis.temporal.resolve(col_name) {
+                Some((data_type, date_stats)) if !is_boolean(&analysis) => (data_type, Some(date_stats)),
+                _ => (determine_best_type(&analysis), None),
+            };
+            let (unique_count, accuracy) = analysis.distinct.count();
+            let null_count = analysis.total_values - analysis.non_empty_values;
+            let numeric_stats = numeric_stats(&analysis, null_count, unique_count);
+            let string_stats = (analysis.non_empty_values > 0).then(|| StringStats {
+                min_length: analysis.min_length,
+                max_length: analysis.max_length,
+                mean_length: analysis.total_length as f64 / analysis.non_empty_values as f64,
+                null_count,
+                distinct_count: unique_count,
+            });
                     
-            columns.insert(col_name.clone(), ColumnMetadata {
-                name: col_name,
+            columns.insert(col_name.to_string(), ColumnMetadata {
+                name: col_name.to_string(),
                 data_type,
-                sample_values,
+                sample_values: analysis.samples.items,
                 nullable: analysis.non_empty_values < analysis.total_values,
                 unique_count,
-                numeric_stats: None,
-                string_stats: None,
+                accuracy,
+                numeric_stats,
+                string_stats,
+                date_stats,
             });
         }
         
+        let accuracy = if columns.values().any(|column| column.accuracy == Accuracy::Estimated) {
+            Accuracy::Estimated
+        } else {
+            Accuracy::Exact
+        };
         let sql_statements = Some(generate_sql_statements("my_table", &columns));
         
+        let mut sample_rows = sample_rows.items;
+        sample_rows.sort_by_key(|(index, _)| *index);
+        
         Ok(CSVMetadata {
             row_count,
             column_count: headers.len(),
+            column_names: headers,
+            has_header,
+            header_confidence,
             columns,
-            sample_rows,
+            sample_rows: sample_rows.into_iter().map(|(_, row)| row).collect(),
             sql_statements,
+            accuracy,
+            dialect,
         })
     }
 }
 
-fn analyze_column<'a>(values: impl Iterator<Item = &'a String>) -> ColumnAnalysis {
-    let mut analysis = ColumnAnalysis::new();
-    
-    for value in values {
-        analysis.total_values += 1;
-        
-        if !value.trim().is_empty() {
-            analysis.non_empty_values += 1;
-            analysis.unique_values.insert(value.clone());
-            
-            analysis.could_be_integer &= could_be_integer(value);
-            analysis.could_be_float &= could_be_float(value);
-            analysis.could_be_boolean &= could_be_boolean(value);
+fn default_has_header() -> bool {
+    true
+}
+
+// Compares the first row with the rows after it, column by column. A
+// column whose other values are all numbers, all booleans or all of one
+// length votes for a header if its first value isn't, and against one if
+// it is; so does a first value that shows up again further down. Without
+// any vote the first row is taken as a header, as most files have one.
+fn detect_header(first_row: &StringRecord, rows: &[StringRecord]) -> (bool, f64) {
+    let (mut header_votes, mut data_votes) = (0, 0);
+    for (index, candidate) in first_row.iter().map(str::trim).enumerate() {
+        let values: Vec<&str> = rows.iter()
+            .filter_map(|row| row.get(index))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .collect();
+        if values.is_empty() || candidate.is_empty() {
+            continue;
+        }
+
+        let length = values[0].chars().count();
+        let looks_like_data = if values.iter().all(|value| could_be_float(value)) {
+            Some(could_be_float(candidate))
+        } else if values.iter().all(|value| could_be_boolean(value)) {
+            Some(could_be_boolean(candidate))
+        } else if values.len() >= 2 && values.iter().all(|value| value.chars().count() == length) {
+            Some(candidate.chars().count() == length)
+        } else if values.contains(&candidate) {
+            Some(true)
+        } else {
+            None
+        };
+        match looks_like_data {
+            Some(true) => data_votes += 1,
+            Some(false) => header_votes += 1,
+            None => {}
         }
     }
-    
-    analysis.could_be_enum = could_be_enum(&analysis);
-    
-    analysis
+
+    if header_votes + data_votes == 0 {
+        return (true, 0.5);
+    }
+    let confidence = header_votes.max(data_votes) as f64 / (header_votes + data_votes) as f64;
+    (header_votes >= data_votes, confidence)
+}
+
+fn numeric_stats(analysis: &ColumnAnalysis, null_count: usize, distinct_count: usize) -> Option<NumericStats> {
+    let numbers = analysis.numbers.as_ref().filter(|numbers| numbers.moments.count > 0)?;
+
+    let (mut counted, quantile_accuracy) = match &analysis.distinct.counts {
+        Some(counts) => (
+            counts.iter().filter_map(|(value, &count)| Some((value.trim().parse::<f64>().ok()?, count))).collect(),
+            Accuracy::Exact,
+        ),
+        None => (numbers.sample.items.iter().map(|&number| (number, 1)).collect::<Vec<(f64, usize)>>(), Accuracy::Estimated),
+    };
+    counted.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let quantile = |q| weighted_quantile(&counted, q);
+
+    Some(NumericStats {
+        min: numbers.min,
+        max: numbers.max,
+        mean: numbers.moments.mean,
+        median: quantile(0.5)?,
+        q1: quantile(0.25)?,
+        q3: quantile(0.75)?,
+        variance: numbers.moments.variance(),
+        std_dev: numbers.moments.std_dev(),
+        skewness: numbers.moments.skewness(),
+        kurtosis: numbers.moments.kurtosis(),
+        null_count,
+        zero_count: numbers.zero_count,
+        negative_count: numbers.negative_count,
+        distinct_count,
+        quantile_accuracy,
+    })
 }
 
 fn could_be_integer(value: &str) -> bool {
@@ -140,39 +499,46 @@ fn could_be_float(value: &str) -> bool {
 }
 
 fn could_be_boolean(value: &str) -> bool {
-    match value.trim().to_lowercase().as_str() {
-        "true" | "false" | "1" | "0" | "yes" | "no" | "t" | "f" | "y" | "n" => true,
-        _ => false
-    }
+    matches!(
+        value.trim().to_lowercase().as_str(),
+        "true" | "false" | "1" | "0" | "yes" | "no" | "t" | "f" | "y" | "n"
+    )
 }
 
+// The distribution checks need the count of every value, not just the
+// unique set, otherwise each value looks like it appears exactly once. A
+// column with too many values to count is not categorical anyway.
 fn could_be_enum(analysis: &ColumnAnalysis) -> bool {
     if analysis.total_values < MIN_ROWS_FOR_ENUM {
         return false;
     }
     
-    let values: Vec<String> = analysis.unique_values.iter().cloned().collect();
-    let quick_check = quick_frequency_check(&values);
-    
-    if !quick_check.should_analyze {
-        return false;
+    match &analysis.distinct.counts {
+        Some(counts) => analyze_frequencies(counts.clone(), analysis.non_empty_values).is_categorical,
+        None => false,
     }
-    
-    analyze_distribution(&values).is_categorical
 }
 
+fn is_boolean(analysis: &ColumnAnalysis) -> bool {
+    analysis.could_be_boolean && analysis.distinct.count().0 <= 2
+}
+
+// Dates and times are settled before this, see `core::temporal`. Numbers
+// stay numbers however few distinct values they take: typed `Enum`, a
+// column of small integer codes would become a string ENUM in the
+// generated SQL, at odds with the numeric stats reported for it.
 fn determine_best_type(analysis: &ColumnAnalysis) -> DataType {
-    if analysis.unique_values.is_empty() {
+    if analysis.non_empty_values == 0 {
         return DataType::Text;
     }
     
-    if analysis.could_be_boolean && analysis.unique_values.len() <= 2 {
+    if is_boolean(analysis) {
         DataType::Boolean
     } else if analysis.could_be_integer {
         DataType::Integer
     } else if analysis.could_be_float {
         DataType::Float
-    } else if analysis.could_be_enum {
+    } else if could_be_enum(analysis) {
         DataType::Enum
     } else {
         DataType::Text
@@ -183,6 +549,7 @@ fn determine_best_type(analysis: &ColumnAnalysis) -> DataType {
 mod tests {
 
     use super::*;
+    use crate::core::csv_dialect::TextEncoding;
 
     const SAMPLE_CSV: &[u8] = b"id,name,price,quantity\n1,Item 1,10.50,100\n2,Item 2,15.75,200\n3,Item 3,20.00,300";
     const POKEMON_CSV: &[u8] = include_bytes!("../../../../datasets/pokemon.csv");
@@ -201,6 +568,15 @@ mod tests {
         assert!(matches!(columns.get("quantity").unwrap().data_type, DataType::Integer));
     }
 
+    #[test]
+    fn test_errors_are_typed() {
+        assert!(matches!(CSVMetadata::analyze(b""), Err(CsvError::Empty)));
+        assert!(matches!(
+            CSVMetadata::analyze(b"a,b\n1,2\n3,4,5"),
+            Err(CsvError::ParseError { line: Some(3), .. })
+        ));
+    }
+
     #[test]
     fn test_pokemon_csv() {
         let metadata = CSVMetadata::analyze(POKEMON_CSV).unwrap();
@@ -226,11 +602,12 @@ mod tests {
             );
         }
         
-        // Generation should be an enum
+        // Generation is a small integer code, typed as a number like every
+        // numeric column so that it matches its numeric stats
         let gen_col = columns.get("Generation").unwrap();
         assert!(
-            matches!(gen_col.data_type, DataType::Enum),
-            "Expected Generation to be Enum, got {:?}", gen_col.data_type
+            matches!(gen_col.data_type, DataType::Integer),
+            "Expected Generation to be Integer, got {:?}", gen_col.data_type
         );
         assert!(gen_col.unique_count <= 8);
         
@@ -242,4 +619,148 @@ mod tests {
         );
         assert_eq!(legendary_col.unique_count, 2);
     }
-}
+
+    #[test]
+    fn test_whole_file_is_streamed() {
+        let mut csv = String::from("id,score,kind\n");
+        for row in 0..20_000 {
+            // one float far past the first thousand rows
+            let score = if row == 15_000 { "2.5".to_string() } else { (row % 100).to_string() };
+            csv.push_str(&format!("{},{},{}\n", row, score, ["a", "b", "c"][row % 3]));
+        }
+
+        let metadata = CSVMetadata::analyze_reader(csv.as_bytes()).unwrap();
+        assert_eq!(metadata.row_count, 20_000);
+        assert_eq!(metadata.accuracy, Accuracy::Estimated);
+        assert!(matches!(metadata.columns["score"].data_type, DataType::Float));
+
+        let id = &metadata.columns["id"];
+        assert_eq!(id.accuracy, Accuracy::Estimated);
+        assert!(id.unique_count.abs_diff(20_000) < 2_000, "estimated {} distinct ids", id.unique_count);
+        assert_eq!(id.sample_values.len(), 5);
+
+        let kind = &metadata.columns["kind"];
+        assert_eq!((kind.unique_count, kind.accuracy), (3, Accuracy::Exact));
+        assert!(matches!(kind.data_type, DataType::Enum));
+
+        // rows are sampled from the whole file and kept in file order
+        let ids: Vec<usize> = metadata.sample_rows.iter().map(|row| row[0].parse().unwrap()).collect();
+        assert_eq!(ids.len(), 5);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ids.iter().any(|&id| id >= 1_000));
+
+        let small = CSVMetadata::analyze(SAMPLE_CSV).unwrap();
+        assert_eq!(small.accuracy, Accuracy::Exact);
+        assert_eq!(small.sample_rows.len(), 3);
+        assert_eq!(small.columns["id"].unique_count, 3);
+    }
+
+    #[test]
+    fn test_dialects() {
+        let european = b"name;price;stock\nApfel;1,50;10\nBirne;2,25;7\n";
+        let metadata = CSVMetadata::analyze(european).unwrap();
+        assert_eq!(metadata.dialect.delimiter, ';');
+        assert_eq!(metadata.column_count, 3);
+        assert!(matches!(metadata.columns["stock"].data_type, DataType::Integer));
+
+        // an override wins over what is sniffed
+        let piped = b"a|b\n1|2\n";
+        assert_eq!(CSVMetadata::analyze(piped).unwrap().column_count, 2);
+        let metadata = CSVMetadata::analyze_with_dialect(piped, CsvDialect::default()).unwrap();
+        assert_eq!(metadata.column_count, 1);
+
+        let mut excel = vec![0xFF, 0xFE];
+        excel.extend("id\tcity\r\n1\tZürich\r\n2\tKöln\r\n".encode_utf16().flat_map(u16::to_le_bytes));
+        let metadata = CSVMetadata::analyze_reader(&excel[..]).unwrap();
+        assert_eq!(metadata.dialect.encoding, TextEncoding::Utf16Le);
+        assert_eq!(metadata.row_count, 2);
+        assert_eq!(metadata.sample_rows[0], vec!["1".to_string(), "Zürich".to_string()]);
+    }
+
+    #[test]
+    fn test_header_detection() {
+        let with_header = CSVMetadata::analyze(POKEMON_CSV).unwrap();
+        assert!(with_header.has_header);
+        // "Type 2" is as long as the "Poison"s and "Flying"s below it
+        assert!(with_header.header_confidence > 0.9);
+        assert_eq!(with_header.column_names[..3], ["#", "Name", "Type 1"]);
+
+        let headerless = b"1,Bulbasaur,Grass,True\n2,Ivysaur,Grass,False\n3,Venusaur,Grass,False\n4,Charmander,Fire,False\n";
+        let metadata = CSVMetadata::analyze(headerless).unwrap();
+        assert!(!metadata.has_header);
+        assert!(metadata.header_confidence > 0.9);
+        assert_eq!(metadata.row_count, 4);
+        assert_eq!(metadata.column_names, ["column_1", "column_2", "column_3", "column_4"]);
+        assert_eq!(metadata.columns["column_1"].unique_count, 4);
+        assert!(matches!(metadata.columns["column_4"].data_type, DataType::Boolean));
+        assert_eq!(metadata.dialect.has_header, Some(false));
+
+        // nothing telling either way: the usual header is assumed
+        let metadata = CSVMetadata::analyze(b"name,city\nJos\xC3\xA9,Z\xC3\xBCrich\nAnn,Oslo\n").unwrap();
+        assert_eq!((metadata.has_header, metadata.header_confidence), (true, 0.5));
+
+        // and the dialect can say so
+        let dialect = CsvDialect { has_header: Some(false), ..CsvDialect::default() };
+        let metadata = CSVMetadata::analyze_with_dialect(b"name,city\nAnn,Oslo\n", dialect).unwrap();
+        assert_eq!((metadata.row_count, metadata.header_confidence), (2, 1.0));
+    }
+
+    #[test]
+    fn test_date_columns() {
+        let orders = b"id,ordered,shipped_at,slot,created_at\n\
+            1700000000,03/04/2024,2024-04-05T10:00:00Z,09:30,1700000000\n\
+            1700000001,13/04/2024,2024-04-14T08:30:00Z,14:00,1700086400\n";
+        let metadata = CSVMetadata::analyze(orders).unwrap();
+        let columns = &metadata.columns;
+        assert!(matches!(columns["id"].data_type, DataType::Integer));
+        assert!(columns["id"].date_stats.is_none());
+
+        let ordered = &columns["ordered"];
+        assert!(matches!(ordered.data_type, DataType::Date));
+        let stats = ordered.date_stats.as_ref().unwrap();
+        assert_eq!((stats.format.as_str(), stats.min.as_str(), stats.max.as_str()), ("%d/%m/%Y", "2024-04-03", "2024-04-13"));
+        assert!(matches!(columns["shipped_at"].data_type, DataType::DateTimeTz));
+        assert!(matches!(columns["slot"].data_type, DataType::Time));
+        assert!(matches!(columns["created_at"].data_type, DataType::DateTimeTz));
+        assert_eq!(columns["created_at"].date_stats.as_ref().unwrap().format, "%s");
+
+        let create_table = &metadata.sql_statements.unwrap().create_table;
+        assert!(create_table.contains("ordered DATE"), "{}", create_table);
+        assert!(create_table.contains("shipped_at TIMESTAMPTZ"), "{}", create_table);
+    }
+
+    #[test]
+    fn test_column_stats() {
+        let metadata = CSVMetadata::analyze(POKEMON_CSV).unwrap();
+        let hp = metadata.columns["HP"].numeric_stats.as_ref().unwrap();
+        assert_eq!((hp.min, hp.max, hp.median, hp.q1, hp.q3), (1.0, 255.0, 65.0, 50.0, 80.0));
+        assert!((hp.mean - 69.25875).abs() < 1e-9);
+        assert!((hp.variance - 652.0193).abs() < 1e-3);
+        assert!((hp.std_dev - hp.variance.sqrt()).abs() < 1e-12);
+        assert!((hp.skewness - 1.5653).abs() < 1e-3);
+        assert!((hp.kurtosis - 7.1795).abs() < 1e-3);
+        assert_eq!((hp.null_count, hp.zero_count, hp.negative_count), (0, 0, 0));
+        assert_eq!(hp.quantile_accuracy, Accuracy::Exact);
+
+        let name = metadata.columns["Name"].string_stats.as_ref().unwrap();
+        assert_eq!((name.min_length, name.max_length, name.null_count), (3, 25, 0));
+        assert!(metadata.columns["Name"].numeric_stats.is_none());
+
+        let metadata = CSVMetadata::analyze(b"delta,label\n-1.5,a\n0,\n2.5,ccc\n,dd\n").unwrap();
+        let delta = metadata.columns["delta"].numeric_stats.as_ref().unwrap();
+        assert!(matches!(metadata.columns["delta"].data_type, DataType::Float));
+        assert_eq!((delta.min, delta.max, delta.median), (-1.5, 2.5, 0.0));
+        assert!((delta.mean - 1.0 / 3.0).abs() < 1e-12);
+        assert_eq!((delta.null_count, delta.zero_count, delta.negative_count), (1, 1, 1));
+        let label = metadata.columns["label"].string_stats.as_ref().unwrap();
+        assert_eq!((label.min_length, label.max_length, label.mean_length, label.null_count), (1, 3, 2.0, 1));
+
+        // too many values to count: the quartiles are sampled, the rest is exact
+        let csv: String = std::iter::once("n".to_string()).chain((0..20_000).map(|n| n.to_string())).collect::<Vec<_>>().join("\n");
+        let n = CSVMetadata::analyze(csv.as_bytes()).unwrap().columns.remove("n").unwrap().numeric_stats.unwrap();
+        assert_eq!((n.min, n.max, n.mean), (0.0, 19_999.0, 9_999.5));
+        assert_eq!(n.quantile_accuracy, Accuracy::Estimated);
+        assert!((n.median - 9_999.5).abs() < 1_000.0, "sampled median {}", n.median);
+        assert!(n.q1 < n.median && n.median < n.q3);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,432 @@
+// core/detection.rs
+//
+// File type detection from content. Binary formats are recognised by their
+// magic bytes, text is told apart from binary data by looking at the first
+// few KB, and delimited text gets a simple CSV/TSV consistency check. The
+// file name extension is only used when the content gives no answer.
+use std::io::Cursor;
+use serde::{Serialize, Deserialize};
+use zip::ZipArchive;
+
+use crate::core::file_system::{SupportedFileType, VirtualFileSystem};
+
+// How much of the content is inspected by the text heuristics.
+const SNIFF_LEN: usize = 8 * 1024;
+const CSV_SNIFF_LINES: usize = 20;
+const CSV_DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum DetectionSource {
+    /// A magic number or container signature matched.
+    Signature,
+    /// The content looked like text or delimited text.
+    ContentHeuristic,
+    /// Nothing in the content was conclusive, the name extension decided.
+    Extension,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileTypeDetection {
+    pub file_type: SupportedFileType,
+    pub mime_type: String,
+    /// Only set for GIFs with more than one frame.
+    pub animated: bool,
+    pub source: DetectionSource,
+}
+
+/// How `VirtualFileSystem` treats the type declared by callers creating files.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum TypeDetectionMode {
+    /// Use the declared type as is, no sniffing.
+    Trust,
+    /// Keep the declared type but record a `TypeMismatch` on the file when
+    /// the content clearly says otherwise.
+    #[default]
+    Warn,
+    /// Replace the declared type with the detected one whenever the content
+    /// is conclusive.
+    Auto,
+}
+
+/// Recorded on a file whose content does not match its declared type.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TypeMismatch {
+    pub declared: SupportedFileType,
+    pub detected: SupportedFileType,
+}
+
+pub fn detect_file_type(content: &[u8], name: &str) -> FileTypeDetection {
+    if let Some(detection) = detect_signature(content) {
+        return detection;
+    }
+
+    let extension_type = SupportedFileType::from_extension(name);
+    let head = &content[..content.len().min(SNIFF_LEN)];
+
+    if !content.is_empty() && looks_like_text(head, content.len() > SNIFF_LEN) {
+        if extension_type == SupportedFileType::CSV || looks_like_delimited(head) {
+            let mime_type = if delimiter_is_consistent(head, b'\t') {
+                "text/tab-separated-values"
+            } else {
+                "text/csv"
+            };
+            return detected(SupportedFileType::CSV, mime_type, DetectionSource::ContentHeuristic);
+        }
+        if matches!(extension_type, SupportedFileType::Text | SupportedFileType::Other(_)) {
+            return detected(SupportedFileType::Text, "text/plain", DetectionSource::ContentHeuristic);
+        }
+    }
+
+    let mime_type = mime_for(&extension_type);
+    detected(extension_type, mime_type, DetectionSource::Extension)
+}
+
+/// Compares a declared type against what the content says. Only conclusive
+/// detections (signatures, or text content declared as a binary format)
+/// count as a mismatch.
+pub fn find_mismatch(declared: &SupportedFileType, detection: &FileTypeDetection) -> Option<TypeMismatch> {
+    if *declared == detection.file_type {
+        return None;
+    }
+
+    let conclusive = match detection.source {
+        DetectionSource::Signature => true,
+        DetectionSource::ContentHeuristic => declared.is_binary(),
+        DetectionSource::Extension => false,
+    };
+
+    conclusive.then(|| TypeMismatch {
+        declared: declared.clone(),
+        detected: detection.file_type.clone(),
+    })
+}
+
+impl VirtualFileSystem {
+    pub fn set_type_detection(&mut self, mode: TypeDetectionMode) {
+        self.type_detection = mode;
+    }
+
+    pub fn type_detection(&self) -> TypeDetectionMode {
+        self.type_detection
+    }
+}
+
+fn detected(file_type: SupportedFileType, mime_type: &str, source: DetectionSource) -> FileTypeDetection {
+    FileTypeDetection {
+        file_type,
+        mime_type: mime_type.to_string(),
+        animated: false,
+        source,
+    }
+}
+
+fn detect_signature(content: &[u8]) -> Option<FileTypeDetection> {
+    let signature = |file_type, mime_type| Some(detected(file_type, mime_type, DetectionSource::Signature));
+
+    if content.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return signature(SupportedFileType::Image, "image/png");
+    }
+    if content.starts_with(&[0xff, 0xd8, 0xff]) {
+        return signature(SupportedFileType::Image, "image/jpeg");
+    }
+    if content.starts_with(b"GIF87a") || content.starts_with(b"GIF89a") {
+        let mut detection = detected(SupportedFileType::Gif, "image/gif", DetectionSource::Signature);
+        detection.animated = count_gif_frames(content) > 1;
+        return Some(detection);
+    }
+    if content.len() >= 12 && &content[..4] == b"RIFF" && &content[8..12] == b"WEBP" {
+        return signature(SupportedFileType::Image, "image/webp");
+    }
+    if content.starts_with(b"%PDF-") {
+        return signature(SupportedFileType::Pdf, "application/pdf");
+    }
+    if content.len() >= 12 && &content[4..8] == b"ftyp" {
+        let mime_type = if &content[8..12] == b"qt  " { "video/quicktime" } else { "video/mp4" };
+        return signature(SupportedFileType::Video, mime_type);
+    }
+    if starts_with_quicktime_atoms(content) {
+        return signature(SupportedFileType::Video, "video/quicktime");
+    }
+    if content.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]) {
+        return signature(SupportedFileType::Video, "video/webm");
+    }
+    if content.starts_with(b"PK\x03\x04") || content.starts_with(b"PK\x05\x06") {
+        return if is_xlsx(content) {
+            signature(SupportedFileType::Spreadsheet, "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")
+        } else {
+            signature(SupportedFileType::Archive, "application/zip")
+        };
+    }
+
+    None
+}
+
+// Older QuickTime files start straight with a movie atom instead of
+// `ftyp`. The atom names are plain words ("The free market…" has `free` at
+// the same offset), so the atom sizes have to add up too: the first one
+// must fit in the content and be followed by another.
+fn starts_with_quicktime_atoms(content: &[u8]) -> bool {
+    const ATOMS: [&[u8]; 6] = [b"moov", b"mdat", b"wide", b"free", b"skip", b"pnot"];
+    let atom = |offset: usize| -> Option<(usize, &[u8])> {
+        let header = content.get(offset..offset.checked_add(8)?)?;
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        Some((size, &header[4..]))
+    };
+
+    let Some((size, kind)) = atom(0) else { return false };
+    if !ATOMS[..4].contains(&kind) || size < 8 || size > content.len() {
+        return false;
+    }
+    match atom(size) {
+        // 0 runs to the end of the file, 1 has a 64-bit size after the name
+        Some((next_size, next_kind)) => {
+            ATOMS.contains(&next_kind) && (next_size <= 1 || (next_size >= 8 && size + next_size <= content.len()))
+        }
+        None => false,
+    }
+}
+
+fn is_xlsx(content: &[u8]) -> bool {
+    match ZipArchive::new(Cursor::new(content)) {
+        Ok(archive) => archive.file_names().any(|name| name == "xl/workbook.xml"),
+        // Truncated archives still carry entry names in the local headers.
+        Err(_) => content.windows(15).any(|window| window == b"xl/workbook.xml"),
+    }
+}
+
+/// Walks the GIF block structure and counts image descriptors. Stops
+/// quietly at the first malformed block.
+fn count_gif_frames(content: &[u8]) -> usize {
+    const HEADER_LEN: usize = 6 + 7;
+    if content.len() < HEADER_LEN {
+        return 0;
+    }
+
+    let mut offset = HEADER_LEN;
+    let flags = content[10];
+    if flags & 0x80 != 0 {
+        offset += 3 * (1 << ((flags & 0x07) + 1));
+    }
+
+    let skip_sub_blocks = |mut offset: usize| -> Option<usize> {
+        loop {
+            let len = *content.get(offset)? as usize;
+            offset += 1;
+            if len == 0 {
+                return Some(offset);
+            }
+            offset += len;
+        }
+    };
+
+    let mut frames = 0;
+    while let Some(&block) = content.get(offset) {
+        match block {
+            // extension: introducer, label, then data sub-blocks
+            0x21 => match skip_sub_blocks(offset + 2) {
+                Some(next) => offset = next,
+                None => break,
+            },
+            // image descriptor, optional local color table, LZW code size, data
+            0x2c => {
+                frames += 1;
+                let Some(&flags) = content.get(offset + 9) else { break };
+                offset += 10;
+                if flags & 0x80 != 0 {
+                    offset += 3 * (1 << ((flags & 0x07) + 1));
+                }
+                match skip_sub_blocks(offset + 1) {
+                    Some(next) => offset = next,
+                    None => break,
+                }
+            }
+            _ => break,
+        }
+    }
+    frames
+}
+
+fn looks_like_text(head: &[u8], truncated: bool) -> bool {
+    let head = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head);
+
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // A multi byte character may be cut off at the end of the sniffed window.
+        Err(e) if truncated && e.error_len().is_none() => {
+            std::str::from_utf8(&head[..e.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return false,
+    };
+
+    let control = text.chars()
+        .filter(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t' | '\x0c'))
+        .count();
+    control == 0 || control * 100 < text.len()
+}
+
+fn looks_like_delimited(head: &[u8]) -> bool {
+    CSV_DELIMITERS.iter().any(|delimiter| delimiter_is_consistent(head, *delimiter))
+}
+
+// Every complete line in the sample has the same, non-zero number of
+// delimiters, and there are at least two lines.
+fn delimiter_is_consistent(head: &[u8], delimiter: u8) -> bool {
+    let mut lines: Vec<&[u8]> = head.split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect();
+    // the last line may be cut off by the sniff window or be the trailing newline
+    lines.pop();
+    lines.retain(|line| !line.is_empty());
+    lines.truncate(CSV_SNIFF_LINES);
+
+    if lines.len() < 2 {
+        return false;
+    }
+
+    let counts: Vec<usize> = lines.iter()
+        .map(|line| line.iter().filter(|b| **b == delimiter).count())
+        .collect();
+    counts[0] > 0 && counts.iter().all(|count| *count == counts[0])
+}
+
+fn mime_for(file_type: &SupportedFileType) -> &'static str {
+    match file_type {
+        SupportedFileType::Image => "image/*",
+        SupportedFileType::Video => "video/*",
+        SupportedFileType::Gif => "image/gif",
+        SupportedFileType::CSV => "text/csv",
+        SupportedFileType::Pdf => "application/pdf",
+        SupportedFileType::Archive => "application/zip",
+        SupportedFileType::Spreadsheet => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        SupportedFileType::Text => "text/plain",
+        SupportedFileType::Other(_) => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    // Minimal GIF: header, 1x1 screen without color table, then `frames` images.
+    fn gif(frames: usize) -> Vec<u8> {
+        let mut data = b"GIF89a\x01\x00\x01\x00\x00\x00\x00".to_vec();
+        data.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+        for _ in 0..frames {
+            data.extend_from_slice(b"\x21\xf9\x04\x00\x0a\x00\x00\x00");
+            data.extend_from_slice(b"\x2c\x00\x00\x00\x00\x01\x00\x01\x00\x00");
+            data.extend_from_slice(b"\x02\x02\x44\x01\x00");
+        }
+        data.push(0x3b);
+        data
+    }
+
+    fn zip_with(names: &[&str]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for name in names {
+            writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(b"<xml/>").unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_signatures() {
+        let png = detect_file_type(b"\x89PNG\r\n\x1a\n\x00\x00", "photo.jpg");
+        assert_eq!(png.file_type, SupportedFileType::Image);
+        assert_eq!(png.mime_type, "image/png");
+        assert_eq!(png.source, DetectionSource::Signature);
+
+        assert_eq!(detect_file_type(&[0xff, 0xd8, 0xff, 0xe0], "x").mime_type, "image/jpeg");
+        assert_eq!(detect_file_type(b"%PDF-1.7\n", "report").file_type, SupportedFileType::Pdf);
+        assert_eq!(detect_file_type(b"\x00\x00\x00\x14ftypqt  \x00\x00", "clip").mime_type, "video/quicktime");
+        assert_eq!(detect_file_type(b"\x00\x00\x00\x18ftypmp42\x00\x00", "clip").mime_type, "video/mp4");
+
+        let legacy = b"\x00\x00\x00\x08wide\x00\x00\x00\x0cmdat\x01\x02\x03\x04";
+        assert_eq!(detect_file_type(legacy, "clip").mime_type, "video/quicktime");
+    }
+
+    #[test]
+    fn test_atom_names_in_text_are_not_video() {
+        let text = detect_file_type(b"The free market is a place, not a policy.\n", "notes.txt");
+        assert_eq!(text.file_type, SupportedFileType::Text);
+        assert_eq!(text.source, DetectionSource::ContentHeuristic);
+
+        let csv = detect_file_type(b"id,moov\n1,2\n", "upload");
+        assert_eq!(csv.file_type, SupportedFileType::CSV);
+        // a plausible first atom with nothing after it
+        assert_ne!(detect_file_type(b"\x00\x00\x00\x08free", "x").file_type, SupportedFileType::Video);
+    }
+
+    #[test]
+    fn test_animated_gif() {
+        let still = detect_file_type(&gif(1), "still.gif");
+        assert_eq!(still.file_type, SupportedFileType::Gif);
+        assert!(!still.animated);
+        assert!(detect_file_type(&gif(3), "anim.gif").animated);
+    }
+
+    #[test]
+    fn test_zip_and_xlsx() {
+        let xlsx = zip_with(&["[Content_Types].xml", "xl/workbook.xml"]);
+        assert_eq!(detect_file_type(&xlsx, "book").file_type, SupportedFileType::Spreadsheet);
+        assert_eq!(detect_file_type(&zip_with(&["a.txt"]), "a.zip").file_type, SupportedFileType::Archive);
+    }
+
+    #[test]
+    fn test_text_heuristics() {
+        let csv = detect_file_type(b"id,name\n1,Bulbasaur\n2,Ivysaur\n", "upload");
+        assert_eq!(csv.file_type, SupportedFileType::CSV);
+        assert_eq!(csv.source, DetectionSource::ContentHeuristic);
+
+        let tsv = detect_file_type(b"id\tname\n1\tBulbasaur\n", "upload");
+        assert_eq!(tsv.mime_type, "text/tab-separated-values");
+
+        let text = detect_file_type("# Notes\nsome thoughts, written down\n\u{e9}t\u{e9}\n".as_bytes(), "notes");
+        assert_eq!(text.file_type, SupportedFileType::Text);
+
+        let binary = detect_file_type(&[0, 159, 146, 150, 1, 2, 3], "blob.dat");
+        assert_eq!(binary.file_type, SupportedFileType::Other("dat".to_string()));
+        assert_eq!(binary.source, DetectionSource::Extension);
+    }
+
+    #[test]
+    fn test_pokemon_csv_is_detected() {
+        const POKEMON_CSV: &[u8] = include_bytes!("../../../../datasets/pokemon.csv");
+        assert_eq!(detect_file_type(POKEMON_CSV, "pokemon").file_type, SupportedFileType::CSV);
+    }
+
+    #[test]
+    fn test_create_file_modes() {
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00".to_vec();
+        let mut vfs = VirtualFileSystem::new();
+
+        let id = vfs.create_file("logo.csv".to_string(), SupportedFileType::CSV, png.clone()).unwrap();
+        let file = vfs.read_file(&id).unwrap();
+        assert_eq!(file.file_type, SupportedFileType::CSV);
+        assert_eq!(file.type_mismatch.as_ref().unwrap().detected, SupportedFileType::Image);
+
+        vfs.set_type_detection(TypeDetectionMode::Auto);
+        let id = vfs.create_file("logo2.csv".to_string(), SupportedFileType::CSV, png.clone()).unwrap();
+        let file = vfs.read_file(&id).unwrap();
+        assert_eq!(file.file_type, SupportedFileType::Image);
+        assert!(file.type_mismatch.is_none());
+
+        vfs.set_type_detection(TypeDetectionMode::Trust);
+        let id = vfs.create_file("logo3.csv".to_string(), SupportedFileType::CSV, png).unwrap();
+        assert!(vfs.read_file(&id).unwrap().type_mismatch.is_none());
+    }
+
+    #[test]
+    fn test_find_mismatch() {
+        let png = detect_file_type(b"\x89PNG\r\n\x1a\n", "a.csv");
+        let mismatch = find_mismatch(&SupportedFileType::CSV, &png).unwrap();
+        assert_eq!(mismatch.detected, SupportedFileType::Image);
+        assert!(find_mismatch(&SupportedFileType::Image, &png).is_none());
+
+        let csv = detect_file_type(b"a,b\n1,2\n", "a");
+        assert!(find_mismatch(&SupportedFileType::Image, &csv).is_some());
+        assert!(find_mismatch(&SupportedFileType::Text, &csv).is_none());
+    }
+}
@@ -0,0 +1,415 @@
+// core/encryption.rs
+//
+// Passphrase based encryption of file content at rest. The content of a
+// file flagged `encrypted` is sealed with ChaCha20-Poly1305 before it goes
+// into the blob store, so snapshots, storage backends and content providers
+// only ever see ciphertext. Reads decrypt it again while the file system is
+// unlocked and fail with `Locked` otherwise.
+//
+// Records are not encrypted: ids, names, paths, types, sizes, metadata,
+// tags, links, timestamps and version messages are stored in the clear.
+// The checksum of encrypted content is an HMAC-SHA256 under the content
+// key rather than a plain SHA-256, so a stored record can't be used to
+// confirm a guess at the content; it changes when a file is converted.
+//
+// Content is sealed with a random key, which is itself stored sealed under
+// a key derived from the passphrase with Argon2id. The `EncryptionHeader`
+// holding the salt, KDF parameters and wrapped key travels with snapshots
+// and storage backends. Unwrapping the key is what checks the passphrase:
+// a failure there is `WrongPassphrase`, while content that fails to
+// decrypt under the unwrapped key is `CorruptContent`.
+//
+// Sealed content lives in its own blob, keyed by the checksum of the
+// ciphertext and recorded as `encrypted_blob` on the version. The nonce is
+// derived from the key and the plaintext, so identical encrypted content
+// still ends up in a single blob. Content is compressed before sealing.
+use std::borrow::Cow;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+
+use crate::core::blob_store::calculate_checksum;
+use crate::core::compression::{compress_if_smaller, Compression};
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::file_system::VirtualFileSystem;
+use crate::core::versioning::FileVersion;
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+
+type Key = [u8; KEY_LEN];
+
+/// Argon2id cost. The default follows the OWASP recommendation of 19 MiB
+/// and two passes.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams { memory_kib: 19 * 1024, iterations: 2 }
+    }
+}
+
+/// Everything needed to get the content key back from the passphrase.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EncryptionHeader {
+    pub kdf: KdfParams,
+    pub salt: Vec<u8>,
+    /// The content key, sealed under the key derived from the passphrase.
+    pub wrapped_key: Vec<u8>,
+}
+
+#[derive(Default)]
+pub(crate) struct Encryption {
+    pub(crate) header: Option<EncryptionHeader>,
+    // only held while unlocked
+    key: Option<Key>,
+    encrypt_new_files: bool,
+}
+
+impl VirtualFileSystem {
+    /// Sets up encryption with a new random content key protected by
+    /// `passphrase`, and leaves the file system unlocked. Files stay as
+    /// they are until flagged with `set_encrypted` or created while
+    /// `set_encrypt_new_files` is on.
+    pub fn enable_encryption(&mut self, passphrase: &str, kdf: KdfParams) -> VfsResult<()> {
+        if self.encryption.header.is_some() {
+            return Err(VfsError::InvalidOperation { reason: "encryption is already enabled".to_string() });
+        }
+
+        let salt: [u8; SALT_LEN] = random_bytes();
+        let key: Key = random_bytes();
+        let wrapping_key = derive_key(passphrase, &salt, kdf)?;
+        self.encryption.header = Some(EncryptionHeader {
+            kdf,
+            salt: salt.to_vec(),
+            wrapped_key: seal(&wrapping_key, random_bytes(), &key),
+        });
+        self.encryption.key = Some(key);
+        self.flush()
+    }
+
+    pub fn encryption_enabled(&self) -> bool {
+        self.encryption.header.is_some()
+    }
+
+    /// True while encrypted content can't be read or written.
+    pub fn is_locked(&self) -> bool {
+        self.encryption.header.is_some() && self.encryption.key.is_none()
+    }
+
+    /// Forgets the content key. Encrypted files keep their records but
+    /// their content is unavailable until `unlock`.
+    pub fn lock(&mut self) {
+        self.encryption.key = None;
+    }
+
+    pub fn unlock(&mut self, passphrase: &str) -> VfsResult<()> {
+        let header = self.encryption.header.as_ref()
+            .ok_or_else(not_enabled)?;
+        let wrapping_key = derive_key(passphrase, &header.salt, header.kdf)?;
+        let key = open(&wrapping_key, &header.wrapped_key)
+            .and_then(|key| Key::try_from(key).ok())
+            .ok_or(VfsError::WrongPassphrase)?;
+        self.encryption.key = Some(key);
+        Ok(())
+    }
+
+    /// Whether files created from now on are encrypted.
+    pub fn set_encrypt_new_files(&mut self, encrypt: bool) {
+        self.encryption.encrypt_new_files = encrypt;
+    }
+
+    pub(crate) fn encrypt_new_files(&self) -> bool {
+        self.encryption.encrypt_new_files
+    }
+
+    /// Encrypts or decrypts every version of a file. Needs the file system
+    /// to be unlocked either way.
+    pub fn set_encrypted(&mut self, id: &str, encrypted: bool) -> VfsResult<()> {
+        let file = self.file(id)?;
+        if file.encrypted == encrypted {
+            return Ok(());
+        }
+        self.content_key()?;
+        let file_type = file.file_type.clone();
+        let versions = file.versions.clone();
+
+        let mut contents = Vec::with_capacity(versions.len());
+        for version in &versions {
+            contents.push(self.load_content(id, &version.checksum)?.into_owned());
+        }
+
+        let mut converted: Vec<FileVersion> = Vec::with_capacity(versions.len());
+        for (version, content) in versions.iter().zip(contents) {
+            let checksum = calculate_checksum(&content);
+            let mut version = FileVersion::new(version.version, checksum, version.size, version.message.clone(), version.timestamp);
+            if let Err(error) = self.store_content(&mut version, content, &file_type, encrypted) {
+                for version in &converted {
+                    self.blobs.release(version.blob_key());
+                }
+                return Err(error);
+            }
+            converted.push(version);
+        }
+        for version in &versions {
+            self.blobs.release(version.blob_key());
+        }
+
+        let file = self.files.get_mut(id)
+            .ok_or_else(|| VfsError::not_found(id))?;
+        if let Some(current) = converted.last() {
+            file.checksum = current.checksum.clone();
+            file.stored_size = current.stored_size;
+            file.compression = current.compression;
+        }
+        file.versions = converted;
+        file.encrypted = encrypted;
+        self.storage.mark_dirty(id);
+        self.flush()
+    }
+
+    // Seals content for `version` and stores it in a blob of its own. The
+    // checksum of the version is replaced by the keyed one.
+    pub(crate) fn store_sealed(&mut self, version: &mut FileVersion, content: Vec<u8>, codec: Compression) -> VfsResult<()> {
+        let key = self.content_key()?;
+        version.checksum = keyed_checksum(&key, &content);
+        let (compression, data) = compress_if_smaller(content, codec);
+        let sealed = seal(&key, content_nonce(&key, &data), &data);
+
+        let blob = calculate_checksum(&sealed);
+        version.stored_size = sealed.len();
+        version.compression = compression;
+        version.encrypted_blob = Some(blob.clone());
+        self.blobs.insert_with_checksum(blob, sealed)
+    }
+
+    // Content of the version of file `id` with this checksum, decrypted if
+    // the version is encrypted.
+    pub(crate) fn load_content(&mut self, id: &str, checksum: &str) -> VfsResult<Cow<'_, [u8]>> {
+        let sealed = self.file(id)?.versions.iter()
+            .find(|version| version.checksum == checksum && version.encrypted_blob.is_some())
+            .cloned();
+        let Some(version) = sealed else {
+            return self.load_blob(id, checksum);
+        };
+
+        let key = self.content_key()?;
+        let corrupt = |reason: &str| VfsError::CorruptContent { id: id.to_string(), reason: reason.to_string() };
+        let data = self.load_blob(id, version.blob_key())?;
+        let data = open(&key, &data)
+            .ok_or_else(|| corrupt("authentication failed"))?;
+        version.compression.decompress(&data, version.size)
+            .map(Cow::Owned)
+            .map_err(|e| corrupt(&e.to_string()))
+    }
+
+    fn content_key(&self) -> VfsResult<Key> {
+        match (&self.encryption.header, self.encryption.key) {
+            (_, Some(key)) => Ok(key),
+            (Some(_), None) => Err(VfsError::Locked),
+            (None, None) => Err(not_enabled()),
+        }
+    }
+}
+
+fn not_enabled() -> VfsError {
+    VfsError::InvalidOperation { reason: "encryption is not enabled".to_string() }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], kdf: KdfParams) -> VfsResult<Key> {
+    let invalid = |e: argon2::Error| VfsError::InvalidOperation { reason: format!("key derivation failed: {}", e) };
+    let params = Params::new(kdf.memory_kib, kdf.iterations, 1, Some(KEY_LEN)).map_err(invalid)?;
+
+    let mut key = [0; KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(invalid)?;
+    Ok(key)
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).expect("the platform provides randomness");
+    bytes
+}
+
+// HMAC-SHA256 of `content` under the content key, hex encoded like
+// `calculate_checksum`.
+fn keyed_checksum(key: &Key, content: &[u8]) -> String {
+    format!("{:x}", hmac_sha256(key, content))
+}
+
+// RFC 2104, for keys no longer than a SHA-256 block.
+fn hmac_sha256(key: &[u8], data: &[u8]) -> sha2::digest::Output<Sha256> {
+    let mut block = [0; 64];
+    block[..key.len()].copy_from_slice(key);
+    let inner = Sha256::new()
+        .chain_update(block.map(|byte| byte ^ 0x36))
+        .chain_update(data)
+        .finalize();
+    Sha256::new()
+        .chain_update(block.map(|byte| byte ^ 0x5c))
+        .chain_update(inner)
+        .finalize()
+}
+
+// A nonce that only repeats for the same key and plaintext.
+fn content_nonce(key: &Key, data: &[u8]) -> [u8; NONCE_LEN] {
+    let digest = Sha256::new().chain_update(key).chain_update(data).finalize();
+    let mut nonce = [0; NONCE_LEN];
+    nonce.copy_from_slice(&digest[..NONCE_LEN]);
+    nonce
+}
+
+// The nonce followed by the ciphertext and tag.
+fn seal(key: &Key, nonce: [u8; NONCE_LEN], data: &[u8]) -> Vec<u8> {
+    let ciphertext = ChaCha20Poly1305::new(key.into())
+        .encrypt(Nonce::from_slice(&nonce), data)
+        .expect("content is far below the ChaCha20 length limit");
+    [nonce.as_slice(), &ciphertext].concat()
+}
+
+fn open(key: &Key, sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    ChaCha20Poly1305::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{MemoryBackend, StorageBackend, SupportedFileType};
+
+    // cheap enough for debug builds
+    const TEST_KDF: KdfParams = KdfParams { memory_kib: 64, iterations: 1 };
+
+    fn encrypted_vfs() -> VirtualFileSystem {
+        let mut vfs = VirtualFileSystem::new();
+        vfs.enable_encryption("correct horse", TEST_KDF).unwrap();
+        vfs.set_encrypt_new_files(true);
+        vfs
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn test_lock_and_unlock() {
+        let mut vfs = encrypted_vfs();
+        let id = vfs.create_file_at("/customers.csv", SupportedFileType::CSV, b"name,iban\nacme,DE89".to_vec()).unwrap();
+        let plain = vfs.create_file_at("/readme.txt", SupportedFileType::Text, b"hello".to_vec()).unwrap();
+        vfs.set_encrypted(&plain, false).unwrap();
+
+        let flags: Vec<(String, bool)> = vfs.list_files().iter().map(|file| (file.name.clone(), file.encrypted)).collect();
+        assert!(flags.contains(&("customers.csv".to_string(), true)));
+        assert!(flags.contains(&("readme.txt".to_string(), false)));
+        assert_eq!(vfs.get_file_content(&id).unwrap(), b"name,iban\nacme,DE89");
+
+        vfs.lock();
+        assert!(vfs.is_locked());
+        assert_eq!(vfs.get_file_content(&id).unwrap_err(), VfsError::Locked);
+        assert_eq!(vfs.update_file(&id, Some(b"x".to_vec())).unwrap_err(), VfsError::Locked);
+        assert_eq!(vfs.read_file(&id).unwrap().size, 19);
+        assert_eq!(vfs.get_file_content(&plain).unwrap(), b"hello");
+
+        assert_eq!(vfs.unlock("wrong horse").unwrap_err().code(), "WRONG_PASSPHRASE");
+        vfs.unlock("correct horse").unwrap();
+        vfs.append(&id, b"\nglobex,FR76").unwrap();
+        assert_eq!(vfs.read_version(&id, 1).unwrap(), b"name,iban\nacme,DE89");
+        // archives carry the plain checksum of what they hold
+        let archive = vfs.export_zip().unwrap();
+        let mut imported = VirtualFileSystem::new();
+        imported.import_zip(&archive).unwrap();
+        let copy = imported.resolve_path("/customers.csv").unwrap();
+        assert_eq!(imported.get_file_content(&copy).unwrap(), vfs.get_file_content(&id).unwrap());
+    }
+
+    #[test]
+    fn test_nothing_persisted_in_plaintext() {
+        let secret = b"account,balance\nacme,1000000".to_vec();
+        let backend = MemoryBackend::new();
+        let mut vfs = VirtualFileSystem::open(Box::new(backend.clone())).unwrap();
+        vfs.enable_encryption("pass", TEST_KDF).unwrap();
+        vfs.set_encrypt_new_files(true);
+        let id = vfs.create_file_at("/ledger.csv", SupportedFileType::CSV, secret.clone()).unwrap();
+        vfs.create_file_at("/copy.csv", SupportedFileType::CSV, secret.clone()).unwrap();
+
+        // identical encrypted content is still stored once
+        assert_eq!(vfs.storage_stats().blob_count, 1);
+        let snapshot = vfs.export_snapshot();
+        assert!(!contains(&snapshot, b"acme"));
+        let blob = vfs.read_file(&id).unwrap().versions[0].encrypted_blob.clone().unwrap();
+        assert!(!contains(&backend.clone().get_blob(&blob).unwrap().unwrap(), b"acme"));
+
+        // the stored checksum doesn't confirm a guess at the content
+        let guess = calculate_checksum(&secret);
+        assert!(!contains(&snapshot, guess.as_bytes()));
+        assert!(backend.clone().load().unwrap().records.iter().all(|record| {
+            record.checksum != guess && record.versions.iter().all(|version| version.checksum != guess)
+        }));
+
+        let mut restored = VirtualFileSystem::new();
+        restored.import_snapshot(&snapshot).unwrap();
+        assert!(restored.is_locked());
+        assert_eq!(restored.get_file_content(&id).unwrap_err(), VfsError::Locked);
+        restored.unlock("pass").unwrap();
+        assert_eq!(restored.get_file_content(&id).unwrap(), secret);
+
+        let mut reopened = VirtualFileSystem::open(Box::new(backend)).unwrap();
+        assert!(reopened.is_locked());
+        reopened.unlock("pass").unwrap();
+        assert_eq!(reopened.get_file_content(&id).unwrap(), secret);
+    }
+
+    #[test]
+    fn test_converting_files_and_corruption() {
+        let mut vfs = VirtualFileSystem::new();
+        let id = vfs.create_file_at("/a.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+        vfs.update_file(&id, Some(b"a\n2".to_vec())).unwrap();
+        assert_eq!(vfs.set_encrypted(&id, true).unwrap_err().code(), "INVALID_OPERATION");
+
+        vfs.enable_encryption("pass", TEST_KDF).unwrap();
+        vfs.set_encrypted(&id, true).unwrap();
+        let file = vfs.read_file(&id).unwrap().clone();
+        assert!(file.versions.iter().all(|version| version.encrypted_blob.is_some()));
+        assert_eq!(vfs.storage_stats().blob_count, 2);
+        assert_eq!(vfs.read_version(&id, 1).unwrap(), b"a\n1");
+
+        // a damaged blob is corruption, not a wrong passphrase
+        let blob = file.versions[1].encrypted_blob.clone().unwrap();
+        let mut sealed = vfs.blobs.get(&blob).unwrap().into_owned();
+        sealed[NONCE_LEN] ^= 1;
+        vfs.blobs.release(&blob);
+        vfs.blobs.insert_with_checksum(blob, sealed).unwrap();
+        assert_eq!(vfs.get_file_content(&id).unwrap_err().code(), "CORRUPT_CONTENT");
+
+        vfs.revert_to_version(&id, 1, None).unwrap();
+        vfs.set_encrypted(&id, false).unwrap_err();
+        vfs.set_max_versions(1);
+        vfs.set_encrypted(&id, false).unwrap();
+        assert_eq!(vfs.get_file_content(&id).unwrap(), b"a\n1");
+        let file = vfs.read_file(&id).unwrap();
+        assert!(file.versions[0].encrypted_blob.is_none());
+        assert_eq!(file.checksum, calculate_checksum(b"a\n1"));
+        assert_eq!(file.versions[0].checksum, file.checksum);
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 1
+        let mac = hmac_sha256(&[0x0b; 20], b"Hi There");
+        assert_eq!(format!("{:x}", mac), "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7");
+    }
+}
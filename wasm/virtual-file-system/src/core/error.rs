@@ -0,0 +1,227 @@
+// core/error.rs
+//
+// Error types for the crate. Every variant has a stable machine readable
+// `code()` so callers (and the JS side) can branch without string matching.
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+pub type VfsResult<T> = Result<T, VfsError>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum VfsError {
+    NotFound { id: String },
+    PathNotFound { path: String },
+    AlreadyExists { path: String },
+    NotADirectory { path: String },
+    DirectoryNotEmpty { path: String },
+    InvalidPath { path: String, reason: String },
+    InvalidOperation { reason: String },
+    VersionNotFound { id: String, version: u32 },
+    MetadataNotFound { id: String, key: String },
+    /// The value does not satisfy the schema registered for the key.
+    InvalidMetadata { key: String, reason: String },
+    /// The file record exists but its bytes are not held in memory.
+    ContentEvicted { id: String },
+    /// Content of `requested` bytes doesn't fit the memory budget, which
+    /// has `available` bytes left once everything evictable is evicted.
+    QuotaExceeded { requested: usize, available: usize },
+    InvalidSnapshot { reason: String },
+    ChecksumMismatch { expected: String, actual: String },
+    InvalidArchive { reason: String },
+    OutOfRange { offset: usize, size: usize },
+    UploadNotFound { upload_id: String },
+    /// A storage backend failed to read or write.
+    Storage { reason: String },
+    /// Encrypted content was needed while the file system is locked.
+    Locked,
+    WrongPassphrase,
+    /// Encrypted content that doesn't decrypt with the right key.
+    CorruptContent { id: String, reason: String },
+    Csv(CsvError),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CsvError {
+    Empty,
+    /// `line` is 1-based, `column` is the 1-based index of the field that
+    /// failed, when there is one; a row with the wrong number of fields has
+    /// none.
+    ParseError { line: Option<u64>, column: Option<u64>, message: String },
+    ColumnNotFound { name: String },
+    /// A `CsvDialect` the reader can't use, e.g. a non-ASCII delimiter.
+    InvalidDialect { reason: String },
+}
+
+impl VfsError {
+    pub fn not_found(id: &str) -> Self {
+        VfsError::NotFound { id: id.to_string() }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            VfsError::NotFound { .. } => "NOT_FOUND",
+            VfsError::PathNotFound { .. } => "PATH_NOT_FOUND",
+            VfsError::AlreadyExists { .. } => "ALREADY_EXISTS",
+            VfsError::NotADirectory { .. } => "NOT_A_DIRECTORY",
+            VfsError::DirectoryNotEmpty { .. } => "DIRECTORY_NOT_EMPTY",
+            VfsError::InvalidPath { .. } => "INVALID_PATH",
+            VfsError::InvalidOperation { .. } => "INVALID_OPERATION",
+            VfsError::VersionNotFound { .. } => "VERSION_NOT_FOUND",
+            VfsError::MetadataNotFound { .. } => "METADATA_NOT_FOUND",
+            VfsError::InvalidMetadata { .. } => "INVALID_METADATA",
+            VfsError::ContentEvicted { .. } => "CONTENT_EVICTED",
+            VfsError::QuotaExceeded { .. } => "QUOTA_EXCEEDED",
+            VfsError::InvalidSnapshot { .. } => "INVALID_SNAPSHOT",
+            VfsError::ChecksumMismatch { .. } => "CHECKSUM_MISMATCH",
+            VfsError::InvalidArchive { .. } => "INVALID_ARCHIVE",
+            VfsError::OutOfRange { .. } => "OUT_OF_RANGE",
+            VfsError::UploadNotFound { .. } => "UPLOAD_NOT_FOUND",
+            VfsError::Storage { .. } => "STORAGE_ERROR",
+            VfsError::Locked => "LOCKED",
+            VfsError::WrongPassphrase => "WRONG_PASSPHRASE",
+            VfsError::CorruptContent { .. } => "CORRUPT_CONTENT",
+            VfsError::Csv(error) => error.code(),
+        }
+    }
+}
+
+impl CsvError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CsvError::Empty => "CSV_EMPTY",
+            CsvError::ParseError { .. } => "CSV_PARSE_ERROR",
+            CsvError::ColumnNotFound { .. } => "CSV_COLUMN_NOT_FOUND",
+            CsvError::InvalidDialect { .. } => "CSV_INVALID_DIALECT",
+        }
+    }
+}
+
+impl fmt::Display for VfsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VfsError::NotFound { id } => write!(f, "File not found: {}", id),
+            VfsError::PathNotFound { path } => write!(f, "Path not found: {}", path),
+            VfsError::AlreadyExists { path } => write!(f, "Path already exists: {}", path),
+            VfsError::NotADirectory { path } => write!(f, "Not a directory: {}", path),
+            VfsError::DirectoryNotEmpty { path } => write!(f, "Directory not empty: {}", path),
+            VfsError::InvalidPath { path, reason } => write!(f, "Invalid path {:?}: {}", path, reason),
+            VfsError::InvalidOperation { reason } => write!(f, "{}", reason),
+            VfsError::VersionNotFound { id, version } => write!(f, "Version {} of file {} not found", version, id),
+            VfsError::MetadataNotFound { id, key } => write!(f, "Metadata {:?} not found on file {}", key, id),
+            VfsError::InvalidMetadata { key, reason } => write!(f, "Invalid value for metadata {:?}: {}", key, reason),
+            VfsError::ContentEvicted { id } => write!(f, "Content of file {} has been evicted from memory", id),
+            VfsError::QuotaExceeded { requested, available } => {
+                write!(f, "Quota exceeded: {} bytes requested, {} available", requested, available)
+            }
+            VfsError::InvalidSnapshot { reason } => write!(f, "Invalid snapshot: {}", reason),
+            VfsError::ChecksumMismatch { expected, actual } => {
+                write!(f, "Checksum mismatch: expected {}, got {}", expected, actual)
+            }
+            VfsError::InvalidArchive { reason } => write!(f, "Invalid archive: {}", reason),
+            VfsError::OutOfRange { offset, size } => {
+                write!(f, "Offset {} is past the end of a {} byte file", offset, size)
+            }
+            VfsError::UploadNotFound { upload_id } => write!(f, "Upload not found: {}", upload_id),
+            VfsError::Storage { reason } => write!(f, "Storage error: {}", reason),
+            VfsError::Locked => write!(f, "Encrypted content is locked"),
+            VfsError::WrongPassphrase => write!(f, "Wrong passphrase"),
+            VfsError::CorruptContent { id, reason } => write!(f, "Content of file {} is corrupt: {}", id, reason),
+            VfsError::Csv(error) => error.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::Empty => write!(f, "Empty CSV"),
+            CsvError::ParseError { line, column, message } => {
+                write!(f, "CSV parse error")?;
+                if let Some(line) = line {
+                    write!(f, " at line {}", line)?;
+                }
+                if let Some(column) = column {
+                    write!(f, ", column {}", column)?;
+                }
+                write!(f, ": {}", message)
+            }
+            CsvError::ColumnNotFound { name } => write!(f, "Column not found: {}", name),
+            CsvError::InvalidDialect { reason } => write!(f, "Invalid CSV dialect: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for VfsError {}
+impl std::error::Error for CsvError {}
+
+impl From<CsvError> for VfsError {
+    fn from(error: CsvError) -> Self {
+        VfsError::Csv(error)
+    }
+}
+
+impl From<zip::result::ZipError> for VfsError {
+    fn from(error: zip::result::ZipError) -> Self {
+        VfsError::InvalidArchive { reason: error.to_string() }
+    }
+}
+
+impl From<std::io::Error> for VfsError {
+    fn from(error: std::io::Error) -> Self {
+        VfsError::Storage { reason: error.to_string() }
+    }
+}
+
+impl From<csv::Error> for CsvError {
+    fn from(error: csv::Error) -> Self {
+        let line = error.position().map(|position| position.line());
+        let column = match error.kind() {
+            csv::ErrorKind::Utf8 { err, .. } => Some(err.field() as u64 + 1),
+            _ => None,
+        };
+
+        CsvError::ParseError {
+            line,
+            column,
+            message: error.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_and_messages() {
+        let error = VfsError::not_found("abc");
+        assert_eq!(error.code(), "NOT_FOUND");
+        assert_eq!(error.to_string(), "File not found: abc");
+
+        let error = VfsError::from(CsvError::ParseError { line: Some(3), column: Some(2), message: "bad".to_string() });
+        assert_eq!(error.code(), "CSV_PARSE_ERROR");
+        assert_eq!(error.to_string(), "CSV parse error at line 3, column 2: bad");
+    }
+
+    #[test]
+    fn test_csv_error_position() {
+        let mut reader = csv::ReaderBuilder::new().from_reader(&b"a,b\n1,2\n3,4,5"[..]);
+        let error = reader.records()
+            .find_map(|record| record.err())
+            .map(CsvError::from)
+            .unwrap();
+
+        match error {
+            CsvError::ParseError { line, column, .. } => {
+                assert_eq!(line, Some(3));
+                // the row is one field too long, no single field is at fault
+                assert_eq!(column, None);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let mut reader = csv::ReaderBuilder::new().from_reader(&b"a,b\n1,\xFF\n"[..]);
+        let error = reader.records().find_map(|record| record.err()).map(CsvError::from).unwrap();
+        assert!(matches!(error, CsvError::ParseError { column: Some(2), .. }), "{:?}", error);
+    }
+}
@@ -0,0 +1,267 @@
+// core/events.rs
+//
+// Change notifications. Every mutation of a file record emits a
+// `FileEvent` to the subscribers whose `EventFilter` matches it, so views
+// showing a file (a CSV table on the canvas, an image node) can refresh or
+// close themselves. Callbacks run synchronously, after the change is
+// applied, and may subscribe or unsubscribe while being called. Inside a
+// transaction events are held back until it commits.
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use serde::{Serialize, Deserialize};
+
+use crate::core::file_system::VirtualFileSystem;
+use crate::core::path;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum FileEvent {
+    Created { id: String, path: String },
+    /// New content, `version` is the version number it was recorded as.
+    Updated { id: String, path: String, version: u32 },
+    MetadataChanged { id: String, path: String, key: String },
+    /// Renamed or moved, including moves of a parent directory.
+    Moved { id: String, from: String, to: String },
+    Deleted { id: String, path: String },
+    /// Moved to the trash from `path`, see `core::trash`. `Deleted` follows
+    /// if it is purged from there.
+    Trashed { id: String, path: String },
+    /// Back from the trash, at `path`.
+    Restored { id: String, path: String },
+    TagsChanged { id: String, path: String },
+    /// Outgoing links were added or removed.
+    LinksChanged { id: String, path: String },
+}
+
+impl FileEvent {
+    pub fn id(&self) -> &str {
+        match self {
+            FileEvent::Created { id, .. }
+            | FileEvent::Updated { id, .. }
+            | FileEvent::MetadataChanged { id, .. }
+            | FileEvent::Moved { id, .. }
+            | FileEvent::Deleted { id, .. }
+            | FileEvent::Trashed { id, .. }
+            | FileEvent::Restored { id, .. }
+            | FileEvent::TagsChanged { id, .. }
+            | FileEvent::LinksChanged { id, .. } => id,
+        }
+    }
+
+    /// Paths the event touches, the old and the new one for moves.
+    pub fn paths(&self) -> Vec<&str> {
+        match self {
+            FileEvent::Created { path, .. }
+            | FileEvent::Updated { path, .. }
+            | FileEvent::MetadataChanged { path, .. }
+            | FileEvent::Deleted { path, .. }
+            | FileEvent::Trashed { path, .. }
+            | FileEvent::Restored { path, .. }
+            | FileEvent::TagsChanged { path, .. }
+            | FileEvent::LinksChanged { path, .. } => vec![path],
+            FileEvent::Moved { from, to, .. } => vec![from, to],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum EventFilter {
+    All,
+    /// Events for one file, wherever it moves.
+    File(String),
+    /// Events for a path, or for anything below it when it is a directory.
+    Path(String),
+}
+
+impl EventFilter {
+    fn matches(&self, event: &FileEvent) -> bool {
+        match self {
+            EventFilter::All => true,
+            EventFilter::File(id) => event.id() == id,
+            EventFilter::Path(filter) => event.paths().into_iter()
+                .any(|event_path| event_path == filter || path::is_descendant(event_path, filter)),
+        }
+    }
+}
+
+type Callback = Rc<RefCell<dyn FnMut(&FileEvent)>>;
+
+struct Subscriber {
+    id: u64,
+    filter: EventFilter,
+    callback: Callback,
+}
+
+#[derive(Default)]
+struct Subscribers {
+    next_id: u64,
+    list: Vec<Subscriber>,
+}
+
+#[derive(Default)]
+pub(crate) struct EventBus {
+    subscribers: Rc<RefCell<Subscribers>>,
+    // Some while a transaction is open
+    held: Option<Vec<FileEvent>>,
+}
+
+impl EventBus {
+    pub(crate) fn hold(&mut self) {
+        self.held.get_or_insert_with(Vec::new);
+    }
+
+    /// Delivers the held events, in order.
+    pub(crate) fn release(&mut self) {
+        for event in self.held.take().unwrap_or_default() {
+            self.emit(event);
+        }
+    }
+
+    pub(crate) fn discard(&mut self) {
+        self.held = None;
+    }
+
+    pub(crate) fn emit(&mut self, event: FileEvent) {
+        if let Some(held) = self.held.as_mut() {
+            held.push(event);
+            return;
+        }
+
+        // Collect first so callbacks can (un)subscribe without a double borrow.
+        let callbacks: Vec<Callback> = self.subscribers.borrow().list.iter()
+            .filter(|subscriber| subscriber.filter.matches(&event))
+            .map(|subscriber| subscriber.callback.clone())
+            .collect();
+
+        for callback in callbacks {
+            // A callback that triggers itself is skipped instead of panicking.
+            if let Ok(mut callback) = callback.try_borrow_mut() {
+                callback(&event);
+            }
+        }
+    }
+}
+
+/// Handle returned by `subscribe`. Dropping it keeps the subscription
+/// alive; call `unsubscribe` to stop receiving events.
+pub struct Subscription {
+    id: u64,
+    subscribers: Weak<RefCell<Subscribers>>,
+}
+
+impl Subscription {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Removes the subscriber. Safe to call more than once, or after the
+    /// file system is gone.
+    pub fn unsubscribe(&self) {
+        if let Some(subscribers) = self.subscribers.upgrade() {
+            subscribers.borrow_mut().list.retain(|subscriber| subscriber.id != self.id);
+        }
+    }
+}
+
+impl VirtualFileSystem {
+    pub fn subscribe(&mut self, filter: EventFilter, callback: impl FnMut(&FileEvent) + 'static) -> Subscription {
+        let mut subscribers = self.events.subscribers.borrow_mut();
+        subscribers.next_id += 1;
+        let id = subscribers.next_id;
+        subscribers.list.push(Subscriber { id, filter, callback: Rc::new(RefCell::new(callback)) });
+
+        Subscription { id, subscribers: Rc::downgrade(&self.events.subscribers) }
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.events.subscribers.borrow().list.len()
+    }
+
+    // Every change to a file record goes through here, so the storage
+    // backend learns about it too.
+    pub(crate) fn emit(&mut self, event: FileEvent) {
+        self.storage.mark_dirty(event.id());
+        self.events.emit(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SupportedFileType;
+
+    fn record(vfs: &mut VirtualFileSystem, filter: EventFilter) -> (Rc<RefCell<Vec<FileEvent>>>, Subscription) {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = events.clone();
+        let subscription = vfs.subscribe(filter, move |event| sink.borrow_mut().push(event.clone()));
+        (events, subscription)
+    }
+
+    #[test]
+    fn test_lifecycle_events() {
+        let mut vfs = VirtualFileSystem::new();
+        let (events, _) = record(&mut vfs, EventFilter::All);
+
+        let id = vfs.create_file_at("/data/a.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+        vfs.update_file(&id, Some(b"a\n2".to_vec())).unwrap();
+        vfs.set_file_metadata(&id, "position".to_string(), "1,1".to_string()).unwrap();
+        vfs.rename("/data", "tables").unwrap();
+        vfs.delete_file(&id).unwrap();
+
+        assert_eq!(*events.borrow(), vec![
+            FileEvent::Created { id: id.clone(), path: "/data/a.csv".to_string() },
+            FileEvent::Updated { id: id.clone(), path: "/data/a.csv".to_string(), version: 2 },
+            FileEvent::MetadataChanged { id: id.clone(), path: "/data/a.csv".to_string(), key: "position".to_string() },
+            FileEvent::Moved { id: id.clone(), from: "/data/a.csv".to_string(), to: "/tables/a.csv".to_string() },
+            FileEvent::Deleted { id: id.clone(), path: "/tables/a.csv".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut vfs = VirtualFileSystem::new();
+        let a = vfs.create_file_at("/data/a.csv", SupportedFileType::CSV, vec![1]).unwrap();
+        let b = vfs.create_file_at("/other/b.csv", SupportedFileType::CSV, vec![2]).unwrap();
+
+        let (by_id, _) = record(&mut vfs, EventFilter::File(a.clone()));
+        let (by_dir, _) = record(&mut vfs, EventFilter::Path("/data".to_string()));
+
+        vfs.update_file(&b, Some(vec![3])).unwrap();
+        vfs.update_file(&a, Some(vec![4])).unwrap();
+        // moving b into /data is seen by the directory subscriber only
+        vfs.move_path("/other/b.csv", "/data/b.csv").unwrap();
+
+        assert_eq!(by_id.borrow().len(), 1);
+        assert_eq!(by_dir.borrow().len(), 2);
+        assert!(matches!(&by_dir.borrow()[1], FileEvent::Moved { id, .. } if *id == b));
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let mut vfs = VirtualFileSystem::new();
+        let (events, subscription) = record(&mut vfs, EventFilter::All);
+        vfs.create_file_at("/a.txt", SupportedFileType::Text, vec![1]).unwrap();
+
+        subscription.unsubscribe();
+        subscription.unsubscribe();
+        assert_eq!(vfs.subscriber_count(), 0);
+        vfs.create_file_at("/b.txt", SupportedFileType::Text, vec![1]).unwrap();
+        assert_eq!(events.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_callback_can_unsubscribe_itself() {
+        let mut vfs = VirtualFileSystem::new();
+        let handle: Rc<RefCell<Option<Subscription>>> = Rc::new(RefCell::new(None));
+        let inner = handle.clone();
+        let subscription = vfs.subscribe(EventFilter::All, move |_| {
+            if let Some(subscription) = inner.borrow().as_ref() {
+                subscription.unsubscribe();
+            }
+        });
+        *handle.borrow_mut() = Some(subscription);
+
+        vfs.create_file_at("/a.txt", SupportedFileType::Text, vec![1]).unwrap();
+        assert_eq!(vfs.subscriber_count(), 0);
+    }
+}
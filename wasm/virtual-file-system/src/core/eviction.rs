@@ -0,0 +1,176 @@
+// core/eviction.rs
+//
+// Memory budget for file content. Once the blobs held in memory go over the
+// budget the least recently used ones are evicted: the file records, paths
+// and checksums stay, only the bytes are dropped. Reading evicted content
+// asks the configured `ContentProvider` for it (IndexedDB on the JS side, a
+// directory on disk natively), then the storage backend, and fails with
+// `ContentEvicted` if nothing can supply it. The host can also push bytes back with `rehydrate`.
+use std::borrow::Cow;
+use serde::{Serialize, Deserialize};
+
+use crate::core::blob_store::{calculate_checksum, ContentProvider};
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::file_system::VirtualFileSystem;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ContentState {
+    /// The current content is held in memory.
+    Resident,
+    /// The current content was evicted and has to be rehydrated.
+    Evicted,
+}
+
+impl VirtualFileSystem {
+    /// Caps the bytes of content kept in memory, `None` for no limit.
+    /// Lowering the budget evicts immediately. Content the provider fails
+    /// to store stays in memory and the error is returned. From then on,
+    /// content that can't be fit under the budget is refused with
+    /// `QuotaExceeded`: new files and versions aren't created, and evicted
+    /// content isn't brought back.
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) -> VfsResult<()> {
+        self.blobs.set_budget(budget)
+    }
+
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.blobs.budget()
+    }
+
+    /// Sets where evicted content is written to and read back from.
+    pub fn set_content_provider(&mut self, provider: Option<Box<dyn ContentProvider>>) {
+        self.blobs.set_provider(provider);
+    }
+
+    pub fn content_state(&self, id: &str) -> VfsResult<ContentState> {
+        let file = self.file(id)?;
+        Ok(if self.blobs.is_resident(file.blob_key()) {
+            ContentState::Resident
+        } else {
+            ContentState::Evicted
+        })
+    }
+
+    /// Hands back the bytes of an evicted file, or of any of its versions.
+    /// The bytes must match the checksum of one of the file's versions;
+    /// for encrypted files they are the ciphertext the provider was given.
+    pub fn rehydrate(&mut self, id: &str, content: Vec<u8>) -> VfsResult<()> {
+        let file = self.file(id)?;
+        let actual = calculate_checksum(&content);
+        if !file.versions.iter().any(|version| version.blob_key() == actual) {
+            return Err(VfsError::ChecksumMismatch { expected: file.checksum.clone(), actual });
+        }
+
+        // The checksum was verified above, only the budget can refuse it.
+        self.blobs.restore(&actual, content)
+    }
+
+    // Bytes of one blob of file `id`, rehydrated through the provider or
+    // the storage backend if needed, and decompressed. For encrypted files
+    // that is still the ciphertext, see `load_content`.
+    pub(crate) fn load_blob(&mut self, id: &str, checksum: &str) -> VfsResult<Cow<'_, [u8]>> {
+        if self.blobs.load(checksum)?.is_none() {
+            if let Some(content) = self.storage.load_blob(checksum)? {
+                self.blobs.restore(checksum, content)?;
+            }
+        }
+        self.blobs.get(checksum)
+            .ok_or_else(|| VfsError::ContentEvicted { id: id.to_string() })
+    }
+}
+
+/// Keeps evicted content in a directory, one file per checksum.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct DirectoryContentProvider {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DirectoryContentProvider {
+    pub fn new(dir: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ContentProvider for DirectoryContentProvider {
+    fn store(&mut self, checksum: &str, content: &[u8]) -> VfsResult<()> {
+        let path = self.dir.join(checksum);
+        // Content addressed, so an existing file already holds these bytes.
+        if !path.exists() {
+            std::fs::write(path, content)?;
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, checksum: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.dir.join(checksum)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SupportedFileType;
+
+    fn binary() -> SupportedFileType {
+        SupportedFileType::Other("bin".to_string())
+    }
+
+    #[test]
+    fn test_budget_evicts_and_rehydrates() {
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_memory_budget(Some(10)).unwrap();
+        let a = vfs.create_file_at("/a.bin", binary(), vec![1; 6]).unwrap();
+        let b = vfs.create_file_at("/b.bin", binary(), vec![2; 6]).unwrap();
+
+        assert_eq!(vfs.content_state(&a).unwrap(), ContentState::Evicted);
+        assert_eq!(vfs.content_state(&b).unwrap(), ContentState::Resident);
+        assert!(matches!(vfs.get_file_content(&a), Err(VfsError::ContentEvicted { .. })));
+
+        let stats = vfs.storage_stats();
+        assert_eq!(stats.stored_bytes, 6);
+        assert_eq!(stats.evicted_blob_count, 1);
+        assert_eq!(stats.memory_budget, Some(10));
+
+        assert!(matches!(vfs.rehydrate(&a, vec![9; 6]), Err(VfsError::ChecksumMismatch { .. })));
+        vfs.rehydrate(&a, vec![1; 6]).unwrap();
+        assert_eq!(vfs.get_file_content(&a).unwrap(), vec![1; 6]);
+        assert_eq!(vfs.content_state(&b).unwrap(), ContentState::Evicted);
+    }
+
+    #[test]
+    fn test_content_over_budget_is_refused() {
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_memory_budget(Some(4)).unwrap();
+        let a = vfs.create_file_at("/a.bin", binary(), vec![1; 4]).unwrap();
+
+        let error = vfs.create_file_at("/b.bin", binary(), vec![2; 6]).unwrap_err();
+        assert_eq!(error.code(), "QUOTA_EXCEEDED");
+        assert!(!vfs.exists("/b.bin"));
+        assert!(matches!(vfs.update_file_with_message(&a, vec![3; 5], None), Err(VfsError::QuotaExceeded { requested: 5, .. })));
+        assert_eq!(vfs.list_versions(&a).unwrap().len(), 1);
+        assert_eq!(vfs.storage_stats().blob_count, 1);
+    }
+
+    #[test]
+    fn test_directory_provider_round_trip() {
+        let dir = std::env::temp_dir().join(format!("vfs-evict-{}", uuid::Uuid::new_v4()));
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_content_provider(Some(Box::new(DirectoryContentProvider::new(&dir).unwrap())));
+        vfs.set_memory_budget(Some(8)).unwrap();
+
+        let a = vfs.create_file_at("/a.bin", binary(), vec![1; 8]).unwrap();
+        let b = vfs.create_file_at("/b.bin", binary(), vec![2; 8]).unwrap();
+        assert_eq!(vfs.content_state(&a).unwrap(), ContentState::Evicted);
+
+        // reading a brings it back from disk and pushes b out instead
+        assert_eq!(vfs.read_range(&a, 0, 2).unwrap(), vec![1, 1]);
+        assert_eq!(vfs.content_state(&a).unwrap(), ContentState::Resident);
+        assert_eq!(vfs.content_state(&b).unwrap(), ContentState::Evicted);
+        assert_eq!(vfs.get_file_content(&b).unwrap(), vec![2; 8]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
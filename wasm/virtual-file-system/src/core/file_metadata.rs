@@ -1,38 +1,52 @@
 use std::collections::HashMap;
 
+use crate::core::csv_dialect::CsvDialect;
+use crate::core::error::CsvError;
+
 pub struct FileMetadata {
     pub row_count: usize,
     pub column_count: usize,
     pub missing_values: usize,
     pub zero_values: usize,
     pub column_types: HashMap<String, String>,
+    pub dialect: CsvDialect,
 }
 
 impl FileMetadata {
-    pub fn analyze_csv(content: &str) -> Result<Self, String> {
-        let mut lines = content.lines();
-        let header = lines.next().ok_or_else(|| "Empty CSV".to_string())?;
-        let columns: Vec<&str> = header.split(',').collect();
+    pub fn analyze_csv(content: &str) -> Result<Self, CsvError> {
+        Self::analyze_csv_with_dialect(content, CsvDialect::sniff(content.as_bytes()))
+    }
+
+    /// `content` is text already, so the dialect's encoding is ignored.
+    pub fn analyze_csv_with_dialect(content: &str, dialect: CsvDialect) -> Result<Self, CsvError> {
+        let mut reader = dialect.reader_builder()?
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(content.trim_start_matches('\u{FEFF}').as_bytes());
+        let columns = reader.headers()?.clone();
+        if columns.is_empty() {
+            return Err(CsvError::Empty);
+        }
         let column_count = columns.len();
-        
+
         let mut row_count = 0;
         let mut missing_values = 0;
         let mut zero_values = 0;
         let mut column_types = HashMap::new();
-        
-        for line in lines {
+
+        for record in reader.records() {
+            let record = record?;
             row_count += 1;
-            let values: Vec<&str> = line.split(',').collect();
-            
-            for (i, value) in values.iter().enumerate() {
+
+            for (i, value) in record.iter().enumerate() {
                 if value.trim().is_empty() {
                     missing_values += 1;
                 } else if value.trim() == "0" {
                     zero_values += 1;
                 }
-                
+
                 if row_count == 1 {
-                    let col_name = columns[i];
+                    let Some(col_name) = columns.get(i) else { continue };
                     let data_type = if value.parse::<i64>().is_ok() {
                         "Integer"
                     } else if value.parse::<f64>().is_ok() {
@@ -44,13 +58,14 @@ impl FileMetadata {
                 }
             }
         }
-        
+
         Ok(FileMetadata {
             row_count,
             column_count,
             missing_values,
             zero_values,
             column_types,
+            dialect,
         })
     }
-}
\ No newline at end of file
+}
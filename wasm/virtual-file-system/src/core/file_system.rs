@@ -1,159 +1,1094 @@
 // core/file_system.rs
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{Cursor, Read, Write};
 use uuid::Uuid;
-use sha2::{Sha256, Digest};
 use serde::{Serialize, Deserialize};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
 
+use crate::core::blob_store::{calculate_checksum, BlobStore};
+use crate::core::clock::{self, Clock, SystemClock};
+use crate::core::compression::{Compression, CompressionPolicy};
+use crate::core::encryption::Encryption;
+use crate::core::detection::{detect_file_type, find_mismatch, TypeDetectionMode, TypeMismatch};
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::events::{EventBus, FileEvent};
+use crate::core::graph::{CascadeRule, FileLink, LinkKind};
+use crate::core::metadata::{MetadataSchema, MetadataValue};
+use crate::core::path;
+use crate::core::provenance::Provenance;
+use crate::core::storage::Storage;
+use crate::core::streaming::PendingUpload;
+use crate::core::transaction::Checkpoint;
+use crate::core::versioning::{FileVersion, DEFAULT_MAX_VERSIONS};
+
+/// A file record. The bytes themselves live in the file system's blob
+/// store under `checksum`, so records stay cheap to clone and list.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct VirtualFile {
     pub id: String,
     pub name: String,
+    pub path: String,
     pub file_type: SupportedFileType,
     pub size: usize,
-    #[serde(skip)]
-    pub content: Option<Vec<u8>>,
-    pub metadata: HashMap<String, String>,
-    pub checksum: String
+    pub metadata: HashMap<String, MetadataValue>,
+    pub checksum: String,
+    /// Bytes the current content takes in memory, below `size` when it is
+    /// compressed, see `core::compression`.
+    #[serde(default)]
+    pub stored_size: usize,
+    #[serde(default)]
+    pub compression: Compression,
+    /// Whether the content is encrypted at rest, see `core::encryption`.
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Content history, oldest first; the last entry matches `checksum`.
+    #[serde(default)]
+    pub versions: Vec<FileVersion>,
+    /// Set when the content contradicts the declared `file_type`.
+    #[serde(default)]
+    pub type_mismatch: Option<TypeMismatch>,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    /// Outgoing links to other files, see `core::graph`.
+    #[serde(default)]
+    pub links: Vec<FileLink>,
+    /// Milliseconds since the Unix epoch, see `core::provenance`.
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub modified_at: u64,
+    #[serde(default)]
+    pub accessed_at: u64,
+    #[serde(default)]
+    pub provenance: Provenance,
+    /// When the file was moved to the trash, `None` while it is in the
+    /// tree, see `core::trash`.
+    #[serde(default)]
+    pub deleted_at: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum SupportedFileType {
     Image,
     Video,
     Gif,
     CSV,
+    Pdf,
+    Archive,
+    Spreadsheet,
+    Text,
     Other(String),
 }
 
+impl SupportedFileType {
+    /// Best guess at the type of a file from the extension of its name.
+    pub fn from_extension(name: &str) -> Self {
+        let extension = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => extension.to_lowercase(),
+            _ => return SupportedFileType::Other(String::new()),
+        };
+
+        match extension.as_str() {
+            "csv" | "tsv" => SupportedFileType::CSV,
+            "gif" => SupportedFileType::Gif,
+            "png" | "jpg" | "jpeg" | "webp" | "bmp" | "svg" => SupportedFileType::Image,
+            "mp4" | "mov" | "webm" | "m4v" => SupportedFileType::Video,
+            "pdf" => SupportedFileType::Pdf,
+            "zip" => SupportedFileType::Archive,
+            "xlsx" | "xls" | "ods" => SupportedFileType::Spreadsheet,
+            "txt" | "md" | "markdown" | "json" | "log" => SupportedFileType::Text,
+            _ => SupportedFileType::Other(extension),
+        }
+    }
+
+    /// True for formats whose content is never plain text.
+    pub fn is_binary(&self) -> bool {
+        matches!(
+            self,
+            SupportedFileType::Image
+                | SupportedFileType::Video
+                | SupportedFileType::Gif
+                | SupportedFileType::Pdf
+                | SupportedFileType::Archive
+                | SupportedFileType::Spreadsheet
+        )
+    }
+}
+
 impl VirtualFile {
-    pub fn new(id: String, name: String, file_type: SupportedFileType, content: Vec<u8>) -> Self {
-        let size = content.len();
-        let checksum = Self::calculate_checksum(&content);
+    pub fn new(id: String, name: String, file_type: SupportedFileType, size: usize, checksum: String) -> Self {
+        let now = clock::now_millis();
         VirtualFile {
             id,
+            path: path::join(path::ROOT, &name),
             name, 
             file_type,
             size,
-            content: Some(content),
             metadata: HashMap::new(),
+            versions: vec![FileVersion::new(1, checksum.clone(), size, None, now)],
             checksum,
+            stored_size: size,
+            compression: Compression::None,
+            encrypted: false,
+            type_mismatch: None,
+            tags: BTreeSet::new(),
+            links: Vec::new(),
+            created_at: now,
+            modified_at: now,
+            accessed_at: now,
+            provenance: Provenance::Unknown,
+            deleted_at: None,
         }
     }
 
-    fn calculate_checksum(content: &[u8]) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(content);
-        format!("{:x}", hasher.finalize())
+    pub fn current_version(&self) -> u32 {
+        self.versions.last().map_or(0, |v| v.version)
+    }
+
+    /// Key of the blob holding the current content.
+    pub fn blob_key(&self) -> &str {
+        self.versions.last().map_or(&self.checksum, |v| v.blob_key())
     }
 }
 
+/// Name of the manifest written into archives by `export_zip`.
+pub const ZIP_MANIFEST_NAME: &str = ".vfs-manifest.json";
+
+/// One file described in an archive manifest.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ZipManifestEntry {
+    pub id: String,
+    pub path: String,
+    pub name: String,
+    pub file_type: SupportedFileType,
+    pub size: usize,
+    pub checksum: String,
+    pub metadata: HashMap<String, MetadataValue>,
+    #[serde(default)]
+    pub created_at: Option<u64>,
+    #[serde(default)]
+    pub modified_at: Option<u64>,
+}
+
+/// Memory usage summary, see `VirtualFileSystem::storage_stats`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StorageStats {
+    pub file_count: usize,
+    pub blob_count: usize,
+    /// Sum of the sizes of every file record.
+    pub logical_bytes: usize,
+    /// Bytes actually held in memory after deduplication and eviction.
+    pub stored_bytes: usize,
+    /// Blobs whose bytes were evicted to stay under the memory budget.
+    pub evicted_blob_count: usize,
+    pub memory_budget: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum EntryKind {
+    File,
+    Directory,
+}
+
+/// A single entry returned by directory listings.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub path: String,
+    pub kind: EntryKind,
+    pub file_id: Option<String>,
+    pub size: usize,
+}
+
 pub struct VirtualFileSystem {
-    files: HashMap<String, VirtualFile>,
+    pub(crate) files: HashMap<String, VirtualFile>,
+    // normalized path -> file id
+    pub(crate) paths: HashMap<String, String>,
+    // every directory that exists, always including the root
+    pub(crate) directories: BTreeSet<String>,
+    pub(crate) blobs: BlobStore,
+    pub(crate) max_versions: usize,
+    pub(crate) uploads: HashMap<String, PendingUpload>,
+    pub(crate) type_detection: TypeDetectionMode,
+    pub(crate) events: EventBus,
+    pub(crate) transaction: Option<Checkpoint>,
+    pub(crate) metadata_schemas: HashMap<String, MetadataSchema>,
+    pub(crate) cascade_rules: HashMap<LinkKind, CascadeRule>,
+    pub(crate) clock: Box<dyn Clock>,
+    pub(crate) storage: Storage,
+    pub(crate) compression: CompressionPolicy,
+    pub(crate) encryption: Encryption,
+    // trashed records by id, out of `files` and `paths`
+    pub(crate) trash: HashMap<String, VirtualFile>,
+    pub(crate) trash_retention: Option<u64>,
+}
+
+impl Default for VirtualFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VirtualFileSystem {
     pub fn new() -> Self {
+        let mut directories = BTreeSet::new();
+        directories.insert(path::ROOT.to_string());
+
         VirtualFileSystem {
             files: HashMap::new(),
+            paths: HashMap::new(),
+            directories,
+            blobs: BlobStore::new(),
+            max_versions: DEFAULT_MAX_VERSIONS,
+            uploads: HashMap::new(),
+            type_detection: TypeDetectionMode::default(),
+            events: EventBus::default(),
+            transaction: None,
+            metadata_schemas: HashMap::new(),
+            cascade_rules: HashMap::new(),
+            clock: Box::new(SystemClock),
+            storage: Storage::default(),
+            compression: CompressionPolicy::default(),
+            encryption: Encryption::default(),
+            trash: HashMap::new(),
+            trash_retention: None,
         }
     }
 
-    pub fn create_file(&mut self, name: String, file_type: SupportedFileType, content: Vec<u8>) -> Result<String, String> {
-        let id = Uuid::new_v4().to_string();
-        let file = VirtualFile::new(id.clone(), name, file_type, content);
-        self.files.insert(id.clone(), file);
-        Ok(id)
+    /// Creates a file in the root directory. If the name is already taken
+    /// a numbered suffix is added, e.g. "pokemon (1).csv".
+    pub fn create_file(&mut self, name: String, file_type: SupportedFileType, content: Vec<u8>) -> VfsResult<String> {
+        path::validate_name(&name)?;
+        let name = self.unique_child_name(path::ROOT, &name);
+        self.create_file_at(&path::join(path::ROOT, &name), file_type, content)
     }
 
-    pub fn read_file(&self, id: &str) -> Result<&VirtualFile, String> {
-        self.files.get(id)
-            .ok_or_else(|| "File not found".to_string())
-    }
-
-    //TODO: there is a cloned content in here, does it not make sense to use Rc<RefCell<>> here?
-    pub fn update_file(&mut self, id: &str, new_content: Option<Vec<u8>>) -> Result<(), String> {
-        self.files.get_mut(id)
-            .ok_or_else(|| "File not found".to_string())
-            .map(|file| {
-                if let Some(content) = new_content {
-                    let content_clone = content.clone();
-                    let size = content.len();
-                    file.content = Some(content);
-                    file.size = size;
-                    file.checksum = VirtualFile::calculate_checksum(&content_clone);
-                }
-            })
+    /// Creates a file at an explicit path, creating any missing parent
+    /// directories along the way.
+    pub fn create_file_at(&mut self, file_path: &str, file_type: SupportedFileType, content: Vec<u8>) -> VfsResult<String> {
+        self.create_file_with_provenance(file_path, file_type, content, Provenance::Upload)
+    }
+
+    /// Looks up a file record and records the access.
+    pub fn read_file(&mut self, id: &str) -> VfsResult<&VirtualFile> {
+        self.file(id)?;
+        self.touch_accessed(id);
+        self.file(id)
+    }
+
+    pub fn read_file_at(&mut self, file_path: &str) -> VfsResult<&VirtualFile> {
+        let id = self.resolve_path(file_path)?;
+        self.read_file(&id)
+    }
+
+    /// Looks up the id of the file stored at `file_path`.
+    pub fn resolve_path(&self, file_path: &str) -> VfsResult<String> {
+        let file_path = path::normalize(file_path)?;
+        self.paths.get(&file_path)
+            .cloned()
+            .ok_or(VfsError::PathNotFound { path: file_path })
+    }
+
+    pub fn exists(&self, entry_path: &str) -> bool {
+        match path::normalize(entry_path) {
+            Ok(entry_path) => self.paths.contains_key(&entry_path) || self.directories.contains(&entry_path),
+            Err(_) => false,
+        }
+    }
+
+    pub fn is_dir(&self, entry_path: &str) -> bool {
+        path::normalize(entry_path)
+            .map(|entry_path| self.directories.contains(&entry_path))
+            .unwrap_or(false)
+    }
+
+    /// Creates a directory and any missing parents. Succeeds if the
+    /// directory already exists.
+    pub fn mkdir(&mut self, dir_path: &str) -> VfsResult<()> {
+        let dir_path = path::normalize(dir_path)?;
+        let mut to_create = path::ancestors(&dir_path);
+        to_create.push(dir_path);
+
+        for dir in &to_create {
+            if self.paths.contains_key(dir) {
+                return Err(VfsError::NotADirectory { path: dir.clone() });
+            }
+        }
+
+        self.directories.extend(to_create);
+        self.flush()
+    }
+
+    /// Lists the direct children of a directory, directories first.
+    pub fn list_dir(&self, dir_path: &str) -> VfsResult<Vec<DirEntry>> {
+        self.collect_entries(dir_path, false)
+    }
+
+    /// Lists every file and directory below a directory.
+    pub fn list_dir_recursive(&self, dir_path: &str) -> VfsResult<Vec<DirEntry>> {
+        self.collect_entries(dir_path, true)
     }
 
-    pub fn delete_file(&mut self, id: &str) -> Result<(), String> {
-        self.files.remove(id)
-            .map(|_| ())
-            .ok_or_else(|| "File not found".to_string())
+    /// Moves a file or a whole directory tree to a new path. Missing parent
+    /// directories of the destination are created.
+    pub fn move_path(&mut self, from: &str, to: &str) -> VfsResult<()> {
+        let from = path::normalize(from)?;
+        let to = path::normalize(to)?;
+
+        if from == to {
+            return Ok(());
+        }
+        if from == path::ROOT {
+            return Err(VfsError::InvalidOperation { reason: "Cannot move the root directory".to_string() });
+        }
+        if self.exists(&to) {
+            return Err(VfsError::AlreadyExists { path: to });
+        }
+        if path::is_descendant(&to, &from) {
+            return Err(VfsError::InvalidOperation { reason: format!("Cannot move {} into itself", from) });
+        }
+
+        if let Some(id) = self.paths.get(&from).cloned() {
+            self.mkdir(path::parent(&to).unwrap_or(path::ROOT))?;
+            self.relocate_file(&id, &from, to);
+            return self.flush();
+        }
+
+        if !self.directories.contains(&from) {
+            return Err(VfsError::PathNotFound { path: from });
+        }
+        self.mkdir(path::parent(&to).unwrap_or(path::ROOT))?;
+
+        let moved_dirs: Vec<String> = self.directories.iter()
+            .filter(|dir| **dir == from || path::is_descendant(dir, &from))
+            .cloned()
+            .collect();
+        for dir in moved_dirs {
+            self.directories.remove(&dir);
+            self.directories.insert(format!("{}{}", to, &dir[from.len()..]));
+        }
+
+        let moved_files: Vec<(String, String)> = self.paths.iter()
+            .filter(|(file_path, _)| path::is_descendant(file_path, &from))
+            .map(|(file_path, id)| (file_path.clone(), id.clone()))
+            .collect();
+        for (file_path, id) in moved_files {
+            let new_path = format!("{}{}", to, &file_path[from.len()..]);
+            self.relocate_file(&id, &file_path, new_path);
+        }
+        self.flush()
+    }
+
+    /// Renames a file or directory in place.
+    pub fn rename(&mut self, entry_path: &str, new_name: &str) -> VfsResult<()> {
+        path::validate_name(new_name)?;
+        let entry_path = path::normalize(entry_path)?;
+        let parent = path::parent(&entry_path)
+            .ok_or_else(|| VfsError::InvalidOperation { reason: "Cannot rename the root directory".to_string() })?;
+        let destination = path::join(parent, new_name);
+        self.move_path(&entry_path, &destination)
+    }
+
+    /// Removes a directory. Without `recursive` the directory must be empty,
+    /// with it every file and directory below it is deleted as well.
+    pub fn remove_dir(&mut self, dir_path: &str, recursive: bool) -> VfsResult<()> {
+        let dir_path = path::normalize(dir_path)?;
+
+        if dir_path == path::ROOT {
+            return Err(VfsError::InvalidOperation { reason: "Cannot remove the root directory".to_string() });
+        }
+        self.ensure_directory(&dir_path)?;
+
+        let child_files: Vec<String> = self.paths.iter()
+            .filter(|(file_path, _)| path::is_descendant(file_path, &dir_path))
+            .map(|(_, id)| id.clone())
+            .collect();
+        let child_dirs: Vec<String> = self.directories.iter()
+            .filter(|dir| path::is_descendant(dir, &dir_path))
+            .cloned()
+            .collect();
+
+        if !recursive && (!child_files.is_empty() || !child_dirs.is_empty()) {
+            return Err(VfsError::DirectoryNotEmpty { path: dir_path });
+        }
+
+        self.delete_files(&child_files)?;
+        for dir in child_dirs {
+            self.directories.remove(&dir);
+        }
+        self.directories.remove(&dir_path);
+        self.flush()
+    }
+
+    pub fn delete_file_at(&mut self, file_path: &str) -> VfsResult<()> {
+        let id = self.resolve_path(file_path)?;
+        self.delete_file(&id)
+    }
+
+    /// Replaces the content of a file. The previous content is kept as an
+    /// older version, see `update_file_with_message`.
+    pub fn update_file(&mut self, id: &str, new_content: Option<Vec<u8>>) -> VfsResult<()> {
+        match new_content {
+            Some(content) => self.update_file_with_message(id, content, None).map(|_| ()),
+            None => self.file(id).map(|_| ()),
+        }
+    }
+
+    /// Deletes a file for good, applying the cascade rules of the links
+    /// pointing at it (see `core::graph`). `trash_file` deletes it in a way
+    /// that can be undone.
+    pub fn delete_file(&mut self, id: &str) -> VfsResult<()> {
+        self.delete_files(&[id.to_string()])
     }
 
     pub fn list_files(&self) -> Vec<&VirtualFile> {
         self.files.values().collect()
     }
 
-    pub fn get_file_content(&self, id: &str) -> Result<Vec<u8>, String> {
-        self.files.get(id)
-            .and_then(|file| file.content.as_ref())
+    /// Expands a ZIP archive into the root directory and returns the ids of
+    /// the created files. File types are inferred from the entry names. If
+    /// the archive carries a manifest written by `export_zip`, file types
+    /// and metadata are restored from it and checksums are verified.
+    ///
+    /// The archive is fully read and validated before anything is created,
+    /// and the files are created in a transaction, so a bad archive or a
+    /// path conflict leaves the file system untouched.
+    pub fn import_zip(&mut self, archive: &[u8]) -> VfsResult<Vec<String>> {
+        let mut archive = ZipArchive::new(Cursor::new(archive))?;
+        let mut directories = Vec::new();
+        let mut entries = Vec::new();
+        let mut manifest: HashMap<String, ZipManifestEntry> = HashMap::new();
+
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index)?;
+            let entry_path = path::normalize(entry.name())?;
+
+            if entry.is_dir() {
+                directories.push(entry_path);
+                continue;
+            }
+
+            // the size in the header is not to be trusted
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)
+                .map_err(|e| VfsError::InvalidArchive { reason: e.to_string() })?;
+
+            if entry_path == path::join(path::ROOT, ZIP_MANIFEST_NAME) {
+                let listed: Vec<ZipManifestEntry> = serde_json::from_slice(&content)
+                    .map_err(|e| VfsError::InvalidArchive { reason: format!("malformed manifest: {}", e) })?;
+                manifest = listed.into_iter().map(|entry| (entry.path.clone(), entry)).collect();
+            } else {
+                entries.push((entry_path, content));
+            }
+        }
+
+        self.check_archive_paths(&directories, &entries)?;
+        for (entry_path, content) in &entries {
+            if let Some(listed) = manifest.get(entry_path) {
+                let actual = calculate_checksum(content);
+                if actual != listed.checksum {
+                    return Err(VfsError::ChecksumMismatch { expected: listed.checksum.clone(), actual });
+                }
+            }
+        }
+
+        let create = move |vfs: &mut Self| {
+            for dir in directories {
+                vfs.mkdir(&dir)?;
+            }
+
+            let mut ids = Vec::with_capacity(entries.len());
+            for (entry_path, content) in entries {
+                let listed = manifest.remove(&entry_path);
+                let file_type = listed.as_ref()
+                    .map(|listed| listed.file_type.clone())
+                    .unwrap_or_else(|| SupportedFileType::from_extension(&entry_path));
+
+                let provenance = Provenance::ArchiveImport { archive: None };
+                let id = vfs.create_file_with_provenance(&entry_path, file_type, content, provenance)?;
+                if let (Some(listed), Some(file)) = (listed, vfs.files.get_mut(&id)) {
+                    file.metadata = listed.metadata;
+                    if let Some(created_at) = listed.created_at {
+                        file.created_at = created_at;
+                    }
+                    if let Some(modified_at) = listed.modified_at {
+                        file.modified_at = modified_at;
+                    }
+                }
+                ids.push(id);
+            }
+            Ok(ids)
+        };
+        // an open transaction is the caller's to roll back
+        if self.in_transaction() {
+            create(self)
+        } else {
+            self.transaction(create)
+        }
+    }
+
+    /// Writes every file into a ZIP archive, keeping the directory layout,
+    /// plus a JSON manifest (`ZIP_MANIFEST_NAME`) with ids, names, types,
+    /// metadata and checksums.
+    pub fn export_zip(&mut self) -> VfsResult<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        for dir in self.directories.iter().filter(|dir| *dir != path::ROOT) {
+            writer.add_directory(format!("{}/", &dir[1..]), options)?;
+        }
+
+        let mut files: Vec<VirtualFile> = self.files.values().cloned().collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut manifest = Vec::with_capacity(files.len());
+        for file in files {
+            let content = self.load_content(&file.id, &file.checksum)?;
+
+            writer.start_file(&file.path[1..], options)?;
+            writer.write_all(&content)
+                .map_err(|e| VfsError::InvalidArchive { reason: e.to_string() })?;
+
+            manifest.push(ZipManifestEntry {
+                id: file.id,
+                path: file.path,
+                name: file.name,
+                file_type: file.file_type,
+                size: file.size,
+                // archives hold the content in the clear, and so its checksum
+                checksum: if file.encrypted { calculate_checksum(&content) } else { file.checksum },
+                metadata: file.metadata,
+                created_at: Some(file.created_at),
+                modified_at: Some(file.modified_at),
+            });
+        }
+
+        let manifest = serde_json::to_vec_pretty(&manifest)
+            .expect("archive manifest is always serializable");
+        writer.start_file(ZIP_MANIFEST_NAME, options)?;
+        writer.write_all(&manifest)
+            .map_err(|e| VfsError::InvalidArchive { reason: e.to_string() })?;
+
+        Ok(writer.finish()?.into_inner())
+    }
+
+    pub fn get_file_content(&mut self, id: &str) -> VfsResult<Vec<u8>> {
+        let checksum = self.file(id)?.checksum.clone();
+        self.touch_accessed(id);
+        self.load_content(id, &checksum).map(|content| content.into_owned())
+    }
+
+    pub fn storage_stats(&self) -> StorageStats {
+        StorageStats {
+            file_count: self.files.len(),
+            blob_count: self.blobs.len(),
+            logical_bytes: self.files.values().map(|file| file.size).sum(),
+            stored_bytes: self.blobs.stored_bytes(),
+            evicted_blob_count: self.blobs.evicted_count(),
+            memory_budget: self.blobs.budget(),
+        }
+    }
+
+    /// Sets one metadata key, checking the value against the key's schema.
+    pub fn set_file_metadata(&mut self, id: &str, key: String, value: impl Into<MetadataValue>) -> VfsResult<()> {
+        let value = value.into();
+        self.validate_metadata(&key, &value)?;
+
+        let file = self.files.get_mut(id)
+            .ok_or_else(|| VfsError::not_found(id))?;
+        file.metadata.insert(key.clone(), value);
+
+        let path = file.path.clone();
+        self.emit(FileEvent::MetadataChanged { id: id.to_string(), path, key });
+        self.flush()
+    }
+
+    pub fn get_file_metadata(&self, id: &str, key: &str) -> VfsResult<MetadataValue> {
+        self.file(id)?
+            .metadata.get(key)
             .cloned()
-            .ok_or_else(|| "File content not found".to_string())
+            .ok_or_else(|| VfsError::MetadataNotFound { id: id.to_string(), key: key.to_string() })
     }
 
-    pub fn set_file_metadata(&mut self, id: &str, key: String, value: String) -> Result<(), String> {
-        self.files.get_mut(id)
-            .ok_or_else(|| "File not found".to_string())
-            .map(|file| {
-                file.metadata.insert(key, value);
-            })
+    fn delete_files(&mut self, ids: &[String]) -> VfsResult<()> {
+        let doomed = self.plan_delete(ids)?;
+        for id in &doomed {
+            let Some(file) = self.files.remove(id) else { continue };
+            self.paths.remove(&file.path);
+            for version in &file.versions {
+                self.blobs.release(version.blob_key());
+            }
+            self.emit(FileEvent::Deleted { id: file.id, path: file.path });
+        }
+
+        self.remove_dangling_links(&doomed.into_iter().collect::<HashSet<String>>());
+        self.flush()
     }
 
-    pub fn get_file_metadata(&self, id: &str, key: &str) -> Result<String, String> {
+    /// Looks up a file record without counting it as an access.
+    pub(crate) fn file(&self, id: &str) -> VfsResult<&VirtualFile> {
         self.files.get(id)
-            .and_then(|file| file.metadata.get(key))
-            .cloned()
-            .ok_or_else(|| "Metadata not found".to_string())
+            .ok_or_else(|| VfsError::not_found(id))
+    }
+
+    fn relocate_file(&mut self, id: &str, from: &str, to: String) {
+        self.paths.remove(from);
+        if let Some(file) = self.files.get_mut(id) {
+            file.name = path::file_name(&to).to_string();
+            file.path = to.clone();
+        }
+        self.paths.insert(to.clone(), id.to_string());
+        self.emit(FileEvent::Moved { id: id.to_string(), from: from.to_string(), to });
+    }
+
+    /// Normalizes the path of a file about to be created and makes sure
+    /// nothing is in the way.
+    pub(crate) fn check_new_file_path(&self, file_path: &str) -> VfsResult<String> {
+        let file_path = path::normalize(file_path)?;
+        if self.exists(&file_path) {
+            return Err(VfsError::AlreadyExists { path: file_path });
+        }
+        for dir in path::ancestors(&file_path) {
+            if self.paths.contains_key(&dir) {
+                return Err(VfsError::NotADirectory { path: dir });
+            }
+        }
+        Ok(file_path)
+    }
+
+    /// Stores a new file record at a path already validated by
+    /// `check_new_file_path`, creating missing parent directories. The
+    /// declared type is checked against the content according to the
+    /// current `TypeDetectionMode`.
+    pub(crate) fn insert_file(&mut self, file_path: String, file_type: SupportedFileType, content: Vec<u8>, checksum: String, provenance: Provenance) -> VfsResult<String> {
+        self.mkdir(path::parent(&file_path).unwrap_or(path::ROOT))?;
+
+        let id = Uuid::new_v4().to_string();
+        let name = path::file_name(&file_path).to_string();
+        let size = content.len();
+
+        let (file_type, type_mismatch) = match self.type_detection {
+            TypeDetectionMode::Trust => (file_type, None),
+            mode => {
+                let detection = detect_file_type(&content, &name);
+                match find_mismatch(&file_type, &detection) {
+                    Some(_) if mode == TypeDetectionMode::Auto => (detection.file_type, None),
+                    mismatch => (file_type, mismatch),
+                }
+            }
+        };
+        let now = self.now();
+        let encrypted = self.encrypt_new_files();
+        let mut file = VirtualFile::new(id.clone(), name, file_type, size, checksum);
+        self.store_content(&mut file.versions[0], content, &file.file_type, encrypted)?;
+
+        file.path = file_path.clone();
+        file.checksum = file.versions[0].checksum.clone();
+        file.compression = file.versions[0].compression;
+        file.stored_size = file.versions[0].stored_size;
+        file.encrypted = encrypted;
+        file.type_mismatch = type_mismatch;
+        file.provenance = provenance;
+        file.created_at = now;
+        file.modified_at = now;
+        file.accessed_at = now;
+        file.versions[0].timestamp = now;
+
+        self.paths.insert(file_path.clone(), id.clone());
+        self.files.insert(id.clone(), file);
+        self.emit(FileEvent::Created { id: id.clone(), path: file_path });
+        self.flush()?;
+        Ok(id)
+    }
+
+    /// Checks that every file and directory of an archive can be created:
+    /// no file twice, no file where the archive or the file system has a
+    /// directory, and no directory, or ancestor of one, where either has
+    /// a file.
+    fn check_archive_paths(&self, directories: &[String], entries: &[(String, Vec<u8>)]) -> VfsResult<()> {
+        let mut planned_dirs: BTreeSet<&str> = BTreeSet::new();
+        let mut ancestors = Vec::new();
+        for dir in directories {
+            planned_dirs.insert(dir);
+            ancestors.extend(path::ancestors(dir));
+        }
+        for (entry_path, _) in entries {
+            ancestors.extend(path::ancestors(entry_path));
+        }
+        planned_dirs.extend(ancestors.iter().map(String::as_str));
+
+        if let Some(dir) = planned_dirs.iter().find(|dir| self.paths.contains_key(**dir)) {
+            return Err(VfsError::NotADirectory { path: dir.to_string() });
+        }
+        let mut planned_files = HashSet::new();
+        for (entry_path, _) in entries {
+            if self.exists(entry_path) || !planned_files.insert(entry_path.as_str()) {
+                return Err(VfsError::AlreadyExists { path: entry_path.clone() });
+            }
+            if planned_dirs.contains(entry_path.as_str()) {
+                return Err(VfsError::NotADirectory { path: entry_path.clone() });
+            }
+        }
+        Ok(())
+    }
+
+    fn ensure_directory(&self, dir_path: &str) -> VfsResult<()> {
+        if self.directories.contains(dir_path) {
+            Ok(())
+        } else if self.paths.contains_key(dir_path) {
+            Err(VfsError::NotADirectory { path: dir_path.to_string() })
+        } else {
+            Err(VfsError::PathNotFound { path: dir_path.to_string() })
+        }
+    }
+
+    pub(crate) fn unique_child_name(&self, dir: &str, name: &str) -> String {
+        if !self.exists(&path::join(dir, name)) {
+            return name.to_string();
+        }
+
+        let (stem, extension) = match name.rfind('.') {
+            Some(index) if index > 0 => (&name[..index], &name[index..]),
+            _ => (name, ""),
+        };
+
+        (1..)
+            .map(|n| format!("{} ({}){}", stem, n, extension))
+            .find(|candidate| !self.exists(&path::join(dir, candidate)))
+            .expect("an unused name always exists")
+    }
+
+    fn collect_entries(&self, dir_path: &str, recursive: bool) -> VfsResult<Vec<DirEntry>> {
+        let dir_path = path::normalize(dir_path)?;
+        self.ensure_directory(&dir_path)?;
+
+        let in_scope = |entry_path: &str| {
+            path::is_descendant(entry_path, &dir_path)
+                && (recursive || path::parent(entry_path) == Some(dir_path.as_str()))
+        };
+
+        let mut entries: Vec<DirEntry> = self.directories.iter()
+            .filter(|dir| in_scope(dir))
+            .map(|dir| DirEntry {
+                name: path::file_name(dir).to_string(),
+                path: dir.clone(),
+                kind: EntryKind::Directory,
+                file_id: None,
+                size: 0,
+            })
+            .collect();
+
+        let mut files: Vec<DirEntry> = self.paths.iter()
+            .filter(|(file_path, _)| in_scope(file_path))
+            .filter_map(|(file_path, id)| self.files.get(id).map(|file| DirEntry {
+                name: file.name.clone(),
+                path: file_path.clone(),
+                kind: EntryKind::File,
+                file_id: Some(id.clone()),
+                size: file.size,
+            }))
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        entries.extend(files);
+        Ok(entries)
+    }
+}
+
+/// Checks directories and records that come from outside, a snapshot or a
+/// storage backend, before they are used: every path absolute and
+/// normalized, every file named after its path, and every directory and
+/// file in the tree inside a directory that exists. Trashed files only
+/// need a sound path, `restore` recreates their directories. `directories`
+/// must include the root. Returns what is wrong.
+pub(crate) fn check_tree<'a>(directories: &BTreeSet<String>, files: impl IntoIterator<Item = &'a VirtualFile>) -> Result<(), String> {
+    for dir in directories {
+        check_normalized(dir)?;
+        if let Some(parent) = path::parent(dir).filter(|parent| !directories.contains(*parent)) {
+            return Err(format!("directory {} is in {}, which is not a directory", dir, parent));
+        }
+    }
+
+    for file in files {
+        check_normalized(&file.path)?;
+        if file.path == path::ROOT || path::file_name(&file.path) != file.name {
+            return Err(format!("file {} is named {:?} but its path is {}", file.id, file.name, file.path));
+        }
+        if file.deleted_at.is_some() {
+            continue;
+        }
+        if directories.contains(&file.path) {
+            return Err(format!("{} is both a file and a directory", file.path));
+        }
+        if let Some(parent) = path::parent(&file.path).filter(|parent| !directories.contains(*parent)) {
+            return Err(format!("file {} is in {}, which is not a directory", file.path, parent));
+        }
+    }
+    Ok(())
+}
+
+fn check_normalized(entry_path: &str) -> Result<(), String> {
+    match path::normalize(entry_path) {
+        Ok(normalized) if normalized == entry_path => Ok(()),
+        _ => Err(format!("{:?} is not a normalized absolute path", entry_path)),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::storage::{MemoryBackend, StorageBackend};
 
-    #[test]
-    fn test_create_and_read_file() {
-        let mut vfs = VirtualFileSystem::new();
-        let id = vfs.create_file("test.jpg".to_string(), SupportedFileType::Image, vec![1, 2, 3]).unwrap();
-        let file = vfs.read_file(&id).unwrap();
-        assert_eq!(file.name, "test.jpg");
-        assert_eq!(file.size, 3);
+    /// Runs each test once per storage backend: none, `MemoryBackend` and,
+    /// natively, `DirectoryBackend`. Afterwards the backend is opened again
+    /// and has to hold exactly what the file system ended up with.
+    macro_rules! backend_tests {
+        ($(fn $name:ident($vfs:ident) $body:block)*) => {
+            mod no_backend {
+                use super::*;
+                $(#[test] fn $name() { let mut $vfs = VirtualFileSystem::new(); $body })*
+            }
+
+            mod memory_backend {
+                use super::*;
+                $(#[test] fn $name() {
+                    let backend = MemoryBackend::new();
+                    let mut $vfs = VirtualFileSystem::open(Box::new(backend.clone())).unwrap();
+                    $body
+                    assert_reopens(&$vfs, Box::new(backend));
+                })*
+            }
+
+            #[cfg(not(target_arch = "wasm32"))]
+            mod directory_backend {
+                use super::*;
+                use crate::core::storage::DirectoryBackend;
+                $(#[test] fn $name() {
+                    let dir = std::env::temp_dir().join(format!("vfs-backend-{}", Uuid::new_v4()));
+                    let mut $vfs = VirtualFileSystem::open(Box::new(DirectoryBackend::new(&dir).unwrap())).unwrap();
+                    $body
+                    assert_reopens(&$vfs, Box::new(DirectoryBackend::new(&dir).unwrap()));
+                    std::fs::remove_dir_all(dir).unwrap();
+                })*
+            }
+        };
     }
 
-    #[test]
-    fn test_update_file() {
-        let mut vfs = VirtualFileSystem::new();
-        let id = vfs.create_file("test.jpg".to_string(), SupportedFileType::Image, vec![1, 2, 3]).unwrap();
-        vfs.update_file(&id, Some(vec![4, 5, 6, 7])).unwrap();
-        let content = vfs.get_file_content(&id).unwrap();
-        assert_eq!(content, vec![4, 5, 6, 7]);
+    fn assert_reopens(vfs: &VirtualFileSystem, backend: Box<dyn StorageBackend>) {
+        // access times are only saved with the next write of their record
+        let records = |vfs: &VirtualFileSystem| -> HashMap<String, serde_json::Value> {
+            vfs.files.values()
+                .map(|file| {
+                    let mut record = serde_json::to_value(file).unwrap();
+                    record.as_object_mut().unwrap().remove("accessed_at");
+                    (file.id.clone(), record)
+                })
+                .collect()
+        };
+
+        let mut reopened = VirtualFileSystem::open(backend).unwrap();
+        assert_eq!(records(&reopened), records(vfs));
+        assert_eq!(reopened.directories, vfs.directories);
+        for (id, file) in &vfs.files {
+            assert_eq!(reopened.get_file_content(id).unwrap(), vfs.blobs.get(&file.checksum).unwrap().as_ref());
+        }
     }
 
-    #[test]
-    fn test_delete_file() {
-        let mut vfs = VirtualFileSystem::new();
-        let id = vfs.create_file("test.jpg".to_string(), SupportedFileType::Image, vec![1, 2, 3]).unwrap();
-        vfs.delete_file(&id).unwrap();
-        assert!(vfs.read_file(&id).is_err());
+    fn build_zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
     }
 
     #[test]
-    fn test_metadata() {
-        let mut vfs = VirtualFileSystem::new();
-        let id = vfs.create_file("test.jpg".to_string(), SupportedFileType::Image, vec![1, 2, 3]).unwrap();
-        vfs.set_file_metadata(&id, "position".to_string(), "100,100".to_string()).unwrap();
-        let position = vfs.get_file_metadata(&id, "position").unwrap();
-        assert_eq!(position, "100,100");
-    }
-}
\ No newline at end of file
+    fn test_file_type_from_extension() {
+        assert!(matches!(SupportedFileType::from_extension("/a/pokemon.CSV"), SupportedFileType::CSV));
+        assert!(matches!(SupportedFileType::from_extension("cat.gif"), SupportedFileType::Gif));
+        assert!(matches!(SupportedFileType::from_extension("video.mov"), SupportedFileType::Video));
+        assert!(matches!(SupportedFileType::from_extension("photo.jpeg"), SupportedFileType::Image));
+        assert!(matches!(SupportedFileType::from_extension("notes.md"), SupportedFileType::Text));
+        assert!(matches!(SupportedFileType::from_extension("data.parquet"), SupportedFileType::Other(ext) if ext == "parquet"));
+    }
+
+    backend_tests! {
+        fn test_create_and_read_file(vfs) {
+            let id = vfs.create_file("test.jpg".to_string(), SupportedFileType::Image, vec![1, 2, 3]).unwrap();
+            let file = vfs.read_file(&id).unwrap();
+            assert_eq!(file.name, "test.jpg");
+            assert_eq!(file.size, 3);
+        }
+
+        fn test_update_file(vfs) {
+            let id = vfs.create_file("test.jpg".to_string(), SupportedFileType::Image, vec![1, 2, 3]).unwrap();
+            vfs.update_file(&id, Some(vec![4, 5, 6, 7])).unwrap();
+            let content = vfs.get_file_content(&id).unwrap();
+            assert_eq!(content, vec![4, 5, 6, 7]);
+        }
+
+        fn test_delete_file(vfs) {
+            let id = vfs.create_file("test.jpg".to_string(), SupportedFileType::Image, vec![1, 2, 3]).unwrap();
+            vfs.delete_file(&id).unwrap();
+            assert!(vfs.read_file(&id).is_err());
+        }
+
+        fn test_metadata(vfs) {
+            let id = vfs.create_file("test.jpg".to_string(), SupportedFileType::Image, vec![1, 2, 3]).unwrap();
+            vfs.set_file_metadata(&id, "position".to_string(), "100,100".to_string()).unwrap();
+            let position = vfs.get_file_metadata(&id, "position").unwrap();
+            assert_eq!(position, "100,100");
+        }
+
+        fn test_identical_uploads_share_a_blob(vfs) {
+            let a = vfs.create_file("a.csv".to_string(), SupportedFileType::CSV, vec![1, 2, 3]).unwrap();
+            let b = vfs.create_file("b.csv".to_string(), SupportedFileType::CSV, vec![1, 2, 3]).unwrap();
+
+            let stats = vfs.storage_stats();
+            assert_eq!(stats.file_count, 2);
+            assert_eq!(stats.blob_count, 1);
+            assert_eq!(stats.logical_bytes, 6);
+            assert_eq!(stats.stored_bytes, 3);
+
+            vfs.update_file(&a, Some(vec![9])).unwrap();
+            assert_eq!(vfs.storage_stats().blob_count, 2);
+            assert_eq!(vfs.get_file_content(&b).unwrap(), vec![1, 2, 3]);
+
+            // a's first version still points at the shared blob
+            vfs.delete_file(&b).unwrap();
+            assert_eq!(vfs.storage_stats().blob_count, 2);
+            vfs.update_file(&a, Some(vec![9])).unwrap();
+            assert_eq!(vfs.get_file_content(&a).unwrap(), vec![9]);
+
+            vfs.delete_file(&a).unwrap();
+            assert_eq!(vfs.storage_stats().stored_bytes, 0);
+        }
+
+        fn test_import_zip_infers_types(vfs) {
+            let archive = build_zip(&[
+                ("datasets/pokemon.csv", b"a,b\n1,2"),
+                ("images/logo.png", &[0x89, b'P', b'N', b'G']),
+            ]);
+
+            let ids = vfs.import_zip(&archive).unwrap();
+            assert_eq!(ids.len(), 2);
+
+            let csv = vfs.read_file_at("/datasets/pokemon.csv").unwrap();
+            assert!(matches!(csv.file_type, SupportedFileType::CSV));
+            assert!(matches!(vfs.read_file_at("/images/logo.png").unwrap().file_type, SupportedFileType::Image));
+
+            // a second import of the same archive conflicts and changes nothing
+            assert!(matches!(vfs.import_zip(&archive), Err(VfsError::AlreadyExists { .. })));
+            assert_eq!(vfs.list_files().len(), 2);
+            assert!(vfs.import_zip(b"not a zip").is_err());
+        }
+
+        fn test_import_zip_conflicts_change_nothing(vfs) {
+            vfs.create_file_at("/notes", SupportedFileType::Text, b"n".to_vec()).unwrap();
+            let before = vfs.list_dir_recursive("/").unwrap().len();
+
+            let conflicts = [
+                // a file, then a file below it
+                build_zip(&[("a", b"1"), ("a/b", b"2")]),
+                build_zip(&[("a/b", b"2"), ("a", b"1")]),
+                build_zip(&[("a", b"1"), ("./a", b"2")]),
+                // below an existing file
+                build_zip(&[("x.csv", b"1"), ("notes/old.txt", b"2")]),
+            ];
+            for archive in &conflicts {
+                assert!(vfs.import_zip(archive).is_err());
+                assert_eq!(vfs.list_dir_recursive("/").unwrap().len(), before);
+            }
+
+            // inside a transaction the caller's rollback undoes it
+            vfs.begin_transaction().unwrap();
+            vfs.import_zip(&build_zip(&[("c.csv", b"1")])).unwrap();
+            vfs.rollback_transaction().unwrap();
+            assert!(!vfs.exists("/c.csv"));
+        }
+
+        fn test_zip_round_trip_keeps_metadata(vfs) {
+            let id = vfs.create_file_at("/datasets/pokemon.csv", SupportedFileType::CSV, b"a,b\n1,2".to_vec()).unwrap();
+            vfs.set_file_metadata(&id, "position".to_string(), "100,100".to_string()).unwrap();
+            vfs.create_file_at("/notes", SupportedFileType::Other("txt".to_string()), b"hello".to_vec()).unwrap();
+            vfs.mkdir("/empty").unwrap();
+
+            let archive = vfs.export_zip().unwrap();
+
+            let mut restored = VirtualFileSystem::new();
+            restored.import_zip(&archive).unwrap();
+            assert!(restored.is_dir("/empty"));
+            assert!(!restored.exists(&format!("/{}", ZIP_MANIFEST_NAME)));
+
+            let imported = restored.read_file_at("/datasets/pokemon.csv").unwrap();
+            assert_eq!(imported.metadata.get("position").and_then(MetadataValue::as_str), Some("100,100"));
+            let imported_id = imported.id.clone();
+            assert!(matches!(restored.read_file_at("/notes").unwrap().file_type, SupportedFileType::Other(ref ext) if ext == "txt"));
+            let content = restored.get_file_content(&imported_id).unwrap();
+            assert_eq!(content, b"a,b\n1,2".to_vec());
+        }
+
+        fn test_create_file_at_creates_parents(vfs) {
+            let id = vfs.create_file_at("/datasets/raw/pokemon.csv", SupportedFileType::CSV, vec![1]).unwrap();
+            assert!(vfs.is_dir("/datasets"));
+            assert!(vfs.is_dir("/datasets/raw"));
+            assert_eq!(vfs.resolve_path("datasets/raw/pokemon.csv").unwrap(), id);
+            assert_eq!(vfs.read_file_at("/datasets/raw/pokemon.csv").unwrap().name, "pokemon.csv");
+            assert!(vfs.create_file_at("/datasets/raw/pokemon.csv", SupportedFileType::CSV, vec![2]).is_err());
+        }
+
+        fn test_create_file_picks_unique_name(vfs) {
+            vfs.create_file("pokemon.csv".to_string(), SupportedFileType::CSV, vec![1]).unwrap();
+            let id = vfs.create_file("pokemon.csv".to_string(), SupportedFileType::CSV, vec![1]).unwrap();
+            assert_eq!(vfs.read_file(&id).unwrap().path, "/pokemon (1).csv");
+        }
+
+        fn test_mkdir_rejects_file_in_the_way(vfs) {
+            vfs.create_file_at("/notes", SupportedFileType::Other("txt".to_string()), vec![]).unwrap();
+            assert!(vfs.mkdir("/notes/archive").is_err());
+            vfs.mkdir("/a/b").unwrap();
+            vfs.mkdir("/a/b").unwrap();
+        }
+
+        fn test_move_and_rename(vfs) {
+            let id = vfs.create_file_at("/raw/pokemon.csv", SupportedFileType::CSV, vec![1]).unwrap();
+            vfs.create_file_at("/raw/deep/starters.csv", SupportedFileType::CSV, vec![2]).unwrap();
+
+            vfs.rename("/raw/pokemon.csv", "all.csv").unwrap();
+            assert_eq!(vfs.read_file(&id).unwrap().path, "/raw/all.csv");
+
+            vfs.move_path("/raw", "/datasets/raw").unwrap();
+            assert!(!vfs.exists("/raw"));
+            assert_eq!(vfs.read_file(&id).unwrap().path, "/datasets/raw/all.csv");
+            assert!(vfs.read_file_at("/datasets/raw/deep/starters.csv").is_ok());
+            assert!(vfs.move_path("/datasets", "/datasets/raw/inner").is_err());
+        }
+
+        fn test_list_dir(vfs) {
+            vfs.create_file_at("/datasets/pokemon.csv", SupportedFileType::CSV, vec![1, 2]).unwrap();
+            vfs.create_file_at("/datasets/university/costs.csv", SupportedFileType::CSV, vec![3]).unwrap();
+
+            let entries = vfs.list_dir("/datasets").unwrap();
+            let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(names, vec!["university", "pokemon.csv"]);
+            assert_eq!(entries[1].size, 2);
+
+            let all = vfs.list_dir_recursive("/").unwrap();
+            assert_eq!(all.len(), 4);
+            assert!(vfs.list_dir("/missing").is_err());
+        }
+
+        fn test_remove_dir(vfs) {
+            let id = vfs.create_file_at("/datasets/university/costs.csv", SupportedFileType::CSV, vec![3]).unwrap();
+
+            assert!(vfs.remove_dir("/datasets", false).is_err());
+            vfs.remove_dir("/datasets", true).unwrap();
+            assert!(vfs.read_file(&id).is_err());
+            assert!(!vfs.exists("/datasets/university"));
+            assert!(vfs.list_files().is_empty());
+            assert!(vfs.remove_dir("/", true).is_err());
+        }
+    }
+}
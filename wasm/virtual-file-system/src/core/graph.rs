@@ -0,0 +1,387 @@
+// core/graph.rs
+//
+// Tags and typed links between files. A link is directed and stored on
+// the file it starts from (`cleaned.csv` --DerivedFrom--> `raw.csv`), so
+// links travel with the record through snapshots and transactions.
+// Incoming links are found by scanning, which is fine at the sizes the
+// canvas deals with.
+//
+// What happens to linking files when a link target is deleted is decided
+// by the `CascadeRule` of the link kind: drop the link (default), refuse
+// the delete, or delete the linking files too.
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use serde::{Serialize, Deserialize};
+
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::events::FileEvent;
+use crate::core::file_system::{VirtualFile, VirtualFileSystem};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub enum LinkKind {
+    /// The source was produced from the target, e.g. a cleaned dataset.
+    DerivedFrom,
+    /// The source is part of the target, e.g. a chart of a dataset.
+    BelongsTo,
+    References,
+    Custom(String),
+}
+
+/// An outgoing link as stored on a `VirtualFile`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileLink {
+    pub target: String,
+    pub kind: LinkKind,
+}
+
+/// A link with both ends, as returned by the graph queries.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LinkEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: LinkKind,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum LinkDirection {
+    /// Follow links from source to target, e.g. towards the raw data.
+    Outgoing,
+    /// Follow links backwards, e.g. towards everything derived from a file.
+    Incoming,
+}
+
+/// Applied to the files linking to a file that is being deleted.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum CascadeRule {
+    /// Remove the dangling link and keep the linking file.
+    #[default]
+    Unlink,
+    /// Refuse to delete a file while links of this kind point at it.
+    Restrict,
+    /// Delete the linking files as well.
+    Delete,
+}
+
+impl VirtualFileSystem {
+    /// Adds a tag. Returns false if the file already had it.
+    pub fn add_tag(&mut self, id: &str, tag: &str) -> VfsResult<bool> {
+        let file = self.files.get_mut(id)
+            .ok_or_else(|| VfsError::not_found(id))?;
+        let added = file.tags.insert(tag.to_string());
+        if added {
+            let path = file.path.clone();
+            self.emit(FileEvent::TagsChanged { id: id.to_string(), path });
+        }
+        self.flush()?;
+        Ok(added)
+    }
+
+    /// Removes a tag. Returns false if the file didn't have it.
+    pub fn remove_tag(&mut self, id: &str, tag: &str) -> VfsResult<bool> {
+        let file = self.files.get_mut(id)
+            .ok_or_else(|| VfsError::not_found(id))?;
+        let removed = file.tags.remove(tag);
+        if removed {
+            let path = file.path.clone();
+            self.emit(FileEvent::TagsChanged { id: id.to_string(), path });
+        }
+        self.flush()?;
+        Ok(removed)
+    }
+
+    pub fn tags(&self, id: &str) -> VfsResult<&BTreeSet<String>> {
+        Ok(&self.file(id)?.tags)
+    }
+
+    /// Files carrying `tag`, sorted by path.
+    pub fn files_with_tag(&self, tag: &str) -> Vec<&VirtualFile> {
+        let mut files: Vec<&VirtualFile> = self.files.values()
+            .filter(|file| file.tags.contains(tag))
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files
+    }
+
+    /// Links `from` to `to`. Adding a link that already exists is a no-op.
+    pub fn link(&mut self, from: &str, to: &str, kind: LinkKind) -> VfsResult<()> {
+        if from == to {
+            return Err(VfsError::InvalidOperation { reason: "A file cannot link to itself".to_string() });
+        }
+        self.file(to)?;
+        let file = self.files.get_mut(from)
+            .ok_or_else(|| VfsError::not_found(from))?;
+
+        let link = FileLink { target: to.to_string(), kind };
+        if !file.links.contains(&link) {
+            file.links.push(link);
+            let path = file.path.clone();
+            self.emit(FileEvent::LinksChanged { id: from.to_string(), path });
+        }
+        self.flush()
+    }
+
+    /// Removes a link. Returns false if there was no such link.
+    pub fn unlink(&mut self, from: &str, to: &str, kind: &LinkKind) -> VfsResult<bool> {
+        let file = self.files.get_mut(from)
+            .ok_or_else(|| VfsError::not_found(from))?;
+
+        let before = file.links.len();
+        file.links.retain(|link| !(link.target == to && link.kind == *kind));
+        let removed = file.links.len() != before;
+        if removed {
+            let path = file.path.clone();
+            self.emit(FileEvent::LinksChanged { id: from.to_string(), path });
+        }
+        self.flush()?;
+        Ok(removed)
+    }
+
+    /// Links from `id`, leaving out those to files in the trash.
+    pub fn outgoing_links(&self, id: &str) -> VfsResult<Vec<LinkEdge>> {
+        Ok(self.file(id)?.links.iter()
+            .filter(|link| self.files.contains_key(&link.target))
+            .map(|link| LinkEdge { from: id.to_string(), to: link.target.clone(), kind: link.kind.clone() })
+            .collect())
+    }
+
+    /// Links pointing at `id`, sorted by the path of the linking file.
+    pub fn incoming_links(&self, id: &str) -> VfsResult<Vec<LinkEdge>> {
+        self.file(id)?;
+        let mut sources: Vec<&VirtualFile> = self.files.values()
+            .filter(|file| file.links.iter().any(|link| link.target == id))
+            .collect();
+        sources.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(sources.into_iter()
+            .flat_map(|file| file.links.iter()
+                .filter(|link| link.target == id)
+                .map(|link| LinkEdge { from: file.id.clone(), to: id.to_string(), kind: link.kind.clone() }))
+            .collect())
+    }
+
+    /// Ids of the files directly linked to or from `id`, without duplicates.
+    pub fn neighbors(&self, id: &str, direction: LinkDirection) -> VfsResult<Vec<String>> {
+        let edges = match direction {
+            LinkDirection::Outgoing => self.outgoing_links(id)?,
+            LinkDirection::Incoming => self.incoming_links(id)?,
+        };
+
+        let mut seen = HashSet::new();
+        Ok(edges.into_iter()
+            .map(|edge| if direction == LinkDirection::Outgoing { edge.to } else { edge.from })
+            .filter(|neighbor| seen.insert(neighbor.clone()))
+            .collect())
+    }
+
+    /// Every file reachable from `id` by following links of `kind` (any
+    /// kind if `None`), nearest first. Cycles are followed only once.
+    pub fn lineage(&self, id: &str, kind: Option<&LinkKind>, direction: LinkDirection) -> VfsResult<Vec<String>> {
+        self.file(id)?;
+        let incoming = if direction == LinkDirection::Incoming {
+            self.incoming_index(kind)
+        } else {
+            HashMap::new()
+        };
+
+        let mut visited: HashSet<String> = HashSet::from([id.to_string()]);
+        let mut queue = VecDeque::from([id.to_string()]);
+        let mut result = Vec::new();
+        while let Some(current) = queue.pop_front() {
+            let next: Vec<String> = match direction {
+                LinkDirection::Outgoing => self.files.get(&current)
+                    .map(|file| file.links.iter()
+                        .filter(|link| kind.is_none_or(|kind| link.kind == *kind))
+                        .filter(|link| self.files.contains_key(&link.target))
+                        .map(|link| link.target.clone())
+                        .collect())
+                    .unwrap_or_default(),
+                LinkDirection::Incoming => incoming.get(&current).cloned().unwrap_or_default(),
+            };
+
+            for neighbor in next {
+                if visited.insert(neighbor.clone()) {
+                    result.push(neighbor.clone());
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn set_cascade_rule(&mut self, kind: LinkKind, rule: CascadeRule) {
+        self.cascade_rules.insert(kind, rule);
+        self.storage.mark_settings_changed();
+    }
+
+    pub fn cascade_rule(&self, kind: &LinkKind) -> CascadeRule {
+        self.cascade_rules.get(kind).copied().unwrap_or_default()
+    }
+
+    /// Works out which files deleting `ids` removes once `Delete` cascades
+    /// are followed, failing if a `Restrict` link from a surviving file
+    /// points at any of them.
+    pub(crate) fn plan_delete(&self, ids: &[String]) -> VfsResult<Vec<String>> {
+        let mut doomed: Vec<String> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<String> = VecDeque::new();
+        for id in ids {
+            self.file(id)?;
+            if seen.insert(id.clone()) {
+                queue.push_back(id.clone());
+            }
+        }
+
+        while let Some(id) = queue.pop_front() {
+            for file in self.files.values() {
+                let cascades = file.links.iter()
+                    .any(|link| link.target == id && self.cascade_rule(&link.kind) == CascadeRule::Delete);
+                if cascades && seen.insert(file.id.clone()) {
+                    queue.push_back(file.id.clone());
+                }
+            }
+            doomed.push(id);
+        }
+
+        for file in self.files.values().filter(|file| !seen.contains(&file.id)) {
+            let restricted = file.links.iter()
+                .find(|link| seen.contains(&link.target) && self.cascade_rule(&link.kind) == CascadeRule::Restrict);
+            if let Some(link) = restricted {
+                return Err(VfsError::InvalidOperation {
+                    reason: format!("{} is linked from {} ({:?})", link.target, file.path, link.kind),
+                });
+            }
+        }
+        Ok(doomed)
+    }
+
+    /// Drops links that point at files which no longer exist.
+    pub(crate) fn remove_dangling_links(&mut self, deleted: &HashSet<String>) {
+        let mut changed = Vec::new();
+        for file in self.files.values_mut() {
+            let before = file.links.len();
+            file.links.retain(|link| !deleted.contains(&link.target));
+            if file.links.len() != before {
+                changed.push((file.id.clone(), file.path.clone()));
+            }
+        }
+
+        changed.sort_by(|a, b| a.1.cmp(&b.1));
+        for (id, path) in changed {
+            self.emit(FileEvent::LinksChanged { id, path });
+        }
+    }
+
+    // target -> sources, for links of `kind` (or every kind)
+    fn incoming_index(&self, kind: Option<&LinkKind>) -> HashMap<String, Vec<String>> {
+        let mut index: HashMap<String, Vec<String>> = HashMap::new();
+        let mut files: Vec<&VirtualFile> = self.files.values().collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        for file in files {
+            for link in file.links.iter().filter(|link| kind.is_none_or(|kind| link.kind == *kind)) {
+                index.entry(link.target.clone()).or_default().push(file.id.clone());
+            }
+        }
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SupportedFileType;
+
+    fn csv(vfs: &mut VirtualFileSystem, path: &str) -> String {
+        vfs.create_file_at(path, SupportedFileType::CSV, path.as_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut vfs = VirtualFileSystem::new();
+        let a = csv(&mut vfs, "/a.csv");
+        let b = csv(&mut vfs, "/b.csv");
+
+        assert!(vfs.add_tag(&a, "pokemon").unwrap());
+        assert!(!vfs.add_tag(&a, "pokemon").unwrap());
+        vfs.add_tag(&b, "pokemon").unwrap();
+        vfs.add_tag(&b, "raw").unwrap();
+
+        let tagged: Vec<&str> = vfs.files_with_tag("pokemon").iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(tagged, vec!["/a.csv", "/b.csv"]);
+        assert!(vfs.remove_tag(&b, "raw").unwrap());
+        assert_eq!(vfs.tags(&b).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_links_and_lineage() {
+        let mut vfs = VirtualFileSystem::new();
+        let raw = csv(&mut vfs, "/raw.csv");
+        let cleaned = csv(&mut vfs, "/cleaned.csv");
+        let summary = csv(&mut vfs, "/summary.csv");
+        let chart = csv(&mut vfs, "/chart.csv");
+
+        vfs.link(&cleaned, &raw, LinkKind::DerivedFrom).unwrap();
+        vfs.link(&summary, &cleaned, LinkKind::DerivedFrom).unwrap();
+        vfs.link(&chart, &summary, LinkKind::BelongsTo).unwrap();
+        vfs.link(&chart, &summary, LinkKind::BelongsTo).unwrap();
+        assert!(vfs.link(&raw, &raw, LinkKind::References).is_err());
+        assert!(vfs.link(&raw, "missing", LinkKind::References).is_err());
+
+        assert_eq!(vfs.outgoing_links(&chart).unwrap().len(), 1);
+        assert_eq!(vfs.neighbors(&summary, LinkDirection::Incoming).unwrap(), vec![chart.clone()]);
+        assert_eq!(vfs.incoming_links(&raw).unwrap()[0].from, cleaned);
+
+        let ancestors = vfs.lineage(&summary, Some(&LinkKind::DerivedFrom), LinkDirection::Outgoing).unwrap();
+        assert_eq!(ancestors, vec![cleaned.clone(), raw.clone()]);
+        let derived = vfs.lineage(&raw, None, LinkDirection::Incoming).unwrap();
+        assert_eq!(derived, vec![cleaned.clone(), summary.clone(), chart.clone()]);
+
+        // cycles terminate
+        vfs.link(&raw, &chart, LinkKind::References).unwrap();
+        assert_eq!(vfs.lineage(&raw, None, LinkDirection::Outgoing).unwrap().len(), 3);
+
+        assert!(vfs.unlink(&chart, &summary, &LinkKind::BelongsTo).unwrap());
+        assert!(!vfs.unlink(&chart, &summary, &LinkKind::BelongsTo).unwrap());
+    }
+
+    #[test]
+    fn test_cascade_rules() {
+        let mut vfs = VirtualFileSystem::new();
+        let raw = csv(&mut vfs, "/raw.csv");
+        let cleaned = csv(&mut vfs, "/cleaned.csv");
+        let chart = csv(&mut vfs, "/chart.csv");
+        let notes = csv(&mut vfs, "/notes.csv");
+        vfs.link(&cleaned, &raw, LinkKind::DerivedFrom).unwrap();
+        vfs.link(&chart, &cleaned, LinkKind::BelongsTo).unwrap();
+        vfs.link(&notes, &cleaned, LinkKind::References).unwrap();
+
+        // default: links are dropped, linking files stay
+        let spare = csv(&mut vfs, "/spare.csv");
+        vfs.link(&notes, &spare, LinkKind::References).unwrap();
+        vfs.delete_file(&spare).unwrap();
+        assert_eq!(vfs.outgoing_links(&notes).unwrap().len(), 1);
+
+        vfs.set_cascade_rule(LinkKind::BelongsTo, CascadeRule::Delete);
+        vfs.set_cascade_rule(LinkKind::DerivedFrom, CascadeRule::Delete);
+        vfs.set_cascade_rule(LinkKind::References, CascadeRule::Restrict);
+        assert!(matches!(vfs.delete_file(&raw), Err(VfsError::InvalidOperation { .. })));
+        assert_eq!(vfs.list_files().len(), 4);
+
+        vfs.set_cascade_rule(LinkKind::References, CascadeRule::Unlink);
+        vfs.delete_file(&raw).unwrap();
+        assert!(vfs.read_file(&cleaned).is_err());
+        assert!(vfs.read_file(&chart).is_err());
+        assert!(vfs.outgoing_links(&notes).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_remove_dir_cascades_once() {
+        let mut vfs = VirtualFileSystem::new();
+        let raw = csv(&mut vfs, "/data/raw.csv");
+        let cleaned = csv(&mut vfs, "/data/cleaned.csv");
+        vfs.link(&cleaned, &raw, LinkKind::DerivedFrom).unwrap();
+        vfs.set_cascade_rule(LinkKind::DerivedFrom, CascadeRule::Delete);
+
+        vfs.remove_dir("/data", true).unwrap();
+        assert!(vfs.list_files().is_empty());
+    }
+}
@@ -0,0 +1,315 @@
+// core/metadata.rs
+//
+// Typed metadata values. Values have the same shape as JSON (null, bool,
+// number, string, array, object) and serialize untagged, so JS objects go
+// in and come out unchanged through serde_wasm_bindgen. Keys can have a
+// `MetadataSchema`; values that don't fit it are rejected on write.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::events::FileEvent;
+use crate::core::file_system::VirtualFileSystem;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<MetadataValue>),
+    Object(BTreeMap<String, MetadataValue>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MetadataType {
+    Null,
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+/// Constraint for the values of one metadata key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetadataSchema {
+    pub value_type: MetadataType,
+    /// Whether `Null` is accepted in addition to `value_type`.
+    #[serde(default)]
+    pub nullable: bool,
+    /// When not empty, the value must be one of these.
+    #[serde(default)]
+    pub allowed: Vec<MetadataValue>,
+}
+
+impl MetadataValue {
+    pub fn value_type(&self) -> MetadataType {
+        match self {
+            MetadataValue::Null => MetadataType::Null,
+            MetadataValue::Bool(_) => MetadataType::Bool,
+            MetadataValue::Number(_) => MetadataType::Number,
+            MetadataValue::String(_) => MetadataType::String,
+            MetadataValue::Array(_) => MetadataType::Array,
+            MetadataValue::Object(_) => MetadataType::Object,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            MetadataValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            MetadataValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            MetadataValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&MetadataValue> {
+        match self {
+            MetadataValue::Object(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /// Substring match for strings, element match for arrays.
+    pub fn contains(&self, needle: &MetadataValue) -> bool {
+        match (self, needle) {
+            (MetadataValue::String(value), MetadataValue::String(needle)) => value.contains(needle.as_str()),
+            (MetadataValue::Array(items), needle) => items.contains(needle),
+            _ => false,
+        }
+    }
+}
+
+impl MetadataSchema {
+    pub fn new(value_type: MetadataType) -> Self {
+        MetadataSchema { value_type, nullable: false, allowed: Vec::new() }
+    }
+
+    pub fn nullable(mut self) -> Self {
+        self.nullable = true;
+        self
+    }
+
+    pub fn one_of(mut self, allowed: Vec<MetadataValue>) -> Self {
+        self.allowed = allowed;
+        self
+    }
+
+    fn check(&self, value: &MetadataValue) -> Result<(), String> {
+        if self.nullable && *value == MetadataValue::Null {
+            return Ok(());
+        }
+        if value.value_type() != self.value_type {
+            return Err(format!("expected {:?}, got {:?}", self.value_type, value.value_type()));
+        }
+        if !self.allowed.is_empty() && !self.allowed.contains(value) {
+            return Err(format!("{} is not an allowed value", value));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MetadataValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        write!(f, "{}", json)
+    }
+}
+
+impl From<String> for MetadataValue {
+    fn from(value: String) -> Self {
+        MetadataValue::String(value)
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        MetadataValue::String(value.to_string())
+    }
+}
+
+impl From<bool> for MetadataValue {
+    fn from(value: bool) -> Self {
+        MetadataValue::Bool(value)
+    }
+}
+
+impl From<f64> for MetadataValue {
+    fn from(value: f64) -> Self {
+        MetadataValue::Number(value)
+    }
+}
+
+impl From<i64> for MetadataValue {
+    fn from(value: i64) -> Self {
+        MetadataValue::Number(value as f64)
+    }
+}
+
+impl<T: Into<MetadataValue>> From<Vec<T>> for MetadataValue {
+    fn from(values: Vec<T>) -> Self {
+        MetadataValue::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<serde_json::Value> for MetadataValue {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => MetadataValue::Null,
+            serde_json::Value::Bool(value) => MetadataValue::Bool(value),
+            serde_json::Value::Number(value) => MetadataValue::Number(value.as_f64().unwrap_or(f64::NAN)),
+            serde_json::Value::String(value) => MetadataValue::String(value),
+            serde_json::Value::Array(values) => MetadataValue::Array(values.into_iter().map(Into::into).collect()),
+            serde_json::Value::Object(map) => {
+                MetadataValue::Object(map.into_iter().map(|(key, value)| (key, value.into())).collect())
+            }
+        }
+    }
+}
+
+impl PartialEq<str> for MetadataValue {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == Some(other)
+    }
+}
+
+impl PartialEq<&str> for MetadataValue {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == Some(*other)
+    }
+}
+
+impl VirtualFileSystem {
+    /// Sets (or with `None` removes) the schema for a metadata key. Values
+    /// already stored are not re-checked.
+    pub fn set_metadata_schema(&mut self, key: &str, schema: Option<MetadataSchema>) {
+        match schema {
+            Some(schema) => self.metadata_schemas.insert(key.to_string(), schema),
+            None => self.metadata_schemas.remove(key),
+        };
+        self.storage.mark_settings_changed();
+    }
+
+    pub fn metadata_schema(&self, key: &str) -> Option<&MetadataSchema> {
+        self.metadata_schemas.get(key)
+    }
+
+    pub fn get_all_metadata(&self, id: &str) -> VfsResult<&HashMap<String, MetadataValue>> {
+        Ok(&self.file(id)?.metadata)
+    }
+
+    /// Sets several keys at once. Every value is validated first, so either
+    /// all of them are written or none.
+    pub fn set_metadata_bulk(&mut self, id: &str, values: HashMap<String, MetadataValue>) -> VfsResult<()> {
+        self.file(id)?;
+        for (key, value) in &values {
+            self.validate_metadata(key, value)?;
+        }
+
+        let mut keys: Vec<String> = values.keys().cloned().collect();
+        keys.sort();
+        let file = self.files.get_mut(id)
+            .ok_or_else(|| VfsError::not_found(id))?;
+        file.metadata.extend(values);
+
+        let path = file.path.clone();
+        for key in keys {
+            self.emit(FileEvent::MetadataChanged { id: id.to_string(), path: path.clone(), key });
+        }
+        self.flush()
+    }
+
+    /// Removes a key and returns its last value.
+    pub fn remove_file_metadata(&mut self, id: &str, key: &str) -> VfsResult<MetadataValue> {
+        let file = self.files.get_mut(id)
+            .ok_or_else(|| VfsError::not_found(id))?;
+        let value = file.metadata.remove(key)
+            .ok_or_else(|| VfsError::MetadataNotFound { id: id.to_string(), key: key.to_string() })?;
+
+        let path = file.path.clone();
+        self.emit(FileEvent::MetadataChanged { id: id.to_string(), path, key: key.to_string() });
+        self.flush()?;
+        Ok(value)
+    }
+
+    pub(crate) fn validate_metadata(&self, key: &str, value: &MetadataValue) -> VfsResult<()> {
+        match self.metadata_schemas.get(key) {
+            Some(schema) => schema.check(value)
+                .map_err(|reason| VfsError::InvalidMetadata { key: key.to_string(), reason }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SupportedFileType;
+
+    #[test]
+    fn test_values_round_trip_through_json() {
+        let value: MetadataValue = serde_json::from_str(r#"{"position": {"x": 100, "y": 100.5}, "tags": ["a", null, true]}"#).unwrap();
+        assert_eq!(value.get("position").and_then(|p| p.get("x")).and_then(MetadataValue::as_f64), Some(100.0));
+        assert!(value.get("tags").unwrap().contains(&MetadataValue::Null));
+
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(serde_json::from_str::<MetadataValue>(&json).unwrap(), value);
+        // plain strings stored by older versions still load
+        assert_eq!(serde_json::from_str::<MetadataValue>(r#""100,100""#).unwrap(), "100,100");
+    }
+
+    #[test]
+    fn test_bulk_set_and_remove() {
+        let mut vfs = VirtualFileSystem::new();
+        let id = vfs.create_file_at("/a.csv", SupportedFileType::CSV, vec![1]).unwrap();
+
+        let mut values = HashMap::new();
+        values.insert("rows".to_string(), MetadataValue::from(10i64));
+        values.insert("reviewed".to_string(), MetadataValue::from(true));
+        vfs.set_metadata_bulk(&id, values).unwrap();
+        assert_eq!(vfs.get_all_metadata(&id).unwrap().len(), 2);
+        assert_eq!(vfs.get_file_metadata(&id, "reviewed").unwrap().as_bool(), Some(true));
+
+        assert_eq!(vfs.remove_file_metadata(&id, "rows").unwrap(), MetadataValue::Number(10.0));
+        assert!(matches!(vfs.remove_file_metadata(&id, "rows"), Err(VfsError::MetadataNotFound { .. })));
+    }
+
+    #[test]
+    fn test_schema_validation() {
+        let mut vfs = VirtualFileSystem::new();
+        let id = vfs.create_file_at("/a.csv", SupportedFileType::CSV, vec![1]).unwrap();
+        vfs.set_metadata_schema("rows", Some(MetadataSchema::new(MetadataType::Number).nullable()));
+        vfs.set_metadata_schema("status", Some(MetadataSchema::new(MetadataType::String).one_of(vec!["draft".into(), "final".into()])));
+
+        vfs.set_file_metadata(&id, "rows".to_string(), 3i64).unwrap();
+        vfs.set_file_metadata(&id, "rows".to_string(), MetadataValue::Null).unwrap();
+        let error = vfs.set_file_metadata(&id, "rows".to_string(), "three").unwrap_err();
+        assert_eq!(error.code(), "INVALID_METADATA");
+        assert!(vfs.set_file_metadata(&id, "status".to_string(), "other").is_err());
+
+        // a bulk write with one bad value writes nothing
+        let mut values = HashMap::new();
+        values.insert("status".to_string(), MetadataValue::from("final"));
+        values.insert("rows".to_string(), MetadataValue::from(false));
+        assert!(vfs.set_metadata_bulk(&id, values).is_err());
+        assert!(vfs.get_file_metadata(&id, "status").is_err());
+
+        vfs.set_metadata_schema("rows", None);
+        vfs.set_file_metadata(&id, "rows".to_string(), "three").unwrap();
+    }
+}
@@ -1,16 +1,59 @@
 
 mod file_system;
+mod error;
+mod blob_store;
+mod compression;
+mod encryption;
+mod clock;
+mod versioning;
+mod snapshot;
+mod streaming;
+mod detection;
+mod eviction;
+mod events;
+mod transaction;
+mod metadata;
+mod graph;
+mod provenance;
+mod storage;
+mod trash;
+mod query;
+pub mod path;
 mod file_metadata;
+mod csv_dialect;
 mod csv_metadata;
+mod temporal;
 mod sql_conversion;
 mod statistical_methods;
 mod types;
 pub(crate) mod test_utils;
 
 // Re-export public items
-pub use self::file_system::{VirtualFileSystem, VirtualFile, SupportedFileType};
+pub use self::file_system::{VirtualFileSystem, VirtualFile, SupportedFileType, DirEntry, EntryKind, StorageStats, ZipManifestEntry, ZIP_MANIFEST_NAME};
+pub use self::blob_store::{BlobStore, ContentProvider, calculate_checksum};
+pub use self::compression::{Compression, CompressionPolicy};
+pub use self::encryption::{EncryptionHeader, KdfParams};
+pub use self::versioning::{FileVersion, DEFAULT_MAX_VERSIONS};
+pub use self::snapshot::{SNAPSHOT_MAGIC, SNAPSHOT_FORMAT_VERSION};
+pub use self::streaming::PendingUpload;
+pub use self::detection::{detect_file_type, DetectionSource, FileTypeDetection, TypeDetectionMode, TypeMismatch};
+pub use self::eviction::ContentState;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::eviction::DirectoryContentProvider;
+pub use self::events::{EventFilter, FileEvent, Subscription};
+pub use self::transaction::BatchOp;
+pub use self::clock::{Clock, ManualClock, SystemClock};
+pub use self::provenance::Provenance;
+pub use self::storage::{AsyncStorageBackend, MemoryBackend, StorageBackend, StorageChanges, StoredBlob, StoredSettings, StoredState};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::storage::DirectoryBackend;
+pub use self::graph::{CascadeRule, FileLink, LinkDirection, LinkEdge, LinkKind};
+pub use self::metadata::{MetadataSchema, MetadataType, MetadataValue};
+pub use self::query::{glob_match, FileQuery, FileRecord, MetadataPredicate, NamePattern, QueryResult, SortField, SortOrder};
+pub use self::error::{VfsError, CsvError, VfsResult};
 pub use self::file_metadata::FileMetadata;
 pub use self::csv_metadata::CSVMetadata;
+pub use self::csv_dialect::{CsvDialect, DecodeReader, EscapeStyle, LineTerminator, TextEncoding, SNIFF_BYTES};
 pub use self::sql_conversion::generate_sql_statements;
 pub use self::statistical_methods::{analyze_distribution, quick_frequency_check};
 pub use self::types::*;
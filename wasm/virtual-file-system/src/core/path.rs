@@ -0,0 +1,132 @@
+// core/path.rs
+//
+// Helpers for the slash separated paths used by the virtual file system.
+// Every stored path is absolute and normalized, e.g. "/datasets/pokemon.csv".
+// The root directory is "/".
+
+use crate::core::error::{VfsError, VfsResult};
+
+pub const ROOT: &str = "/";
+
+/// Normalizes a user supplied path into its absolute, canonical form.
+/// Relative paths are resolved from the root, "." segments are dropped
+/// and ".." pops the previous segment.
+pub fn normalize(path: &str) -> VfsResult<String> {
+    let mut segments: Vec<&str> = Vec::new();
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err(VfsError::InvalidPath {
+                        path: path.to_string(),
+                        reason: "escapes the root directory".to_string(),
+                    });
+                }
+            }
+            _ => segments.push(segment),
+        }
+    }
+
+    if segments.is_empty() {
+        return Ok(ROOT.to_string());
+    }
+
+    Ok(format!("/{}", segments.join("/")))
+}
+
+/// Returns the parent directory of a normalized path, `None` for the root.
+pub fn parent(path: &str) -> Option<&str> {
+    if path == ROOT {
+        return None;
+    }
+
+    match path.rfind('/') {
+        Some(0) => Some(ROOT),
+        Some(index) => Some(&path[..index]),
+        None => None,
+    }
+}
+
+/// Returns the last segment of a normalized path.
+pub fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or("")
+}
+
+/// Joins a normalized directory path with a single entry name.
+pub fn join(dir: &str, name: &str) -> String {
+    if dir == ROOT {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// True when `path` lives somewhere below `dir` (not equal to it).
+pub fn is_descendant(path: &str, dir: &str) -> bool {
+    if dir == ROOT {
+        return path != ROOT;
+    }
+
+    path.len() > dir.len() && path.starts_with(dir) && path.as_bytes()[dir.len()] == b'/'
+}
+
+/// Checks that a single entry name can be used inside a directory.
+pub fn validate_name(name: &str) -> VfsResult<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(VfsError::InvalidPath {
+            path: name.to_string(),
+            reason: "not a valid file name".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Ancestor directories of a normalized path, from the root down,
+/// excluding the path itself.
+pub fn ancestors(path: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = parent(path);
+
+    while let Some(dir) = current {
+        result.push(dir.to_string());
+        current = parent(dir);
+    }
+
+    result.reverse();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("/").unwrap(), "/");
+        assert_eq!(normalize("").unwrap(), "/");
+        assert_eq!(normalize("datasets/pokemon.csv").unwrap(), "/datasets/pokemon.csv");
+        assert_eq!(normalize("//datasets/./raw//../pokemon.csv").unwrap(), "/datasets/pokemon.csv");
+        assert!(normalize("/..").is_err());
+    }
+
+    #[test]
+    fn test_parent_and_name() {
+        assert_eq!(parent("/"), None);
+        assert_eq!(parent("/a"), Some("/"));
+        assert_eq!(parent("/a/b/c.csv"), Some("/a/b"));
+        assert_eq!(file_name("/a/b/c.csv"), "c.csv");
+        assert_eq!(join("/", "a"), "/a");
+        assert_eq!(join("/a", "b"), "/a/b");
+        assert_eq!(ancestors("/a/b/c.csv"), vec!["/", "/a", "/a/b"]);
+    }
+
+    #[test]
+    fn test_is_descendant() {
+        assert!(is_descendant("/a/b", "/a"));
+        assert!(is_descendant("/a", "/"));
+        assert!(!is_descendant("/ab", "/a"));
+        assert!(!is_descendant("/a", "/a"));
+    }
+}
@@ -0,0 +1,123 @@
+// core/provenance.rs
+//
+// Where a file came from and when it was last touched. `created_at`,
+// `modified_at` and `accessed_at` on `VirtualFile` are kept up to date by
+// the file system itself, using its `Clock`:
+//
+//   created    set once by every create path
+//   modified   new content (updates, reverts, appends, partial writes)
+//   accessed   any of the above, plus reads of the record or its content
+use serde::{Serialize, Deserialize};
+
+use crate::core::clock::Clock;
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::file_system::{SupportedFileType, VirtualFileSystem};
+use crate::core::blob_store::calculate_checksum;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub enum Provenance {
+    /// Records written before provenance was tracked.
+    #[default]
+    Unknown,
+    /// Picked or dropped by the user, the default for `create_file`.
+    Upload,
+    Paste,
+    /// Produced by a transform of other files, e.g. a cleaned CSV.
+    Derived { sources: Vec<String>, transform: String },
+    /// Extracted from an archive, `archive` names it when known.
+    ArchiveImport { archive: Option<String> },
+}
+
+impl VirtualFileSystem {
+    /// Replaces the clock used for all timestamps.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub(crate) fn now(&self) -> u64 {
+        self.clock.now_millis()
+    }
+
+    /// `create_file_at` with an explicit provenance.
+    pub fn create_file_with_provenance(&mut self, file_path: &str, file_type: SupportedFileType, content: Vec<u8>, provenance: Provenance) -> VfsResult<String> {
+        let file_path = self.check_new_file_path(file_path)?;
+        let checksum = calculate_checksum(&content);
+        self.insert_file(file_path, file_type, content, checksum, provenance)
+    }
+
+    pub fn set_provenance(&mut self, id: &str, provenance: Provenance) -> VfsResult<()> {
+        self.files.get_mut(id)
+            .ok_or_else(|| VfsError::not_found(id))?
+            .provenance = provenance;
+        self.storage.mark_dirty(id);
+        self.flush()
+    }
+
+    // Access times are saved with the next write of their record, so a
+    // read alone does not mark it dirty.
+    pub(crate) fn touch_accessed(&mut self, id: &str) {
+        let now = self.now();
+        if let Some(file) = self.files.get_mut(id) {
+            file.accessed_at = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::clock::ManualClock;
+    use crate::core::{FileQuery, SortField, SortOrder};
+
+    #[test]
+    fn test_timestamps_follow_the_clock() {
+        let clock = ManualClock::new(1_000);
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_clock(Box::new(clock.clone()));
+
+        let id = vfs.create_file_at("/a.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+        let file = vfs.read_file(&id).unwrap();
+        assert_eq!((file.created_at, file.modified_at, file.accessed_at), (1_000, 1_000, 1_000));
+        assert_eq!(file.versions[0].timestamp, 1_000);
+        assert_eq!(file.provenance, Provenance::Upload);
+
+        clock.advance(500);
+        let file = vfs.read_file(&id).unwrap();
+        assert_eq!((file.modified_at, file.accessed_at), (1_000, 1_500));
+
+        clock.advance(500);
+        vfs.read_range(&id, 0, 1).unwrap();
+        assert_eq!(vfs.file(&id).unwrap().accessed_at, 2_000);
+
+        clock.advance(500);
+        vfs.append(&id, b"\n2").unwrap();
+        let file = vfs.read_file(&id).unwrap();
+        assert_eq!((file.created_at, file.modified_at), (1_000, 2_500));
+        assert_eq!(file.versions[1].timestamp, 2_500);
+
+        clock.advance(500);
+        vfs.get_file_content(&id).unwrap();
+        let file = vfs.read_file(&id).unwrap();
+        assert_eq!((file.modified_at, file.accessed_at), (2_500, 3_000));
+    }
+
+    #[test]
+    fn test_provenance_and_recent_files() {
+        let clock = ManualClock::new(0);
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_clock(Box::new(clock.clone()));
+
+        let raw = vfs.create_file_at("/raw.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+        clock.advance(10);
+        let derived = Provenance::Derived { sources: vec![raw.clone()], transform: "dedupe".to_string() };
+        let cleaned = vfs.create_file_with_provenance("/cleaned.csv", SupportedFileType::CSV, b"a\n1".to_vec(), derived.clone()).unwrap();
+        assert_eq!(vfs.read_file(&cleaned).unwrap().provenance, derived);
+
+        vfs.set_provenance(&raw, Provenance::Paste).unwrap();
+        assert_eq!(vfs.read_file(&raw).unwrap().provenance, Provenance::Paste);
+
+        let recent = vfs.query(&FileQuery::new().sort(SortField::Created, SortOrder::Descending));
+        assert_eq!(recent.records[0].id, cleaned);
+        assert_eq!(recent.records[0].created_at, 10);
+    }
+}
@@ -0,0 +1,388 @@
+// core/query.rs
+//
+// Structured file search. A `FileQuery` combines name, type, size, location
+// and metadata filters with sorting and pagination, and returns
+// lightweight `FileRecord`s so results never carry content or version
+// history. The query deserializes from the same shape on the JS side.
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+use crate::core::file_system::{SupportedFileType, VirtualFile, VirtualFileSystem};
+use crate::core::metadata::MetadataValue;
+use crate::core::path;
+use crate::core::provenance::Provenance;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum NamePattern {
+    /// Shell style pattern with `*` and `?`, matched against the whole name.
+    /// Patterns containing `/` are matched against the full path instead.
+    Glob(String),
+    /// Case insensitive substring of the name.
+    Contains(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum MetadataPredicate {
+    Exists { key: String },
+    Equals { key: String, value: MetadataValue },
+    /// Substring of a string value, or element of an array value.
+    Contains { key: String, value: MetadataValue },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum SortField {
+    Name,
+    #[default]
+    Path,
+    Size,
+    Type,
+    Created,
+    Modified,
+    Accessed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// Filters are combined with AND. `file_types` matches any of the listed
+/// types, an empty list matches every type.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct FileQuery {
+    pub name: Option<NamePattern>,
+    pub file_types: Vec<SupportedFileType>,
+    pub min_size: Option<usize>,
+    pub max_size: Option<usize>,
+    /// Only files below this directory.
+    pub directory: Option<String>,
+    /// With `directory` set, also search its subdirectories.
+    pub recursive: bool,
+    pub metadata: Vec<MetadataPredicate>,
+    /// Files must carry every one of these tags.
+    pub tags: Vec<String>,
+    pub sort_by: SortField,
+    pub order: SortOrder,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+/// A file without its content or version history.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileRecord {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+    pub file_type: SupportedFileType,
+    pub size: usize,
+    pub stored_size: usize,
+    pub encrypted: bool,
+    pub checksum: String,
+    pub version: u32,
+    pub metadata: HashMap<String, MetadataValue>,
+    pub tags: Vec<String>,
+    pub created_at: u64,
+    pub modified_at: u64,
+    pub accessed_at: u64,
+    pub provenance: Provenance,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueryResult {
+    /// Number of matches before pagination.
+    pub total: usize,
+    pub records: Vec<FileRecord>,
+}
+
+impl FileQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn name_glob(mut self, pattern: &str) -> Self {
+        self.name = Some(NamePattern::Glob(pattern.to_string()));
+        self
+    }
+
+    pub fn name_contains(mut self, text: &str) -> Self {
+        self.name = Some(NamePattern::Contains(text.to_string()));
+        self
+    }
+
+    pub fn file_type(mut self, file_type: SupportedFileType) -> Self {
+        self.file_types.push(file_type);
+        self
+    }
+
+    pub fn size_between(mut self, min: Option<usize>, max: Option<usize>) -> Self {
+        self.min_size = min;
+        self.max_size = max;
+        self
+    }
+
+    pub fn in_directory(mut self, directory: &str, recursive: bool) -> Self {
+        self.directory = Some(directory.to_string());
+        self.recursive = recursive;
+        self
+    }
+
+    pub fn metadata_exists(mut self, key: &str) -> Self {
+        self.metadata.push(MetadataPredicate::Exists { key: key.to_string() });
+        self
+    }
+
+    pub fn metadata_equals(mut self, key: &str, value: impl Into<MetadataValue>) -> Self {
+        self.metadata.push(MetadataPredicate::Equals { key: key.to_string(), value: value.into() });
+        self
+    }
+
+    pub fn metadata_contains(mut self, key: &str, value: impl Into<MetadataValue>) -> Self {
+        self.metadata.push(MetadataPredicate::Contains { key: key.to_string(), value: value.into() });
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn sort(mut self, field: SortField, order: SortOrder) -> Self {
+        self.sort_by = field;
+        self.order = order;
+        self
+    }
+
+    pub fn page(mut self, offset: usize, limit: usize) -> Self {
+        self.offset = offset;
+        self.limit = Some(limit);
+        self
+    }
+
+    fn matches(&self, file: &VirtualFile, directory: Option<&str>) -> bool {
+        let name_matches = match &self.name {
+            Some(NamePattern::Glob(pattern)) if pattern.contains('/') => glob_match(pattern, &file.path),
+            Some(NamePattern::Glob(pattern)) => glob_match(pattern, &file.name),
+            Some(NamePattern::Contains(text)) => file.name.to_lowercase().contains(&text.to_lowercase()),
+            None => true,
+        };
+        let location_matches = match directory {
+            Some(dir) if self.recursive => path::is_descendant(&file.path, dir),
+            Some(dir) => path::parent(&file.path) == Some(dir),
+            None => true,
+        };
+
+        name_matches
+            && location_matches
+            && (self.file_types.is_empty() || self.file_types.contains(&file.file_type))
+            && self.min_size.is_none_or(|min| file.size >= min)
+            && self.max_size.is_none_or(|max| file.size <= max)
+            && self.metadata.iter().all(|predicate| predicate.matches(&file.metadata))
+            && self.tags.iter().all(|tag| file.tags.contains(tag))
+    }
+}
+
+impl MetadataPredicate {
+    fn matches(&self, metadata: &HashMap<String, MetadataValue>) -> bool {
+        match self {
+            MetadataPredicate::Exists { key } => metadata.contains_key(key),
+            MetadataPredicate::Equals { key, value } => metadata.get(key) == Some(value),
+            MetadataPredicate::Contains { key, value } => {
+                metadata.get(key).is_some_and(|actual| actual.contains(value))
+            }
+        }
+    }
+}
+
+impl From<&VirtualFile> for FileRecord {
+    fn from(file: &VirtualFile) -> Self {
+        FileRecord {
+            id: file.id.clone(),
+            name: file.name.clone(),
+            path: file.path.clone(),
+            file_type: file.file_type.clone(),
+            size: file.size,
+            stored_size: file.stored_size,
+            encrypted: file.encrypted,
+            checksum: file.checksum.clone(),
+            version: file.current_version(),
+            metadata: file.metadata.clone(),
+            tags: file.tags.iter().cloned().collect(),
+            created_at: file.created_at,
+            modified_at: file.modified_at,
+            accessed_at: file.accessed_at,
+            provenance: file.provenance.clone(),
+        }
+    }
+}
+
+impl VirtualFileSystem {
+    pub fn query(&self, query: &FileQuery) -> QueryResult {
+        // An unnormalizable directory can't contain anything.
+        let directory = match &query.directory {
+            Some(dir) => match path::normalize(dir) {
+                Ok(dir) => Some(dir),
+                Err(_) => return QueryResult { total: 0, records: Vec::new() },
+            },
+            None => None,
+        };
+
+        let mut matches: Vec<&VirtualFile> = self.files.values()
+            .filter(|file| query.matches(file, directory.as_deref()))
+            .collect();
+
+        matches.sort_by(|a, b| {
+            let ordering = match query.sort_by {
+                SortField::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+                SortField::Path => a.path.cmp(&b.path),
+                SortField::Size => a.size.cmp(&b.size),
+                SortField::Type => type_label(&a.file_type).cmp(&type_label(&b.file_type)),
+                SortField::Created => a.created_at.cmp(&b.created_at),
+                SortField::Modified => a.modified_at.cmp(&b.modified_at),
+                SortField::Accessed => a.accessed_at.cmp(&b.accessed_at),
+            };
+            // Ties fall back to the path so pages are stable.
+            let ordering = ordering.then_with(|| a.path.cmp(&b.path));
+            match query.order {
+                SortOrder::Ascending => ordering,
+                SortOrder::Descending => ordering.reverse(),
+            }
+        });
+
+        let total = matches.len();
+        let records = matches.into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .map(FileRecord::from)
+            .collect();
+
+        QueryResult { total, records }
+    }
+}
+
+fn type_label(file_type: &SupportedFileType) -> String {
+    match file_type {
+        SupportedFileType::Other(ext) => format!("Other({})", ext),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Case insensitive glob match supporting `*` (any run of characters) and
+/// `?` (exactly one character).
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text index it was tried at.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_fs() -> VirtualFileSystem {
+        let mut vfs = VirtualFileSystem::new();
+        let pokemon = vfs.create_file_at("/datasets/pokemon.csv", SupportedFileType::CSV, vec![0; 300]).unwrap();
+        vfs.set_file_metadata(&pokemon, "source".to_string(), "kaggle".to_string()).unwrap();
+        vfs.create_file_at("/datasets/raw/moves.csv", SupportedFileType::CSV, vec![1; 50]).unwrap();
+        vfs.create_file_at("/images/Pikachu.png", SupportedFileType::Image, vec![2; 1000]).unwrap();
+        vfs.create_file_at("/notes.txt", SupportedFileType::Text, vec![3; 10]).unwrap();
+        vfs
+    }
+
+    fn paths(result: &QueryResult) -> Vec<&str> {
+        result.records.iter().map(|record| record.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.csv", "pokemon.csv"));
+        assert!(glob_match("POKE*", "pokemon.csv"));
+        assert!(glob_match("p?kemon.*", "pokemon.csv"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("*.csv", "pokemon.csv.bak"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut vfs = sample_fs();
+
+        let result = vfs.query(&FileQuery::new().name_glob("*.csv"));
+        assert_eq!(paths(&result), vec!["/datasets/pokemon.csv", "/datasets/raw/moves.csv"]);
+
+        let result = vfs.query(&FileQuery::new().name_glob("/datasets/*/*.csv"));
+        assert_eq!(paths(&result), vec!["/datasets/raw/moves.csv"]);
+
+        let result = vfs.query(&FileQuery::new().name_contains("pika"));
+        assert_eq!(paths(&result), vec!["/images/Pikachu.png"]);
+
+        let result = vfs.query(&FileQuery::new().file_type(SupportedFileType::Image).file_type(SupportedFileType::Text));
+        assert_eq!(paths(&result), vec!["/images/Pikachu.png", "/notes.txt"]);
+
+        let result = vfs.query(&FileQuery::new().size_between(Some(50), Some(300)));
+        assert_eq!(result.total, 2);
+
+        let result = vfs.query(&FileQuery::new().in_directory("/datasets", false));
+        assert_eq!(paths(&result), vec!["/datasets/pokemon.csv"]);
+        let result = vfs.query(&FileQuery::new().in_directory("/datasets", true));
+        assert_eq!(result.total, 2);
+
+        let result = vfs.query(&FileQuery::new().metadata_equals("source", "kaggle"));
+        assert_eq!(paths(&result), vec!["/datasets/pokemon.csv"]);
+        assert_eq!(result.records[0].metadata.get("source").and_then(MetadataValue::as_str), Some("kaggle"));
+        assert_eq!(vfs.query(&FileQuery::new().metadata_contains("source", "agg")).total, 1);
+        assert_eq!(vfs.query(&FileQuery::new().metadata_exists("missing")).total, 0);
+
+        let moves = vfs.resolve_path("/datasets/raw/moves.csv").unwrap();
+        vfs.add_tag(&moves, "raw").unwrap();
+        let result = vfs.query(&FileQuery::new().tag("raw").file_type(SupportedFileType::CSV));
+        assert_eq!(paths(&result), vec!["/datasets/raw/moves.csv"]);
+        assert_eq!(result.records[0].tags, vec!["raw".to_string()]);
+    }
+
+    #[test]
+    fn test_sort_and_paginate() {
+        let vfs = sample_fs();
+        let query = FileQuery::new().sort(SortField::Size, SortOrder::Descending).page(1, 2);
+        let result = vfs.query(&query);
+        assert_eq!(result.total, 4);
+        assert_eq!(paths(&result), vec!["/datasets/pokemon.csv", "/datasets/raw/moves.csv"]);
+
+        let result = vfs.query(&FileQuery::new().sort(SortField::Name, SortOrder::Ascending).page(2, 10));
+        assert_eq!(paths(&result), vec!["/images/Pikachu.png", "/datasets/pokemon.csv"]);
+    }
+
+    #[test]
+    fn test_query_from_json() {
+        let vfs = sample_fs();
+        let query: FileQuery = serde_json::from_str(r#"{
+            "name": {"Glob": "*.csv"},
+            "metadata": [{"Exists": {"key": "source"}}],
+            "limit": 5
+        }"#).unwrap();
+        assert_eq!(paths(&vfs.query(&query)), vec!["/datasets/pokemon.csv"]);
+    }
+}
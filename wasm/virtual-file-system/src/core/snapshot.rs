@@ -0,0 +1,386 @@
+// core/snapshot.rs
+//
+// Whole file system snapshots, including file content.
+//
+// Layout (all integers little endian):
+//
+//   magic            8 bytes   b"NSVFSNAP"
+//   format version   u16
+//   manifest length  u32
+//   manifest         JSON, see `SnapshotManifest`
+//   blobs            for each entry of `manifest.blobs`, in order:
+//                    u64 length followed by the bytes as stored, i.e.
+//                    compressed with the entry's `compression`
+//
+// Blobs that were evicted from memory when the snapshot was taken are
+// listed with `evicted: true` and have no bytes in the blob section; they
+// come back evicted on import and are rehydrated like any other.
+//
+// Trashed files are listed with the others, told apart by `deleted_at`.
+//
+// Content of encrypted files is written sealed, in blobs keyed by the
+// checksum of the ciphertext; the manifest carries the encryption header
+// but never the key, so an imported snapshot starts out locked.
+//
+// The manifest describes everything needed to read the rest of the file,
+// and every blob is checked against its SHA-256 checksum on import, after
+// decompressing it. Paths are checked too: a snapshot whose paths aren't
+// normalized, or whose files sit outside its directories, is refused.
+use std::collections::{BTreeSet, HashMap};
+use serde::{Serialize, Deserialize};
+
+use crate::core::blob_store::{calculate_checksum, BlobStore};
+use crate::core::compression::Compression;
+use crate::core::encryption::EncryptionHeader;
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::file_system::{check_tree, VirtualFile, VirtualFileSystem};
+use crate::core::path;
+use crate::core::storage::StoredSettings;
+
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"NSVFSNAP";
+pub const SNAPSHOT_FORMAT_VERSION: u16 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotManifest {
+    files: Vec<VirtualFile>,
+    directories: Vec<String>,
+    blobs: Vec<BlobEntry>,
+    #[serde(flatten)]
+    settings: StoredSettings,
+    #[serde(default)]
+    encryption: Option<EncryptionHeader>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BlobEntry {
+    checksum: String,
+    // uncompressed
+    size: u64,
+    #[serde(default)]
+    evicted: bool,
+    #[serde(default)]
+    compression: Compression,
+}
+
+impl VirtualFileSystem {
+    /// Serializes every directory, file record, version and blob into a
+    /// single binary snapshot, see the module docs for the layout.
+    pub fn export_snapshot(&self) -> Vec<u8> {
+        let blobs: Vec<(&str, usize, Compression, Option<&[u8]>)> = self.blobs.iter().collect();
+
+        let manifest = SnapshotManifest {
+            files: self.files.values().chain(self.trash.values()).cloned().collect(),
+            directories: self.directories.iter().cloned().collect(),
+            settings: self.settings(),
+            encryption: self.encryption.header.clone(),
+            blobs: blobs.iter()
+                .map(|(checksum, size, compression, data)| BlobEntry {
+                    checksum: checksum.to_string(),
+                    size: *size as u64,
+                    evicted: data.is_none(),
+                    compression: *compression,
+                })
+                .collect(),
+        };
+        let manifest = serde_json::to_vec(&manifest)
+            .expect("snapshot manifest is always serializable");
+
+        let content_size: usize = blobs.iter()
+            .filter_map(|(.., data)| data.map(|data| data.len() + 8))
+            .sum();
+        let mut out = Vec::with_capacity(SNAPSHOT_MAGIC.len() + 6 + manifest.len() + content_size);
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.extend_from_slice(&SNAPSHOT_FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&(manifest.len() as u32).to_le_bytes());
+        out.extend_from_slice(&manifest);
+        for data in blobs.iter().filter_map(|(.., data)| *data) {
+            out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            out.extend_from_slice(data);
+        }
+        out
+    }
+
+    /// Replaces the whole state of the file system with a snapshot produced
+    /// by `export_snapshot`. Nothing changes if the snapshot is invalid.
+    /// If its content can't be evicted down to the memory budget, the
+    /// snapshot is still imported and the eviction error is returned.
+    pub fn import_snapshot(&mut self, snapshot: &[u8]) -> VfsResult<()> {
+        let mut reader = SnapshotReader { data: snapshot, offset: 0 };
+
+        if reader.take(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+            return Err(invalid("not a virtual file system snapshot"));
+        }
+        let version = u16::from_le_bytes(reader.take_array()?);
+        if version != SNAPSHOT_FORMAT_VERSION {
+            return Err(invalid(&format!("unsupported format version {}", version)));
+        }
+
+        let manifest_len = u32::from_le_bytes(reader.take_array()?) as usize;
+        let manifest: SnapshotManifest = serde_json::from_slice(reader.take(manifest_len)?)
+            .map_err(|e| invalid(&format!("malformed manifest: {}", e)))?;
+
+        // checksum -> (size, compression, bytes as stored or None if evicted)
+        let mut content: HashMap<String, (usize, Compression, Option<Vec<u8>>)> = HashMap::new();
+        for entry in &manifest.blobs {
+            let size = usize::try_from(entry.size)
+                .map_err(|_| invalid(&format!("blob {} is too large", entry.checksum)))?;
+            if entry.evicted {
+                content.insert(entry.checksum.clone(), (size, entry.compression, None));
+                continue;
+            }
+            let len = u64::from_le_bytes(reader.take_array()?);
+            if entry.compression == Compression::None && len != entry.size {
+                return Err(invalid(&format!("blob {} has the wrong size", entry.checksum)));
+            }
+            let data = reader.take(len as usize)?;
+
+            let actual = match entry.compression {
+                Compression::None => calculate_checksum(data),
+                compression => compression.decompress(data, size)
+                    .map(|decompressed| calculate_checksum(&decompressed))
+                    .map_err(|e| invalid(&format!("blob {} does not decompress: {}", entry.checksum, e)))?,
+            };
+            if actual != entry.checksum {
+                return Err(VfsError::ChecksumMismatch { expected: entry.checksum.clone(), actual });
+            }
+            content.insert(entry.checksum.clone(), (size, entry.compression, Some(data.to_vec())));
+        }
+        if reader.offset != snapshot.len() {
+            return Err(invalid("trailing data after the last blob"));
+        }
+
+        let mut directories: BTreeSet<String> = manifest.directories.into_iter().collect();
+        directories.insert(path::ROOT.to_string());
+
+        let mut files = HashMap::new();
+        let mut paths = HashMap::new();
+        let mut trash = HashMap::new();
+        let mut blobs = BlobStore::new();
+        blobs.set_pin_new(self.blobs.pins_new());
+        for file in manifest.files {
+            if files.contains_key(&file.id) || trash.contains_key(&file.id) {
+                return Err(invalid(&format!("duplicate file id {}", file.id)));
+            }
+            for version in &file.versions {
+                if blobs.retain(version.blob_key()) {
+                    continue;
+                }
+                let Some((size, compression, data)) = content.remove(version.blob_key()) else {
+                    return Err(invalid(&format!("missing content for file {}", file.id)));
+                };
+                blobs.insert_stored(version.blob_key().to_string(), size, compression, data)?;
+            }
+            if file.deleted_at.is_some() {
+                trash.insert(file.id.clone(), file);
+                continue;
+            }
+            if paths.insert(file.path.clone(), file.id.clone()).is_some() {
+                return Err(invalid(&format!("duplicate path {}", file.path)));
+            }
+            files.insert(file.id.clone(), file);
+        }
+        check_tree(&directories, files.values().chain(trash.values()))
+            .map_err(|reason| invalid(&reason))?;
+
+        // Old records the snapshot doesn't have are removed from storage.
+        let replaced: Vec<String> = self.files.keys().chain(self.trash.keys())
+            .chain(files.keys()).chain(trash.keys())
+            .cloned()
+            .collect();
+        for id in &replaced {
+            self.storage.mark_dirty(id);
+        }
+
+        self.files = files;
+        self.paths = paths;
+        self.trash = trash;
+        self.directories = directories;
+        let budget = self.blobs.replace_blobs(blobs);
+        self.apply_settings(manifest.settings);
+        // A different header means a different key, so the one held is useless.
+        if self.encryption.header != manifest.encryption {
+            self.encryption.header = manifest.encryption;
+            self.lock();
+        }
+        self.flush()?;
+        budget
+    }
+}
+
+fn invalid(reason: &str) -> VfsError {
+    VfsError::InvalidSnapshot { reason: reason.to_string() }
+}
+
+struct SnapshotReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> SnapshotReader<'a> {
+    fn take(&mut self, len: usize) -> VfsResult<&'a [u8]> {
+        let end = self.offset.checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| invalid("unexpected end of snapshot"))?;
+        let slice = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> VfsResult<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{Compression, CompressionPolicy, VirtualFileSystem, SupportedFileType, VfsError};
+
+    fn sample_fs() -> (VirtualFileSystem, String) {
+        let mut vfs = VirtualFileSystem::new();
+        let id = vfs.create_file_at("/datasets/pokemon.csv", SupportedFileType::CSV, b"a,b\n1,2".to_vec()).unwrap();
+        vfs.update_file(&id, Some(b"a,b\n1,3".to_vec())).unwrap();
+        vfs.set_file_metadata(&id, "position".to_string(), "100,100".to_string()).unwrap();
+        vfs.create_file_at("/copy.csv", SupportedFileType::CSV, b"a,b\n1,3".to_vec()).unwrap();
+        vfs.mkdir("/empty").unwrap();
+        (vfs, id)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (vfs, id) = sample_fs();
+        let snapshot = vfs.export_snapshot();
+
+        let mut restored = VirtualFileSystem::new();
+        restored.import_snapshot(&snapshot).unwrap();
+
+        assert_eq!(restored.get_file_content(&id).unwrap(), b"a,b\n1,3".to_vec());
+        assert_eq!(restored.read_version(&id, 1).unwrap(), b"a,b\n1,2".to_vec());
+        assert_eq!(restored.get_file_metadata(&id, "position").unwrap(), "100,100");
+        assert_eq!(restored.resolve_path("/datasets/pokemon.csv").unwrap(), id);
+        assert!(restored.is_dir("/empty"));
+
+        let stats = restored.storage_stats();
+        assert_eq!(stats.file_count, 2);
+        assert_eq!(stats.blob_count, 2);
+
+        // reference counts are rebuilt, so the shared blob survives one delete
+        let copy = restored.resolve_path("/copy.csv").unwrap();
+        restored.delete_file(&copy).unwrap();
+        assert_eq!(restored.get_file_content(&id).unwrap(), b"a,b\n1,3".to_vec());
+    }
+
+    #[test]
+    fn test_rejects_corrupted_content() {
+        let (vfs, _) = sample_fs();
+        let mut snapshot = vfs.export_snapshot();
+        let last = snapshot.len() - 1;
+        snapshot[last] ^= 0xff;
+
+        let mut restored = VirtualFileSystem::new();
+        let id = restored.create_file("keep.txt".to_string(), SupportedFileType::Other("txt".to_string()), vec![1]).unwrap();
+
+        assert!(matches!(restored.import_snapshot(&snapshot), Err(VfsError::ChecksumMismatch { .. })));
+        assert!(restored.read_file(&id).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_malformed_input() {
+        let mut vfs = VirtualFileSystem::new();
+        assert!(matches!(vfs.import_snapshot(b"nope"), Err(VfsError::InvalidSnapshot { .. })));
+
+        let (source, _) = sample_fs();
+        let snapshot = source.export_snapshot();
+        let truncated = &snapshot[..snapshot.len() - 3];
+        assert!(matches!(vfs.import_snapshot(truncated), Err(VfsError::InvalidSnapshot { .. })));
+    }
+
+    #[test]
+    fn test_evicted_content_stays_evicted() {
+        let mut vfs = VirtualFileSystem::new();
+        let big = vfs.create_file_at("/big.bin", SupportedFileType::Other("bin".to_string()), vec![7; 64]).unwrap();
+        let small = vfs.create_file_at("/small.bin", SupportedFileType::Other("bin".to_string()), vec![1; 8]).unwrap();
+        vfs.set_memory_budget(Some(16)).unwrap();
+
+        let snapshot = vfs.export_snapshot();
+        let mut restored = VirtualFileSystem::new();
+        restored.import_snapshot(&snapshot).unwrap();
+
+        assert_eq!(restored.get_file_content(&small).unwrap(), vec![1; 8]);
+        assert!(matches!(restored.get_file_content(&big), Err(VfsError::ContentEvicted { .. })));
+        restored.rehydrate(&big, vec![7; 64]).unwrap();
+        assert_eq!(restored.get_file_content(&big).unwrap(), vec![7; 64]);
+    }
+
+    // Replaces the manifest of a snapshot with whatever `edit` makes of it.
+    fn edit_manifest(snapshot: &[u8], edit: impl FnOnce(&mut serde_json::Value)) -> Vec<u8> {
+        let manifest_len = u32::from_le_bytes(snapshot[10..14].try_into().unwrap()) as usize;
+        let mut manifest: serde_json::Value = serde_json::from_slice(&snapshot[14..14 + manifest_len]).unwrap();
+        edit(&mut manifest);
+        let manifest = serde_json::to_vec(&manifest).unwrap();
+
+        let mut edited = snapshot[..10].to_vec();
+        edited.extend_from_slice(&(manifest.len() as u32).to_le_bytes());
+        edited.extend_from_slice(&manifest);
+        edited.extend_from_slice(&snapshot[14 + manifest_len..]);
+        edited
+    }
+
+    #[test]
+    fn test_rejects_unsound_paths() {
+        let mut vfs = VirtualFileSystem::new();
+        vfs.create_file_at("/data/a.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+        let snapshot = vfs.export_snapshot();
+
+        let mut restored = VirtualFileSystem::new();
+        let kept = restored.create_file_at("/keep.txt", SupportedFileType::Text, b"k".to_vec()).unwrap();
+        let forgeries = [
+            ("escapes the root", edit_manifest(&snapshot, |manifest| {
+                manifest["files"][0]["path"] = "/../escaped.txt".into();
+                manifest["files"][0]["name"] = "escaped.txt".into();
+            })),
+            ("not normalized", edit_manifest(&snapshot, |manifest| manifest["files"][0]["path"] = "/data/./a.csv".into())),
+            ("name and path disagree", edit_manifest(&snapshot, |manifest| manifest["files"][0]["name"] = "b.csv".into())),
+            ("no parent directory", edit_manifest(&snapshot, |manifest| manifest["directories"] = serde_json::json!(["/"]))),
+            ("directory without parent", edit_manifest(&snapshot, |manifest| {
+                manifest["directories"] = serde_json::json!(["/", "/data", "/x/y"]);
+            })),
+            ("duplicate id", edit_manifest(&snapshot, |manifest| {
+                let mut copy = manifest["files"][0].clone();
+                copy["path"] = "/data/b.csv".into();
+                copy["name"] = "b.csv".into();
+                manifest["files"].as_array_mut().unwrap().push(copy);
+            })),
+            ("same id in the trash", edit_manifest(&snapshot, |manifest| {
+                let mut copy = manifest["files"][0].clone();
+                copy["deleted_at"] = 1.into();
+                manifest["files"].as_array_mut().unwrap().push(copy);
+            })),
+        ];
+        for (case, forged) in forgeries {
+            let result = restored.import_snapshot(&forged);
+            assert!(matches!(result, Err(VfsError::InvalidSnapshot { .. })), "{}: {:?}", case, result);
+        }
+        assert_eq!(restored.list_files().len(), 1);
+        assert!(restored.read_file(&kept).is_ok());
+
+        restored.import_snapshot(&edit_manifest(&snapshot, |_| {})).unwrap();
+        assert_eq!(restored.list_dir_recursive("/").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_rejects_forged_blob_sizes() {
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_compression_policy(CompressionPolicy::new(Compression::Deflate));
+        let content = b"id,name\n".repeat(100);
+        vfs.create_file_at("/a.csv", SupportedFileType::CSV, content.clone()).unwrap();
+        let snapshot = vfs.export_snapshot();
+
+        for size in [u64::MAX / 4, content.len() as u64 * 1_000] {
+            let forged = edit_manifest(&snapshot, |manifest| manifest["blobs"][0]["size"] = size.into());
+            let result = VirtualFileSystem::new().import_snapshot(&forged);
+            assert!(matches!(result, Err(VfsError::InvalidSnapshot { .. })), "{}: {:?}", size, result);
+        }
+    }
+}
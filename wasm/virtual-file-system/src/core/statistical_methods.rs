@@ -29,26 +29,30 @@ pub struct QuickCheck {
 }
 
 pub fn quick_frequency_check(values: &[String]) -> QuickCheck {
-    let mut frequencies = HashMap::new();
-    let total_count = values.len();
-    
-    // Early exit conditions
-    if total_count < MIN_TOTAL_VALUES {
+    // Early exit before counting anything
+    if values.len() < MIN_TOTAL_VALUES {
         return QuickCheck {
             should_analyze: false,
-            frequencies,
-            total_count,
+            frequencies: HashMap::new(),
+            total_count: values.len(),
         };
     }
     
     // Calculate frequencies
+    let mut frequencies = HashMap::new();
     for value in values {
         *frequencies.entry(value.clone()).or_insert(0) += 1;
     }
     
+    check_frequencies(frequencies, values.len())
+}
+
+/// `quick_frequency_check` for values that have been counted already, e.g.
+/// while streaming through a file.
+pub fn check_frequencies(frequencies: HashMap<String, usize>, total_count: usize) -> QuickCheck {
     // Quick validation checks
     let unique_count = frequencies.len();
-    if unique_count == total_count || unique_count > MAX_CATEGORIES {
+    if total_count < MIN_TOTAL_VALUES || unique_count == total_count || unique_count > MAX_CATEGORIES {
         return QuickCheck {
             should_analyze: false,
             frequencies,
@@ -69,8 +73,15 @@ pub fn quick_frequency_check(values: &[String]) -> QuickCheck {
 }
 
 pub fn analyze_distribution(values: &[String]) -> DistributionAnalysis {
-    let quick_check = quick_frequency_check(values);
-    
+    analyze_quick_check(quick_frequency_check(values))
+}
+
+/// `analyze_distribution` for values that have been counted already.
+pub fn analyze_frequencies(frequencies: HashMap<String, usize>, total_count: usize) -> DistributionAnalysis {
+    analyze_quick_check(check_frequencies(frequencies, total_count))
+}
+
+fn analyze_quick_check(quick_check: QuickCheck) -> DistributionAnalysis {
     if !quick_check.should_analyze {
         return create_analysis(
             false,
@@ -152,7 +163,7 @@ fn calculate_confidence_score(
     unique_count: usize,
     total_count: usize,
     repeat_ratio: f64,
-    distribution_score: f64,
+    _distribution_score: f64,
     entropy: f64,
 ) -> f64 {
     let mut score = 0.0;
@@ -168,7 +179,6 @@ fn calculate_confidence_score(
     
     // Factor 3: Category utilization (0.2 weight)
     // All categories should be used somewhat, but don't need to be even
-    let min_usage = MIN_CATEGORY_SIZE_RATIO;
     let categories_well_used = entropy / unique_count as f64;
     score += 0.2 * categories_well_used.min(1.0);
     
@@ -177,7 +187,7 @@ fn calculate_confidence_score(
         score += 0.1;
     }
     
-    score.min(1.0).max(0.0)
+    score.clamp(0.0, 1.0)
 }
 
 fn create_analysis(
@@ -226,7 +236,83 @@ fn create_analysis(
     }
 }
 
+/// Mean and central moments of a stream of numbers, updated one value at a
+/// time (Pébay's single-pass formulas) so a column never has to be held in
+/// memory and large values don't lose precision the way sums of powers do.
+#[derive(Debug, Clone, Default)]
+pub struct Moments {
+    pub count: usize,
+    pub mean: f64,
+    m2: f64,
+    m3: f64,
+    m4: f64,
+}
+
+impl Moments {
+    pub fn add(&mut self, value: f64) {
+        let n1 = self.count as f64;
+        self.count += 1;
+        let n = self.count as f64;
+        let delta = value - self.mean;
+        let delta_n = delta / n;
+        let delta_n2 = delta_n * delta_n;
+        let term1 = delta * delta_n * n1;
+
+        self.mean += delta_n;
+        self.m4 += term1 * delta_n2 * (n * n - 3.0 * n + 3.0) + 6.0 * delta_n2 * self.m2 - 4.0 * delta_n * self.m3;
+        self.m3 += term1 * delta_n * (n - 2.0) - 3.0 * delta_n * self.m2;
+        self.m2 += term1;
+    }
+
+    /// Sample variance, 0 for fewer than two values.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 { 0.0 } else { self.m2 / (self.count - 1) as f64 }
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// 0 when all values are equal.
+    pub fn skewness(&self) -> f64 {
+        if self.m2 == 0.0 { 0.0 } else { (self.count as f64).sqrt() * self.m3 / self.m2.powf(1.5) }
+    }
+
+    /// Excess kurtosis, so 0 for a normal distribution.
+    pub fn kurtosis(&self) -> f64 {
+        if self.m2 == 0.0 { 0.0 } else { self.count as f64 * self.m4 / (self.m2 * self.m2) - 3.0 }
+    }
+}
+
+/// The `q`-quantile (0.0 to 1.0) of values given with their counts, sorted
+/// by value, interpolating between neighbours like numpy's default.
+pub fn weighted_quantile(sorted: &[(f64, usize)], q: f64) -> Option<f64> {
+    let total: usize = sorted.iter().map(|(_, count)| count).sum();
+    if total == 0 {
+        return None;
+    }
+    let rank = q.clamp(0.0, 1.0) * (total - 1) as f64;
+    let (below, fraction) = (rank.floor() as usize, rank.fract());
+
+    let value_at = |rank: usize| {
+        let mut seen = 0;
+        sorted.iter()
+            .find(|(_, count)| {
+                seen += count;
+                seen > rank
+            })
+            .map(|(value, _)| *value)
+    };
+    let low = value_at(below)?;
+    if fraction == 0.0 {
+        return Some(low);
+    }
+    let high = value_at(below + 1)?;
+    Some(low + (high - low) * fraction)
+}
+
 #[cfg(test)]
+#[allow(clippy::useless_vec, clippy::redundant_slicing, non_upper_case_globals)]
 mod tests {
     use super::*;
 
@@ -334,4 +420,27 @@ mod tests {
             analysis.confidence_score, CONFIDENCE_THRESHOLD
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_moments_and_quantiles() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        let mut moments = Moments::default();
+        values.iter().for_each(|&value| moments.add(value));
+        assert_eq!(moments.mean, 5.0);
+        assert!((moments.variance() - 32.0 / 7.0).abs() < 1e-12);
+        assert!((moments.skewness() - 0.65625).abs() < 1e-12);
+        assert!((moments.kurtosis() + 0.21875).abs() < 1e-12);
+
+        let mut constant = Moments::default();
+        (0..3).for_each(|_| constant.add(1e9));
+        assert_eq!((constant.variance(), constant.skewness(), constant.kurtosis()), (0.0, 0.0, 0.0));
+
+        let counted = [(2.0, 1), (4.0, 3), (5.0, 2), (7.0, 1), (9.0, 1)];
+        assert_eq!(weighted_quantile(&counted, 0.0), Some(2.0));
+        assert_eq!(weighted_quantile(&counted, 0.25), Some(4.0));
+        assert_eq!(weighted_quantile(&counted, 0.5), Some(4.5));
+        assert_eq!(weighted_quantile(&counted, 0.75), Some(5.5));
+        assert_eq!(weighted_quantile(&counted, 1.0), Some(9.0));
+        assert_eq!(weighted_quantile(&[], 0.5), None);
+    }
+}
@@ -0,0 +1,841 @@
+// core/storage.rs
+//
+// Persistence for file records and content. The file system always works
+// on its in-memory index; without a backend that index is all there is,
+// which is the default. A `StorageBackend` attached with
+// `VirtualFileSystem::open` is written through: every operation that
+// changes a record writes that record, any content the backend does not
+// have yet and removes content nothing points at any more before it
+// returns. Content is only loaded back from the backend when it is read.
+//
+// Changes are tracked per file id from the events every mutation emits.
+// Nothing is written inside a transaction until it commits. Content the
+// backend doesn't have yet is pinned in memory, so the memory budget never
+// evicts it before it is written. Access times
+// are saved along with the next write of their record. Settings (version
+// limit, metadata schemas, cascade rules, compression policy and trash
+// retention) are stored too, with the next write after they change.
+//
+// A write that fails leaves the operation applied in memory: the method
+// returns the error, and the changes stay pending until the next write or
+// `flush` gets them through.
+//
+// `AsyncStorageBackend` is the same contract for stores that can only be
+// reached asynchronously, like IndexedDB behind a JS adapter. Changes are
+// collected synchronously with `take_changes` and written by `sync_async`.
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::rc::Rc;
+use serde::{Serialize, Serializer, Deserialize};
+
+use crate::core::compression::{Compression, CompressionPolicy};
+use crate::core::encryption::EncryptionHeader;
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::file_system::{check_tree, VirtualFile, VirtualFileSystem};
+use crate::core::graph::{CascadeRule, LinkKind};
+use crate::core::metadata::MetadataSchema;
+
+/// Everything `load` returns. Content is fetched separately, by checksum.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct StoredState {
+    #[serde(default)]
+    pub records: Vec<VirtualFile>,
+    #[serde(default)]
+    pub directories: Vec<String>,
+    #[serde(default)]
+    pub encryption: Option<EncryptionHeader>,
+    /// `None` for stores written before settings were kept.
+    #[serde(default)]
+    pub settings: Option<StoredSettings>,
+}
+
+/// The settings of a file system that outlive a session.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StoredSettings {
+    pub max_versions: usize,
+    #[serde(default)]
+    pub metadata_schemas: HashMap<String, MetadataSchema>,
+    // A list rather than a map, `LinkKind::Custom` can't be a JSON key.
+    #[serde(default)]
+    pub cascade_rules: Vec<(LinkKind, CascadeRule)>,
+    #[serde(default)]
+    pub compression: CompressionPolicy,
+    #[serde(default)]
+    pub trash_retention: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StoredBlob {
+    pub checksum: String,
+    // bytes, so JS receives a Uint8Array instead of an array of numbers
+    #[serde(serialize_with = "serialize_bytes")]
+    pub content: Vec<u8>,
+}
+
+/// One batch of writes. Removing something the backend doesn't have is
+/// not an error.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct StorageChanges {
+    pub records: Vec<VirtualFile>,
+    pub removed_records: Vec<String>,
+    pub blobs: Vec<StoredBlob>,
+    pub removed_blobs: Vec<String>,
+    /// The full directory list, when it changed.
+    pub directories: Option<Vec<String>>,
+    /// The encryption header, once encryption is enabled.
+    pub encryption: Option<EncryptionHeader>,
+    /// All settings, when any of them changed.
+    #[serde(default)]
+    pub settings: Option<StoredSettings>,
+}
+
+impl StorageChanges {
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+            && self.removed_records.is_empty()
+            && self.blobs.is_empty()
+            && self.removed_blobs.is_empty()
+            && self.directories.is_none()
+            && self.encryption.is_none()
+            && self.settings.is_none()
+    }
+}
+
+pub trait StorageBackend {
+    fn load(&mut self) -> VfsResult<StoredState>;
+
+    fn get_blob(&mut self, checksum: &str) -> VfsResult<Option<Vec<u8>>>;
+
+    fn put_record(&mut self, file: &VirtualFile) -> VfsResult<()>;
+
+    fn remove_record(&mut self, id: &str) -> VfsResult<()>;
+
+    /// Content is addressed by checksum, so a blob that already exists
+    /// holds the same bytes and need not be written again.
+    fn put_blob(&mut self, checksum: &str, content: &[u8]) -> VfsResult<()>;
+
+    fn remove_blob(&mut self, checksum: &str) -> VfsResult<()>;
+
+    fn put_directories(&mut self, directories: &[String]) -> VfsResult<()>;
+
+    /// Encrypted content can't be read back without this, so it must be
+    /// stored as reliably as the content itself.
+    fn put_encryption(&mut self, header: &EncryptionHeader) -> VfsResult<()>;
+
+    fn put_settings(&mut self, settings: &StoredSettings) -> VfsResult<()>;
+
+    /// Writes a batch. Content is added first and removed last, so stored
+    /// records never point at content the backend doesn't have, and the
+    /// encryption header goes before any content sealed with its key.
+    fn apply(&mut self, changes: &StorageChanges) -> VfsResult<()> {
+        if let Some(header) = &changes.encryption {
+            self.put_encryption(header)?;
+        }
+        for blob in &changes.blobs {
+            self.put_blob(&blob.checksum, &blob.content)?;
+        }
+        for file in &changes.records {
+            self.put_record(file)?;
+        }
+        for id in &changes.removed_records {
+            self.remove_record(id)?;
+        }
+        for checksum in &changes.removed_blobs {
+            self.remove_blob(checksum)?;
+        }
+        if let Some(directories) = &changes.directories {
+            self.put_directories(directories)?;
+        }
+        if let Some(settings) = &changes.settings {
+            self.put_settings(settings)?;
+        }
+        Ok(())
+    }
+}
+
+/// `StorageBackend` for stores that can only be reached asynchronously.
+/// The futures don't need to be `Send`, wasm is single threaded.
+#[allow(async_fn_in_trait)]
+pub trait AsyncStorageBackend {
+    async fn load(&mut self) -> VfsResult<StoredState>;
+
+    async fn get_blob(&mut self, checksum: &str) -> VfsResult<Option<Vec<u8>>>;
+
+    async fn apply(&mut self, changes: &StorageChanges) -> VfsResult<()>;
+}
+
+/// What has been written so far and what still has to be.
+#[derive(Default)]
+pub(crate) struct Storage {
+    backend: Option<Box<dyn StorageBackend>>,
+    // off until the file system is opened on a backend
+    tracking: bool,
+    dirty: BTreeSet<String>,
+    stored_blobs: HashSet<String>,
+    stored_directories: BTreeSet<String>,
+    stored_encryption: Option<EncryptionHeader>,
+    settings_changed: bool,
+}
+
+impl Storage {
+    pub(crate) fn mark_dirty(&mut self, id: &str) {
+        if self.tracking {
+            self.dirty.insert(id.to_string());
+        }
+    }
+
+    pub(crate) fn mark_settings_changed(&mut self) {
+        if self.tracking {
+            self.settings_changed = true;
+        }
+    }
+
+    pub(crate) fn load_blob(&mut self, checksum: &str) -> VfsResult<Option<Vec<u8>>> {
+        match (self.backend.as_mut(), self.stored_blobs.contains(checksum)) {
+            (Some(backend), true) => backend.get_blob(checksum),
+            _ => Ok(None),
+        }
+    }
+
+    fn written(&mut self, changes: &StorageChanges) {
+        for file in &changes.records {
+            self.dirty.remove(&file.id);
+        }
+        for id in &changes.removed_records {
+            self.dirty.remove(id);
+        }
+        self.stored_blobs.extend(changes.blobs.iter().map(|blob| blob.checksum.clone()));
+        for checksum in &changes.removed_blobs {
+            self.stored_blobs.remove(checksum);
+        }
+        if let Some(directories) = &changes.directories {
+            self.stored_directories = directories.iter().cloned().collect();
+        }
+        if let Some(header) = &changes.encryption {
+            self.stored_encryption = Some(header.clone());
+        }
+        if changes.settings.is_some() {
+            self.settings_changed = false;
+        }
+    }
+}
+
+impl VirtualFileSystem {
+    /// Opens the file system stored in `backend`. Records are loaded right
+    /// away, content when it is first read.
+    pub fn open(mut backend: Box<dyn StorageBackend>) -> VfsResult<Self> {
+        let mut vfs = Self::from_stored(backend.load()?)?;
+        vfs.storage.backend = Some(backend);
+        Ok(vfs)
+    }
+
+    /// Opens the file system stored in an async backend. Content can't be
+    /// fetched lazily from a synchronous read, so all of it is loaded here.
+    pub async fn open_async(backend: &mut impl AsyncStorageBackend) -> VfsResult<Self> {
+        let mut vfs = Self::from_stored(backend.load().await?)?;
+
+        let checksums: Vec<String> = vfs.storage.stored_blobs.iter().cloned().collect();
+        for checksum in checksums {
+            if let Some(content) = backend.get_blob(&checksum).await? {
+                vfs.blobs.restore(&checksum, content)?;
+            }
+        }
+        Ok(vfs)
+    }
+
+    pub fn has_backend(&self) -> bool {
+        self.storage.backend.is_some()
+    }
+
+    /// Writes pending changes to the backend. Operations that change files
+    /// do this themselves; call it after changing a setting, or to retry
+    /// after a write failed. Does nothing inside a transaction.
+    pub fn flush(&mut self) -> VfsResult<()> {
+        if self.storage.backend.is_none() || self.transaction.is_some() {
+            return Ok(());
+        }
+
+        let changes = self.collect_changes()?;
+        if changes.is_empty() {
+            return Ok(());
+        }
+        if let Some(backend) = self.storage.backend.as_mut() {
+            backend.apply(&changes)?;
+        }
+        self.written(&changes);
+        Ok(())
+    }
+
+    /// Collects every change not written yet and counts it as written, for
+    /// hosts that store the changes themselves. Only tracked after
+    /// `open` or `open_async`.
+    pub fn take_changes(&mut self) -> VfsResult<StorageChanges> {
+        let changes = self.collect_changes()?;
+        self.written(&changes);
+        Ok(changes)
+    }
+
+    /// Writes pending changes to an async backend. If that fails they stay
+    /// pending for the next call.
+    pub async fn sync_async(&mut self, backend: &mut impl AsyncStorageBackend) -> VfsResult<()> {
+        let changes = self.collect_changes()?;
+        if changes.is_empty() {
+            return Ok(());
+        }
+        backend.apply(&changes).await?;
+        self.written(&changes);
+        Ok(())
+    }
+
+    /// Counts every record, blob and the directory list as not written, so
+    /// the next write stores all of it again. For hosts that lost a batch
+    /// returned by `take_changes`.
+    pub fn mark_all_changed(&mut self) {
+        if self.storage.tracking {
+            self.storage.dirty.extend(self.files.keys().chain(self.trash.keys()).cloned());
+            self.storage.stored_blobs.clear();
+            self.blobs.pin_resident();
+            self.storage.stored_directories.clear();
+            self.storage.stored_encryption = None;
+            self.storage.settings_changed = true;
+        }
+    }
+
+    fn from_stored(state: StoredState) -> VfsResult<Self> {
+        let mut vfs = VirtualFileSystem::new();
+        vfs.directories.extend(state.directories);
+        vfs.encryption.header = state.encryption.clone();
+        if let Some(settings) = state.settings {
+            vfs.apply_settings(settings);
+        }
+
+        for file in state.records {
+            if vfs.files.contains_key(&file.id) || vfs.trash.contains_key(&file.id) {
+                return Err(VfsError::Storage { reason: format!("duplicate record id {}", file.id) });
+            }
+            for version in &file.versions {
+                if !vfs.blobs.retain(version.blob_key()) {
+                    // a sealed blob is the ciphertext, compressed inside
+                    let (size, compression) = match version.encrypted_blob {
+                        Some(_) => (version.stored_size, Compression::None),
+                        None => (version.size, version.compression),
+                    };
+                    vfs.blobs.insert_evicted(version.blob_key().to_string(), size, compression);
+                }
+            }
+            if file.deleted_at.is_some() {
+                vfs.trash.insert(file.id.clone(), file);
+                continue;
+            }
+            if vfs.paths.insert(file.path.clone(), file.id.clone()).is_some() {
+                return Err(VfsError::Storage { reason: format!("duplicate path {}", file.path) });
+            }
+            vfs.files.insert(file.id.clone(), file);
+        }
+        check_tree(&vfs.directories, vfs.files.values().chain(vfs.trash.values()))
+            .map_err(|reason| VfsError::Storage { reason })?;
+
+        vfs.storage.tracking = true;
+        vfs.blobs.set_pin_new(true);
+        vfs.storage.stored_blobs = vfs.blobs.iter().map(|(checksum, ..)| checksum.to_string()).collect();
+        vfs.storage.stored_directories = vfs.directories.clone();
+        vfs.storage.stored_encryption = state.encryption;
+        Ok(vfs)
+    }
+
+    // Counts a batch as written and lets the memory budget evict the
+    // content it saved.
+    fn written(&mut self, changes: &StorageChanges) {
+        self.storage.written(changes);
+        let stored = &self.storage.stored_blobs;
+        self.blobs.unpin(|checksum| stored.contains(checksum));
+    }
+
+    fn collect_changes(&mut self) -> VfsResult<StorageChanges> {
+        let mut changes = StorageChanges::default();
+        if !self.storage.tracking {
+            return Ok(changes);
+        }
+        let mut new_blobs = Vec::new();
+        let mut seen = HashSet::new();
+
+        for id in &self.storage.dirty {
+            let Some(file) = self.files.get(id).or_else(|| self.trash.get(id)) else {
+                changes.removed_records.push(id.clone());
+                continue;
+            };
+            for version in &file.versions {
+                let key = version.blob_key();
+                if !self.storage.stored_blobs.contains(key) && seen.insert(key) {
+                    new_blobs.push((id.clone(), key.to_string()));
+                }
+            }
+            changes.records.push(file.clone());
+        }
+
+        for (id, checksum) in new_blobs {
+            let content = self.load_blob(&id, &checksum)?.into_owned();
+            changes.blobs.push(StoredBlob { checksum, content });
+        }
+        changes.removed_blobs = self.storage.stored_blobs.iter()
+            .filter(|checksum| !self.blobs.contains(checksum))
+            .cloned()
+            .collect();
+        if self.storage.stored_directories != self.directories {
+            changes.directories = Some(self.directories.iter().cloned().collect());
+        }
+        if self.storage.stored_encryption != self.encryption.header {
+            changes.encryption = self.encryption.header.clone();
+        }
+        if self.storage.settings_changed {
+            changes.settings = Some(self.settings());
+        }
+        Ok(changes)
+    }
+
+    pub(crate) fn settings(&self) -> StoredSettings {
+        StoredSettings {
+            max_versions: self.max_versions,
+            metadata_schemas: self.metadata_schemas.clone(),
+            cascade_rules: self.cascade_rules.iter().map(|(kind, rule)| (kind.clone(), *rule)).collect(),
+            compression: self.compression.clone(),
+            trash_retention: self.trash_retention,
+        }
+    }
+
+    pub(crate) fn apply_settings(&mut self, settings: StoredSettings) {
+        self.max_versions = settings.max_versions.max(1);
+        self.metadata_schemas = settings.metadata_schemas;
+        self.cascade_rules = settings.cascade_rules.into_iter().collect();
+        self.compression = settings.compression;
+        self.trash_retention = settings.trash_retention;
+        self.storage.mark_settings_changed();
+    }
+}
+
+fn serialize_bytes<S: Serializer>(content: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(content)
+}
+
+/// Keeps everything in memory. Clones share the same contents, so a second
+/// file system can be opened on what the first one wrote.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    contents: Rc<RefCell<MemoryContents>>,
+}
+
+#[derive(Default)]
+struct MemoryContents {
+    records: HashMap<String, VirtualFile>,
+    blobs: HashMap<String, Vec<u8>>,
+    directories: Vec<String>,
+    encryption: Option<EncryptionHeader>,
+    settings: Option<StoredSettings>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.contents.borrow().records.len()
+    }
+
+    pub fn blob_count(&self) -> usize {
+        self.contents.borrow().blobs.len()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn load(&mut self) -> VfsResult<StoredState> {
+        let contents = self.contents.borrow();
+        Ok(StoredState {
+            records: contents.records.values().cloned().collect(),
+            directories: contents.directories.clone(),
+            encryption: contents.encryption.clone(),
+            settings: contents.settings.clone(),
+        })
+    }
+
+    fn get_blob(&mut self, checksum: &str) -> VfsResult<Option<Vec<u8>>> {
+        Ok(self.contents.borrow().blobs.get(checksum).cloned())
+    }
+
+    fn put_record(&mut self, file: &VirtualFile) -> VfsResult<()> {
+        self.contents.borrow_mut().records.insert(file.id.clone(), file.clone());
+        Ok(())
+    }
+
+    fn remove_record(&mut self, id: &str) -> VfsResult<()> {
+        self.contents.borrow_mut().records.remove(id);
+        Ok(())
+    }
+
+    fn put_blob(&mut self, checksum: &str, content: &[u8]) -> VfsResult<()> {
+        self.contents.borrow_mut().blobs
+            .entry(checksum.to_string())
+            .or_insert_with(|| content.to_vec());
+        Ok(())
+    }
+
+    fn remove_blob(&mut self, checksum: &str) -> VfsResult<()> {
+        self.contents.borrow_mut().blobs.remove(checksum);
+        Ok(())
+    }
+
+    fn put_directories(&mut self, directories: &[String]) -> VfsResult<()> {
+        self.contents.borrow_mut().directories = directories.to_vec();
+        Ok(())
+    }
+
+    fn put_encryption(&mut self, header: &EncryptionHeader) -> VfsResult<()> {
+        self.contents.borrow_mut().encryption = Some(header.clone());
+        Ok(())
+    }
+
+    fn put_settings(&mut self, settings: &StoredSettings) -> VfsResult<()> {
+        self.contents.borrow_mut().settings = Some(settings.clone());
+        Ok(())
+    }
+}
+
+/// Keeps a file system in a directory on disk:
+///
+///   <root>/records/<id>.json   one JSON file per record
+///   <root>/blobs/<checksum>    raw content
+///   <root>/directories.json
+///   <root>/encryption.json     once encryption is enabled
+///   <root>/settings.json       once a setting is changed
+///
+/// Files are written to a temporary name and renamed into place, so a
+/// crash leaves either the old or the new version.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct DirectoryBackend {
+    root: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DirectoryBackend {
+    pub fn new(root: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(root.join("records"))?;
+        std::fs::create_dir_all(root.join("blobs"))?;
+        Ok(Self { root })
+    }
+
+    // Ids and checksums become file names, so they must not reach outside
+    // their directory.
+    fn entry(&self, dir: &str, name: &str, extension: &str) -> VfsResult<std::path::PathBuf> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(VfsError::Storage { reason: format!("unusable storage key {:?}", name) });
+        }
+        Ok(self.root.join(dir).join(format!("{}{}", name, extension)))
+    }
+
+    fn write(path: &std::path::Path, content: &[u8]) -> VfsResult<()> {
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, content)?;
+        std::fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    fn remove(path: &std::path::Path) -> VfsResult<()> {
+        match std::fs::remove_file(path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StorageBackend for DirectoryBackend {
+    fn load(&mut self) -> VfsResult<StoredState> {
+        let mut state = StoredState::default();
+
+        for entry in std::fs::read_dir(self.root.join("records"))? {
+            let entry_path = entry?.path();
+            if entry_path.extension().is_some_and(|extension| extension == "json") {
+                let record = serde_json::from_slice(&std::fs::read(&entry_path)?)
+                    .map_err(|e| VfsError::Storage { reason: format!("malformed record {}: {}", entry_path.display(), e) })?;
+                state.records.push(record);
+            }
+        }
+
+        match std::fs::read(self.root.join("directories.json")) {
+            Ok(content) => {
+                state.directories = serde_json::from_slice(&content)
+                    .map_err(|e| VfsError::Storage { reason: format!("malformed directory list: {}", e) })?;
+            }
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(error.into()),
+            Err(_) => {}
+        }
+
+        match std::fs::read(self.root.join("encryption.json")) {
+            Ok(content) => {
+                state.encryption = serde_json::from_slice(&content)
+                    .map_err(|e| VfsError::Storage { reason: format!("malformed encryption header: {}", e) })?;
+            }
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(error.into()),
+            Err(_) => {}
+        }
+
+        match std::fs::read(self.root.join("settings.json")) {
+            Ok(content) => {
+                state.settings = serde_json::from_slice(&content)
+                    .map_err(|e| VfsError::Storage { reason: format!("malformed settings: {}", e) })?;
+            }
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(error.into()),
+            Err(_) => {}
+        }
+        Ok(state)
+    }
+
+    fn get_blob(&mut self, checksum: &str) -> VfsResult<Option<Vec<u8>>> {
+        match std::fs::read(self.entry("blobs", checksum, "")?) {
+            Ok(content) => Ok(Some(content)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn put_record(&mut self, file: &VirtualFile) -> VfsResult<()> {
+        let record = serde_json::to_vec(file)
+            .expect("file records are always serializable");
+        Self::write(&self.entry("records", &file.id, ".json")?, &record)
+    }
+
+    fn remove_record(&mut self, id: &str) -> VfsResult<()> {
+        Self::remove(&self.entry("records", id, ".json")?)
+    }
+
+    fn put_blob(&mut self, checksum: &str, content: &[u8]) -> VfsResult<()> {
+        let blob_path = self.entry("blobs", checksum, "")?;
+        if blob_path.exists() {
+            return Ok(());
+        }
+        Self::write(&blob_path, content)
+    }
+
+    fn remove_blob(&mut self, checksum: &str) -> VfsResult<()> {
+        Self::remove(&self.entry("blobs", checksum, "")?)
+    }
+
+    fn put_directories(&mut self, directories: &[String]) -> VfsResult<()> {
+        let list = serde_json::to_vec(directories)
+            .expect("directory lists are always serializable");
+        Self::write(&self.root.join("directories.json"), &list)
+    }
+
+    fn put_encryption(&mut self, header: &EncryptionHeader) -> VfsResult<()> {
+        let header = serde_json::to_vec(header)
+            .expect("encryption headers are always serializable");
+        Self::write(&self.root.join("encryption.json"), &header)
+    }
+
+    fn put_settings(&mut self, settings: &StoredSettings) -> VfsResult<()> {
+        let settings = serde_json::to_vec(settings)
+            .expect("settings are always serializable");
+        Self::write(&self.root.join("settings.json"), &settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+    use crate::core::{ContentState, MetadataType, MetadataValue, SupportedFileType};
+
+    // The test backends never wait, so polling once is enough.
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(value) => value,
+            Poll::Pending => panic!("test futures complete immediately"),
+        }
+    }
+
+    struct AsyncMemory {
+        inner: MemoryBackend,
+        fail: bool,
+    }
+
+    impl AsyncStorageBackend for AsyncMemory {
+        async fn load(&mut self) -> VfsResult<StoredState> {
+            self.inner.load()
+        }
+
+        async fn get_blob(&mut self, checksum: &str) -> VfsResult<Option<Vec<u8>>> {
+            self.inner.get_blob(checksum)
+        }
+
+        async fn apply(&mut self, changes: &StorageChanges) -> VfsResult<()> {
+            if self.fail {
+                return Err(VfsError::Storage { reason: "offline".to_string() });
+            }
+            self.inner.apply(changes)
+        }
+    }
+
+    #[test]
+    fn test_directory_backend_loads_content_lazily() {
+        let dir = std::env::temp_dir().join(format!("vfs-storage-{}", uuid::Uuid::new_v4()));
+        let mut vfs = VirtualFileSystem::open(Box::new(DirectoryBackend::new(&dir).unwrap())).unwrap();
+        let id = vfs.create_file_at("/data/a.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+        vfs.update_file(&id, Some(b"a\n2".to_vec())).unwrap();
+        let gone = vfs.create_file_at("/b.txt", SupportedFileType::Text, b"b".to_vec()).unwrap();
+        vfs.delete_file(&gone).unwrap();
+        assert_eq!(std::fs::read_dir(dir.join("blobs")).unwrap().count(), 2);
+
+        let mut reopened = VirtualFileSystem::open(Box::new(DirectoryBackend::new(&dir).unwrap())).unwrap();
+        assert!(reopened.has_backend());
+        assert_eq!(reopened.list_files().len(), 1);
+        assert_eq!(reopened.content_state(&id).unwrap(), ContentState::Evicted);
+        assert_eq!(reopened.get_file_content(&id).unwrap(), b"a\n2".to_vec());
+        assert_eq!(reopened.read_version(&id, 1).unwrap(), b"a\n1".to_vec());
+        assert_eq!(reopened.content_state(&id).unwrap(), ContentState::Resident);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_transactions_write_on_commit() {
+        let backend = MemoryBackend::new();
+        let mut vfs = VirtualFileSystem::open(Box::new(backend.clone())).unwrap();
+
+        vfs.begin_transaction().unwrap();
+        vfs.create_file_at("/a.txt", SupportedFileType::Text, b"a".to_vec()).unwrap();
+        assert_eq!(backend.record_count(), 0);
+        vfs.rollback_transaction().unwrap();
+        vfs.flush().unwrap();
+        assert_eq!((backend.record_count(), backend.blob_count()), (0, 0));
+
+        vfs.transaction(|tx| tx.create_file_at("/b.txt", SupportedFileType::Text, b"b".to_vec())).unwrap();
+        assert_eq!((backend.record_count(), backend.blob_count()), (1, 1));
+    }
+
+    #[test]
+    fn test_settings_survive_reopen() {
+        let backend = MemoryBackend::new();
+        let mut vfs = VirtualFileSystem::open(Box::new(backend.clone())).unwrap();
+        vfs.set_max_versions(3);
+        vfs.set_metadata_schema("rows", Some(MetadataSchema::new(MetadataType::Number)));
+        vfs.set_cascade_rule(LinkKind::Custom("copy_of".to_string()), CascadeRule::Restrict);
+        vfs.set_compression_policy(CompressionPolicy::new(Compression::Lz4));
+        vfs.set_trash_retention(Some(60_000)).unwrap();
+        assert!(vfs.take_changes().unwrap().is_empty());
+
+        let reopened = VirtualFileSystem::open(Box::new(backend.clone())).unwrap();
+        assert_eq!(reopened.settings(), vfs.settings());
+        assert_eq!(reopened.max_versions(), 3);
+        assert_eq!(reopened.trash_retention(), Some(60_000));
+
+        // a store written before settings were kept opens with the defaults
+        backend.contents.borrow_mut().settings = None;
+        let reopened = VirtualFileSystem::open(Box::new(backend)).unwrap();
+        assert_eq!(reopened.settings(), VirtualFileSystem::new().settings());
+    }
+
+    #[test]
+    fn test_async_backend_round_trip() {
+        let mut backend = AsyncMemory { inner: MemoryBackend::new(), fail: false };
+        let mut vfs = block_on(VirtualFileSystem::open_async(&mut backend)).unwrap();
+        let id = vfs.create_file_at("/a.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+        vfs.set_file_metadata(&id, "rows".to_string(), 1i64).unwrap();
+
+        backend.fail = true;
+        assert!(block_on(vfs.sync_async(&mut backend)).is_err());
+        assert_eq!(backend.inner.record_count(), 0);
+        backend.fail = false;
+        block_on(vfs.sync_async(&mut backend)).unwrap();
+        assert!(vfs.take_changes().unwrap().is_empty());
+
+        let mut reopened = block_on(VirtualFileSystem::open_async(&mut backend)).unwrap();
+        assert_eq!(reopened.content_state(&id).unwrap(), ContentState::Resident);
+        assert_eq!(reopened.get_file_content(&id).unwrap(), b"a\n1".to_vec());
+        assert_eq!(reopened.get_file_metadata(&id, "rows").unwrap(), MetadataValue::Number(1.0));
+    }
+
+    #[test]
+    fn test_budget_keeps_unsaved_content() {
+        let mut backend = AsyncMemory { inner: MemoryBackend::new(), fail: false };
+        let mut vfs = block_on(VirtualFileSystem::open_async(&mut backend)).unwrap();
+        vfs.set_memory_budget(Some(10)).unwrap();
+        let a = vfs.create_file_at("/a.bin", SupportedFileType::Text, vec![1; 8]).unwrap();
+        let b = vfs.create_file_at("/b.bin", SupportedFileType::Text, vec![2; 8]).unwrap();
+        assert_eq!(vfs.content_state(&a).unwrap(), ContentState::Resident);
+
+        backend.fail = true;
+        assert!(block_on(vfs.sync_async(&mut backend)).is_err());
+        backend.fail = false;
+        block_on(vfs.sync_async(&mut backend)).unwrap();
+        assert_eq!((backend.inner.record_count(), backend.inner.blob_count()), (2, 2));
+        // saved content is evictable again
+        assert_eq!(vfs.storage_stats().evicted_blob_count, 1);
+        assert_eq!(vfs.storage_stats().stored_bytes, 8);
+
+        let mut reopened = block_on(VirtualFileSystem::open_async(&mut backend)).unwrap();
+        assert_eq!(reopened.get_file_content(&a).unwrap(), vec![1; 8]);
+        assert_eq!(reopened.get_file_content(&b).unwrap(), vec![2; 8]);
+    }
+
+    #[test]
+    fn test_budget_keeps_content_of_open_transactions() {
+        let backend = MemoryBackend::new();
+        let mut vfs = VirtualFileSystem::open(Box::new(backend.clone())).unwrap();
+        vfs.set_memory_budget(Some(10)).unwrap();
+
+        vfs.begin_transaction().unwrap();
+        let a = vfs.create_file_at("/a.bin", SupportedFileType::Text, vec![1; 8]).unwrap();
+        vfs.create_file_at("/b.bin", SupportedFileType::Text, vec![2; 8]).unwrap();
+        vfs.commit_transaction().unwrap();
+        assert_eq!((backend.record_count(), backend.blob_count()), (2, 2));
+
+        let mut reopened = VirtualFileSystem::open(Box::new(backend)).unwrap();
+        assert_eq!(reopened.list_files().len(), 2);
+        assert_eq!(reopened.get_file_content(&a).unwrap(), vec![1; 8]);
+
+        // without a backend the content is only kept until commit
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_memory_budget(Some(10)).unwrap();
+        vfs.begin_transaction().unwrap();
+        let a = vfs.create_file_at("/a.bin", SupportedFileType::Text, vec![1; 8]).unwrap();
+        vfs.create_file_at("/b.bin", SupportedFileType::Text, vec![2; 8]).unwrap();
+        assert_eq!(vfs.content_state(&a).unwrap(), ContentState::Resident);
+        vfs.commit_transaction().unwrap();
+        assert_eq!(vfs.content_state(&a).unwrap(), ContentState::Evicted);
+    }
+
+    #[test]
+    fn test_open_rejects_unsound_paths() {
+        let backend = MemoryBackend::new();
+        let mut vfs = VirtualFileSystem::open(Box::new(backend.clone())).unwrap();
+        let id = vfs.create_file_at("/data/a.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+        let file = vfs.read_file(&id).unwrap().clone();
+
+        for (path, name) in [("/../escaped.txt", "escaped.txt"), ("/data//a.csv", "a.csv"), ("/data/a.csv", "b.csv"), ("/elsewhere/a.csv", "a.csv")] {
+            let mut forged = backend.clone();
+            forged.put_record(&VirtualFile { path: path.to_string(), name: name.to_string(), ..file.clone() }).unwrap();
+            let error = VirtualFileSystem::open(Box::new(forged)).err();
+            assert!(matches!(error, Some(VfsError::Storage { .. })), "{} opened: {:?}", path, error);
+        }
+    }
+
+    #[test]
+    fn test_open_rejects_duplicate_ids() {
+        let dir = std::env::temp_dir().join(format!("vfs-storage-{}", uuid::Uuid::new_v4()));
+        let mut vfs = VirtualFileSystem::open(Box::new(DirectoryBackend::new(&dir).unwrap())).unwrap();
+        let id = vfs.create_file_at("/a.txt", SupportedFileType::Text, b"a".to_vec()).unwrap();
+
+        // a second record file holding the same id, once in the tree and once trashed
+        let record: VirtualFile = serde_json::from_slice(&std::fs::read(dir.join("records").join(format!("{}.json", id))).unwrap()).unwrap();
+        for copy in [VirtualFile { path: "/b.txt".to_string(), name: "b.txt".to_string(), ..record.clone() }, VirtualFile { deleted_at: Some(1), ..record }] {
+            std::fs::write(dir.join("records/copy.json"), serde_json::to_vec(&copy).unwrap()).unwrap();
+            let error = VirtualFileSystem::open(Box::new(DirectoryBackend::new(&dir).unwrap())).err();
+            assert!(matches!(error, Some(VfsError::Storage { .. })), "opened: {:?}", error);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
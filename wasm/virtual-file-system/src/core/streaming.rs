@@ -0,0 +1,185 @@
+// core/streaming.rs
+//
+// Partial reads and writes, plus chunked uploads so the JS side can feed
+// large files in `File.slice()` sized pieces instead of one huge buffer.
+use std::borrow::Cow;
+use sha2::{Sha256, Digest};
+use uuid::Uuid;
+
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::file_system::{SupportedFileType, VirtualFileSystem};
+use crate::core::provenance::Provenance;
+
+/// A file being uploaded chunk by chunk. The checksum is computed as the
+/// chunks arrive, so finishing the upload never re-reads the content.
+pub struct PendingUpload {
+    path: String,
+    file_type: SupportedFileType,
+    hasher: Sha256,
+    buffer: Vec<u8>,
+}
+
+impl VirtualFileSystem {
+    /// Reads up to `len` bytes starting at `offset`. Reading past the end
+    /// returns the bytes that exist; starting past the end is an error.
+    pub fn read_range(&mut self, id: &str, offset: usize, len: usize) -> VfsResult<Vec<u8>> {
+        self.touch_accessed(id);
+        let content = self.content_slice(id)?;
+        if offset > content.len() {
+            return Err(VfsError::OutOfRange { offset, size: content.len() });
+        }
+
+        let end = offset.saturating_add(len).min(content.len());
+        Ok(content[offset..end].to_vec())
+    }
+
+    /// Appends bytes to the end of a file, recording a new version.
+    pub fn append(&mut self, id: &str, bytes: &[u8]) -> VfsResult<u32> {
+        let current = self.content_slice(id)?;
+
+        let mut content = Vec::with_capacity(current.len() + bytes.len());
+        content.extend_from_slice(&current);
+        content.extend_from_slice(bytes);
+        self.update_file_with_message(id, content, None)
+    }
+
+    /// Overwrites bytes starting at `offset`, growing the file if the write
+    /// runs past the end. `offset` may be at most the current size.
+    pub fn write_at(&mut self, id: &str, offset: usize, bytes: &[u8]) -> VfsResult<u32> {
+        let current = self.content_slice(id)?;
+        if offset > current.len() {
+            return Err(VfsError::OutOfRange { offset, size: current.len() });
+        }
+
+        let mut content = current.into_owned();
+        let overlap = (content.len() - offset).min(bytes.len());
+        content[offset..offset + overlap].copy_from_slice(&bytes[..overlap]);
+        content.extend_from_slice(&bytes[overlap..]);
+        self.update_file_with_message(id, content, None)
+    }
+
+    /// Starts a chunked upload of a new file at `file_path` and returns an
+    /// upload id for `push_chunk` / `finish_upload`.
+    pub fn begin_upload(&mut self, file_path: &str, file_type: SupportedFileType) -> VfsResult<String> {
+        let file_path = self.check_new_file_path(file_path)?;
+        let upload_id = Uuid::new_v4().to_string();
+
+        self.uploads.insert(upload_id.clone(), PendingUpload {
+            path: file_path,
+            file_type,
+            hasher: Sha256::new(),
+            buffer: Vec::new(),
+        });
+        Ok(upload_id)
+    }
+
+    /// Adds the next chunk to an upload. Returns the bytes received so far.
+    pub fn push_chunk(&mut self, upload_id: &str, chunk: &[u8]) -> VfsResult<usize> {
+        let upload = self.uploads.get_mut(upload_id)
+            .ok_or_else(|| VfsError::UploadNotFound { upload_id: upload_id.to_string() })?;
+
+        upload.hasher.update(chunk);
+        upload.buffer.extend_from_slice(chunk);
+        Ok(upload.buffer.len())
+    }
+
+    /// Completes an upload and creates the file, returning its id. If the
+    /// path was taken since the upload began, the upload is kept so the
+    /// caller can free the path and finish again, or abort.
+    pub fn finish_upload(&mut self, upload_id: &str) -> VfsResult<String> {
+        let upload = self.uploads.get(upload_id)
+            .ok_or_else(|| VfsError::UploadNotFound { upload_id: upload_id.to_string() })?;
+
+        // The path was free when the upload started but may have been taken since.
+        let file_path = self.check_new_file_path(&upload.path)?;
+        let upload = self.uploads.remove(upload_id).expect("looked up above");
+        let checksum = format!("{:x}", upload.hasher.finalize());
+        self.insert_file(file_path, upload.file_type, upload.buffer, checksum, Provenance::Upload)
+    }
+
+    /// Discards an upload and everything pushed to it.
+    pub fn abort_upload(&mut self, upload_id: &str) -> VfsResult<()> {
+        self.uploads.remove(upload_id)
+            .map(|_| ())
+            .ok_or_else(|| VfsError::UploadNotFound { upload_id: upload_id.to_string() })
+    }
+
+    fn content_slice(&mut self, id: &str) -> VfsResult<Cow<'_, [u8]>> {
+        let checksum = self.file(id)?.checksum.clone();
+        self.load_content(id, &checksum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{calculate_checksum, VirtualFileSystem, SupportedFileType, VfsError};
+
+    #[test]
+    fn test_read_range() {
+        let mut vfs = VirtualFileSystem::new();
+        let id = vfs.create_file("data.bin".to_string(), SupportedFileType::Other("bin".to_string()), vec![0, 1, 2, 3, 4]).unwrap();
+
+        assert_eq!(vfs.read_range(&id, 1, 2).unwrap(), vec![1, 2]);
+        assert_eq!(vfs.read_range(&id, 3, 100).unwrap(), vec![3, 4]);
+        assert!(vfs.read_range(&id, 5, 1).unwrap().is_empty());
+        assert!(matches!(vfs.read_range(&id, 6, 1), Err(VfsError::OutOfRange { offset: 6, size: 5 })));
+    }
+
+    #[test]
+    fn test_append_and_write_at() {
+        let mut vfs = VirtualFileSystem::new();
+        let id = vfs.create_file("log.csv".to_string(), SupportedFileType::CSV, b"a,b\n".to_vec()).unwrap();
+
+        vfs.append(&id, b"1,2\n").unwrap();
+        assert_eq!(vfs.get_file_content(&id).unwrap(), b"a,b\n1,2\n".to_vec());
+
+        vfs.write_at(&id, 4, b"9").unwrap();
+        assert_eq!(vfs.get_file_content(&id).unwrap(), b"a,b\n9,2\n".to_vec());
+
+        vfs.write_at(&id, 6, b"22\n").unwrap();
+        assert_eq!(vfs.get_file_content(&id).unwrap(), b"a,b\n9,22\n".to_vec());
+
+        let file = vfs.read_file(&id).unwrap();
+        assert_eq!(file.size, 9);
+        assert_eq!(file.checksum, calculate_checksum(b"a,b\n9,22\n"));
+        assert_eq!(file.current_version(), 4);
+        assert!(vfs.write_at(&id, 10, b"x").is_err());
+    }
+
+    #[test]
+    fn test_chunked_upload() {
+        let mut vfs = VirtualFileSystem::new();
+        let upload = vfs.begin_upload("/videos/demo.mov", SupportedFileType::Video).unwrap();
+        assert_eq!(vfs.push_chunk(&upload, b"hello ").unwrap(), 6);
+        assert_eq!(vfs.push_chunk(&upload, b"world").unwrap(), 11);
+
+        let id = vfs.finish_upload(&upload).unwrap();
+        let file = vfs.read_file_at("/videos/demo.mov").unwrap();
+        assert_eq!(file.id, id);
+        assert_eq!(file.checksum, calculate_checksum(b"hello world"));
+        assert_eq!(vfs.get_file_content(&id).unwrap(), b"hello world".to_vec());
+
+        assert!(matches!(vfs.push_chunk(&upload, b"!"), Err(VfsError::UploadNotFound { .. })));
+        assert!(vfs.begin_upload("/videos/demo.mov", SupportedFileType::Video).is_err());
+    }
+
+    #[test]
+    fn test_upload_conflicts_and_abort() {
+        let mut vfs = VirtualFileSystem::new();
+        let upload = vfs.begin_upload("/a.csv", SupportedFileType::CSV).unwrap();
+        vfs.push_chunk(&upload, b"x").unwrap();
+        vfs.create_file_at("/a.csv", SupportedFileType::CSV, vec![]).unwrap();
+        assert!(matches!(vfs.finish_upload(&upload), Err(VfsError::AlreadyExists { .. })));
+
+        // the upload survives the conflict
+        vfs.move_path("/a.csv", "/old.csv").unwrap();
+        let id = vfs.finish_upload(&upload).unwrap();
+        assert_eq!(vfs.get_file_content(&id).unwrap(), b"x");
+        assert_eq!(vfs.resolve_path("/a.csv").unwrap(), id);
+
+        let upload = vfs.begin_upload("/b.csv", SupportedFileType::CSV).unwrap();
+        vfs.abort_upload(&upload).unwrap();
+        assert!(vfs.finish_upload(&upload).is_err());
+        assert!(!vfs.exists("/b.csv"));
+    }
+}
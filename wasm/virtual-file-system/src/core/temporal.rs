@@ -0,0 +1,218 @@
+// core/temporal.rs
+//
+// Date and time detection for CSV profiling. A column starts out with
+// every format in `FORMATS` as a candidate and drops each one a value
+// doesn't parse with, so after the first value of a text column there is
+// usually nothing left to try. The first candidate still standing at the
+// end wins, which makes the order of `FORMATS` the order of preference:
+// ISO 8601 first, month-first before day-first, so a column of
+// `01/02/2024`s reads as US dates until a `13/02/2024` shows up in it.
+//
+// Integers between 1990 and 2100 as Unix seconds or milliseconds parse
+// too, but only count in a column whose name says it holds times
+// (`created_at`, `timestamp`…); ids and amounts of that size are far more
+// common than unnamed timestamps.
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::core::types::{DataType, DateStats};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Date,
+    Time,
+    DateTime,
+    DateTimeTz,
+    EpochSeconds,
+    EpochMillis,
+}
+
+const FORMATS: &[(&str, Kind)] = &[
+    ("%Y-%m-%d", Kind::Date),
+    ("%Y/%m/%d", Kind::Date),
+    ("%m/%d/%Y", Kind::Date),
+    ("%d/%m/%Y", Kind::Date),
+    ("%m-%d-%Y", Kind::Date),
+    ("%d-%m-%Y", Kind::Date),
+    ("%d.%m.%Y", Kind::Date),
+    ("%m/%d/%y", Kind::Date),
+    ("%d/%m/%y", Kind::Date),
+    ("%d.%m.%y", Kind::Date),
+    ("%d %b %Y", Kind::Date),
+    ("%b %d, %Y", Kind::Date),
+    ("%H:%M:%S", Kind::Time),
+    ("%H:%M:%S%.f", Kind::Time),
+    ("%H:%M", Kind::Time),
+    ("%I:%M %p", Kind::Time),
+    ("%I:%M:%S %p", Kind::Time),
+    ("%Y-%m-%dT%H:%M:%S", Kind::DateTime),
+    ("%Y-%m-%dT%H:%M:%S%.f", Kind::DateTime),
+    ("%Y-%m-%dT%H:%M", Kind::DateTime),
+    ("%Y-%m-%d %H:%M:%S", Kind::DateTime),
+    ("%Y-%m-%d %H:%M:%S%.f", Kind::DateTime),
+    ("%Y-%m-%d %H:%M", Kind::DateTime),
+    ("%m/%d/%Y %H:%M:%S", Kind::DateTime),
+    ("%m/%d/%Y %H:%M", Kind::DateTime),
+    ("%m/%d/%Y %I:%M %p", Kind::DateTime),
+    ("%d/%m/%Y %H:%M:%S", Kind::DateTime),
+    ("%d/%m/%Y %H:%M", Kind::DateTime),
+    ("%d.%m.%Y %H:%M:%S", Kind::DateTime),
+    ("%d.%m.%Y %H:%M", Kind::DateTime),
+    ("%Y-%m-%dT%H:%M:%SZ", Kind::DateTimeTz),
+    ("%Y-%m-%dT%H:%M:%S%.fZ", Kind::DateTimeTz),
+    ("%Y-%m-%dT%H:%M:%S%:z", Kind::DateTimeTz),
+    ("%Y-%m-%dT%H:%M:%S%.f%:z", Kind::DateTimeTz),
+    ("%Y-%m-%d %H:%M:%S%:z", Kind::DateTimeTz),
+    ("%Y-%m-%d %H:%M:%S%.f%:z", Kind::DateTimeTz),
+    ("%s", Kind::EpochSeconds),
+    ("%s%3f", Kind::EpochMillis),
+];
+
+// 1990-01-01 and 2100-01-01 in Unix seconds.
+const EPOCH_RANGE: std::ops::RangeInclusive<i64> = 631_152_000..=4_102_444_800;
+
+/// The formats a column's values all parsed with so far, each with the
+/// range of the values as read with it.
+#[derive(Debug)]
+pub(crate) struct TemporalCandidates(Vec<Candidate>);
+
+#[derive(Debug)]
+struct Candidate {
+    format: &'static str,
+    kind: Kind,
+    range: Option<(NaiveDateTime, NaiveDateTime)>,
+}
+
+impl TemporalCandidates {
+    pub(crate) fn new() -> Self {
+        TemporalCandidates(FORMATS.iter()
+            .map(|&(format, kind)| Candidate { format, kind, range: None })
+            .collect())
+    }
+
+    /// Takes a non-empty value.
+    pub(crate) fn add(&mut self, value: &str) {
+        let value = value.trim();
+        if !value.bytes().any(|byte| byte.is_ascii_digit()) {
+            self.0.clear();
+            return;
+        }
+        self.0.retain_mut(|candidate| {
+            let Some(parsed) = parse(value, candidate.format, candidate.kind) else {
+                return false;
+            };
+            candidate.range = Some(match candidate.range {
+                Some((min, max)) => (min.min(parsed), max.max(parsed)),
+                None => (parsed, parsed),
+            });
+            true
+        });
+    }
+
+    /// The type and stats of a column named `column_name`, if all of its
+    /// values were dates or times in one format.
+    pub(crate) fn resolve(&self, column_name: &str) -> Option<(DataType, DateStats)> {
+        let candidate = self.0.iter().find(|candidate| {
+            !matches!(candidate.kind, Kind::EpochSeconds | Kind::EpochMillis) || names_a_time(column_name)
+        })?;
+        let (min, max) = candidate.range?;
+
+        let (data_type, iso_format) = match candidate.kind {
+            Kind::Date => (DataType::Date, "%Y-%m-%d"),
+            Kind::Time => (DataType::Time, "%H:%M:%S%.f"),
+            Kind::DateTime => (DataType::DateTime, "%Y-%m-%dT%H:%M:%S%.f"),
+            Kind::DateTimeTz | Kind::EpochSeconds | Kind::EpochMillis => (DataType::DateTimeTz, "%Y-%m-%dT%H:%M:%S%.fZ"),
+        };
+        Some((data_type, DateStats {
+            format: candidate.format.to_string(),
+            min: min.format(iso_format).to_string(),
+            max: max.format(iso_format).to_string(),
+        }))
+    }
+}
+
+// Times land on 1970-01-01 and offsets are applied, so that values of any
+// kind compare as one timeline.
+fn parse(value: &str, format: &str, kind: Kind) -> Option<NaiveDateTime> {
+    let parsed = match kind {
+        Kind::Date => NaiveDate::parse_from_str(value, format).ok()?.and_time(NaiveTime::MIN),
+        Kind::Time => NaiveDate::from_ymd_opt(1970, 1, 1)?.and_time(NaiveTime::parse_from_str(value, format).ok()?),
+        Kind::DateTime => NaiveDateTime::parse_from_str(value, format).ok()?,
+        Kind::DateTimeTz if format.ends_with('Z') => NaiveDateTime::parse_from_str(value, format).ok()?,
+        Kind::DateTimeTz => DateTime::parse_from_str(value, format).ok()?.naive_utc(),
+        Kind::EpochSeconds => {
+            let seconds = value.parse::<i64>().ok().filter(|seconds| EPOCH_RANGE.contains(seconds))?;
+            DateTime::from_timestamp(seconds, 0)?.naive_utc()
+        }
+        Kind::EpochMillis => {
+            let millis = value.parse::<i64>().ok().filter(|millis| EPOCH_RANGE.contains(&(millis / 1000)))?;
+            DateTime::from_timestamp_millis(millis)?.naive_utc()
+        }
+    };
+    // `%Y` takes any number of digits, leave two-digit years to `%y`
+    if format.contains("%Y") && parsed.year() < 1000 {
+        return None;
+    }
+    Some(parsed)
+}
+
+fn names_a_time(column_name: &str) -> bool {
+    let name = column_name.to_lowercase();
+    ["time", "date", "epoch", "stamp", "created", "updated", "modified"].iter().any(|word| name.contains(word))
+        || name.split(|c: char| !c.is_ascii_alphanumeric()).any(|word| matches!(word, "at" | "ts"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(column_name: &str, values: &[&str]) -> Option<(DataType, DateStats)> {
+        let mut candidates = TemporalCandidates::new();
+        for value in values {
+            candidates.add(value);
+        }
+        candidates.resolve(column_name)
+    }
+
+    #[test]
+    fn test_formats() {
+        let (data_type, stats) = resolve("day", &["2024-03-01", "2023-12-31", "2024-01-15"]).unwrap();
+        assert!(matches!(data_type, DataType::Date));
+        assert_eq!((stats.format.as_str(), stats.min.as_str(), stats.max.as_str()), ("%Y-%m-%d", "2023-12-31", "2024-03-01"));
+
+        // month-first until a value can only be day-first
+        assert_eq!(resolve("day", &["01/02/2024", "03/04/2024"]).unwrap().1.format, "%m/%d/%Y");
+        let (_, stats) = resolve("day", &["01/02/2024", "13/04/2024"]).unwrap();
+        assert_eq!((stats.format.as_str(), stats.min.as_str()), ("%d/%m/%Y", "2024-02-01"));
+        assert_eq!(resolve("day", &["24.12.23"]).unwrap().1.max, "2023-12-24");
+
+        let (data_type, stats) = resolve("at", &["09:30", "17:05"]).unwrap();
+        assert!(matches!(data_type, DataType::Time));
+        assert_eq!((stats.format.as_str(), stats.max.as_str()), ("%H:%M", "17:05:00"));
+
+        let (data_type, stats) = resolve("seen", &["2024-01-05 10:00:00", "2024-01-05 09:59:59.5"]).unwrap();
+        assert!(matches!(data_type, DataType::DateTime));
+        assert_eq!((stats.format.as_str(), stats.min.as_str()), ("%Y-%m-%d %H:%M:%S%.f", "2024-01-05T09:59:59.500"));
+
+        // offsets are compared, and reported, in UTC
+        let (data_type, stats) = resolve("seen", &["2024-01-05T10:00:00+02:00", "2024-01-05T09:00:00-01:00"]).unwrap();
+        assert!(matches!(data_type, DataType::DateTimeTz));
+        assert_eq!((stats.min.as_str(), stats.max.as_str()), ("2024-01-05T08:00:00Z", "2024-01-05T10:00:00Z"));
+        assert_eq!(resolve("seen", &["2024-01-05T09:00:00.25Z"]).unwrap().1.format, "%Y-%m-%dT%H:%M:%S%.fZ");
+
+        assert!(resolve("name", &["2024-01-05", "soon"]).is_none());
+        assert!(resolve("amount", &["10.50", "3"]).is_none());
+    }
+
+    #[test]
+    fn test_epochs_need_a_telling_name() {
+        let seconds = ["1700000000", "1700003600"];
+        let (data_type, stats) = resolve("created_at", &seconds).unwrap();
+        assert!(matches!(data_type, DataType::DateTimeTz));
+        assert_eq!((stats.format.as_str(), stats.min.as_str()), ("%s", "2023-11-14T22:13:20Z"));
+        assert!(resolve("customer_id", &seconds).is_none());
+
+        let (_, stats) = resolve("timestamp", &["1700000000123"]).unwrap();
+        assert_eq!((stats.format.as_str(), stats.max.as_str()), ("%s%3f", "2023-11-14T22:13:20.123Z"));
+        assert!(resolve("timestamp", &["42"]).is_none());
+    }
+}
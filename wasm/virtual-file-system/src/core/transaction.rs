@@ -0,0 +1,227 @@
+// core/transaction.rs
+//
+// All-or-nothing groups of changes. Beginning a transaction checkpoints
+// the file records, paths, directories, trash and blob reference counts (content
+// is shared with the checkpoint, never copied); rolling back puts them
+// back, along with the settings (version limit, metadata schemas, cascade
+// rules, compression policy and trash retention). Nothing is evicted while
+// a transaction is open, so the content it replaces or adds stays in
+// memory until it ends and the memory budget applies again.
+//
+// Encryption, the memory budget and content provider, subscriptions and
+// pending uploads are outside of transactions: changing them inside one
+// takes effect right away and stays after a rollback. Events are held until commit and dropped on rollback, so
+// subscribers never see changes that did not happen.
+use std::collections::{BTreeSet, HashMap};
+use serde::{Serialize, Deserialize};
+
+use crate::core::blob_store::BlobCheckpoint;
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::file_system::{SupportedFileType, VirtualFile, VirtualFileSystem};
+use crate::core::metadata::MetadataValue;
+use crate::core::storage::StoredSettings;
+
+pub(crate) struct Checkpoint {
+    files: HashMap<String, VirtualFile>,
+    paths: HashMap<String, String>,
+    directories: BTreeSet<String>,
+    trash: HashMap<String, VirtualFile>,
+    blobs: BlobCheckpoint,
+    settings: StoredSettings,
+}
+
+/// One step of `apply_batch`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "op")]
+pub enum BatchOp {
+    Create { path: String, file_type: SupportedFileType, content: Vec<u8> },
+    Update { id: String, content: Vec<u8>, #[serde(default)] message: Option<String> },
+    Delete { id: String },
+    SetMetadata { id: String, key: String, value: MetadataValue },
+    Mkdir { path: String },
+    Move { from: String, to: String },
+}
+
+impl VirtualFileSystem {
+    /// Starts a transaction. Transactions don't nest.
+    pub fn begin_transaction(&mut self) -> VfsResult<()> {
+        if self.transaction.is_some() {
+            return Err(VfsError::InvalidOperation { reason: "A transaction is already open".to_string() });
+        }
+
+        self.transaction = Some(Checkpoint {
+            files: self.files.clone(),
+            paths: self.paths.clone(),
+            directories: self.directories.clone(),
+            trash: self.trash.clone(),
+            blobs: self.blobs.checkpoint(),
+            settings: self.settings(),
+        });
+        self.blobs.suspend_eviction();
+        self.events.hold();
+        Ok(())
+    }
+
+    /// Keeps every change made since `begin_transaction` and delivers the
+    /// held events.
+    pub fn commit_transaction(&mut self) -> VfsResult<()> {
+        self.transaction.take().ok_or_else(no_transaction)?;
+        self.blobs.resume_eviction();
+        self.events.release();
+        self.flush()
+    }
+
+    /// Undoes every change made since `begin_transaction`.
+    pub fn rollback_transaction(&mut self) -> VfsResult<()> {
+        let checkpoint = self.transaction.take().ok_or_else(no_transaction)?;
+
+        self.files = checkpoint.files;
+        self.paths = checkpoint.paths;
+        self.directories = checkpoint.directories;
+        self.trash = checkpoint.trash;
+        self.blobs.restore_checkpoint(checkpoint.blobs);
+        self.blobs.resume_eviction();
+        if self.settings() != checkpoint.settings {
+            self.apply_settings(checkpoint.settings);
+        }
+        self.events.discard();
+        Ok(())
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    /// Runs `f` in a transaction, committing if it returns `Ok` and rolling
+    /// back if it returns an error.
+    pub fn transaction<T>(&mut self, f: impl FnOnce(&mut Self) -> VfsResult<T>) -> VfsResult<T> {
+        self.begin_transaction()?;
+        match f(self) {
+            Ok(value) => {
+                self.commit_transaction()?;
+                Ok(value)
+            }
+            Err(error) => {
+                self.rollback_transaction()?;
+                Err(error)
+            }
+        }
+    }
+
+    /// Applies every operation or none of them. Returns the id of the file
+    /// each `Create` made, `None` for the other operations.
+    pub fn apply_batch(&mut self, ops: Vec<BatchOp>) -> VfsResult<Vec<Option<String>>> {
+        self.transaction(|vfs| {
+            ops.into_iter()
+                .map(|op| match op {
+                    BatchOp::Create { path, file_type, content } => vfs.create_file_at(&path, file_type, content).map(Some),
+                    BatchOp::Update { id, content, message } => vfs.update_file_with_message(&id, content, message).map(|_| None),
+                    BatchOp::Delete { id } => vfs.delete_file(&id).map(|_| None),
+                    BatchOp::SetMetadata { id, key, value } => vfs.set_file_metadata(&id, key, value).map(|_| None),
+                    BatchOp::Mkdir { path } => vfs.mkdir(&path).map(|_| None),
+                    BatchOp::Move { from, to } => vfs.move_path(&from, &to).map(|_| None),
+                })
+                .collect()
+        })
+    }
+}
+
+fn no_transaction() -> VfsError {
+    VfsError::InvalidOperation { reason: "No transaction is open".to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::core::EventFilter;
+    use crate::core::eviction::ContentState;
+    use crate::core::graph::{CascadeRule, LinkKind};
+
+    #[test]
+    fn test_rollback_restores_everything() {
+        let mut vfs = VirtualFileSystem::new();
+        let keep = vfs.create_file_at("/data/keep.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+
+        let result: VfsResult<()> = vfs.transaction(|tx| {
+            let new = tx.create_file_at("/data/new.csv", SupportedFileType::CSV, b"b\n2".to_vec())?;
+            tx.set_file_metadata(&new, "source".to_string(), "import".to_string())?;
+            tx.update_file(&keep, Some(b"a\n9".to_vec()))?;
+            tx.delete_file(&keep)?;
+            tx.update_file("missing", Some(vec![]))
+        });
+
+        assert!(matches!(result, Err(VfsError::NotFound { .. })));
+        assert!(!vfs.in_transaction());
+        assert!(!vfs.exists("/data/new.csv"));
+        assert_eq!(vfs.get_file_content(&keep).unwrap(), b"a\n1".to_vec());
+        assert_eq!(vfs.list_versions(&keep).unwrap().len(), 1);
+
+        let stats = vfs.storage_stats();
+        assert_eq!((stats.file_count, stats.blob_count, stats.stored_bytes), (1, 1, 3));
+    }
+
+    #[test]
+    fn test_rollback_restores_settings_and_content() {
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_memory_budget(Some(4)).unwrap();
+        let keep = vfs.create_file_at("/a.bin", SupportedFileType::Text, vec![1; 4]).unwrap();
+
+        vfs.begin_transaction().unwrap();
+        vfs.set_max_versions(1);
+        vfs.set_trash_retention(Some(10)).unwrap();
+        vfs.set_cascade_rule(LinkKind::Custom("copy_of".to_string()), CascadeRule::Restrict);
+        vfs.create_file_at("/b.bin", SupportedFileType::Text, vec![2; 4]).unwrap();
+        // nothing is evicted until the transaction ends
+        assert_eq!(vfs.content_state(&keep).unwrap(), ContentState::Resident);
+        vfs.rollback_transaction().unwrap();
+
+        assert_eq!(vfs.settings(), VirtualFileSystem::new().settings());
+        assert_eq!(vfs.content_state(&keep).unwrap(), ContentState::Resident);
+        assert_eq!(vfs.get_file_content(&keep).unwrap(), vec![1; 4]);
+        assert_eq!(vfs.storage_stats().stored_bytes, 4);
+    }
+
+    #[test]
+    fn test_commit_and_events() {
+        let mut vfs = VirtualFileSystem::new();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = events.clone();
+        vfs.subscribe(EventFilter::All, move |event| sink.borrow_mut().push(event.clone()));
+
+        vfs.begin_transaction().unwrap();
+        assert!(vfs.begin_transaction().is_err());
+        vfs.create_file_at("/a.txt", SupportedFileType::Text, b"a".to_vec()).unwrap();
+        assert!(events.borrow().is_empty());
+        vfs.commit_transaction().unwrap();
+        assert_eq!(events.borrow().len(), 1);
+
+        vfs.begin_transaction().unwrap();
+        vfs.create_file_at("/b.txt", SupportedFileType::Text, b"b".to_vec()).unwrap();
+        vfs.rollback_transaction().unwrap();
+        assert_eq!(events.borrow().len(), 1);
+        assert!(vfs.commit_transaction().is_err());
+    }
+
+    #[test]
+    fn test_apply_batch() {
+        let mut vfs = VirtualFileSystem::new();
+        let ops: Vec<BatchOp> = serde_json::from_str(r#"[
+            {"op": "Mkdir", "path": "/datasets"},
+            {"op": "Create", "path": "/datasets/a.csv", "file_type": "CSV", "content": [97, 10, 49]},
+            {"op": "Move", "from": "/datasets/a.csv", "to": "/datasets/b.csv"}
+        ]"#).unwrap();
+
+        let ids = vfs.apply_batch(ops).unwrap();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(vfs.resolve_path("/datasets/b.csv").unwrap(), ids[1].clone().unwrap());
+
+        let failing = vec![
+            BatchOp::Create { path: "/c.csv".to_string(), file_type: SupportedFileType::CSV, content: vec![1] },
+            BatchOp::Create { path: "/datasets/b.csv".to_string(), file_type: SupportedFileType::CSV, content: vec![2] },
+        ];
+        assert!(matches!(vfs.apply_batch(failing), Err(VfsError::AlreadyExists { .. })));
+        assert!(!vfs.exists("/c.csv"));
+    }
+}
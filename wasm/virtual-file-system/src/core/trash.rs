@@ -0,0 +1,247 @@
+// core/trash.rs
+//
+// Soft delete. `trash_file` takes a file out of the tree like
+// `delete_file`, but keeps its record, content and links so `restore`
+// can put it back; `empty_trash`, or the retention age set with
+// `set_trash_retention`, deletes it for good. Trashed records keep the
+// path they were deleted from and carry `deleted_at`, which is how
+// storage and snapshots, where they sit next to the other records, tell
+// them apart.
+//
+// Links from other files to a trashed file are kept, so restoring it
+// undoes the delete completely; the graph queries skip them meanwhile.
+use std::collections::HashSet;
+
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::events::FileEvent;
+use crate::core::file_system::{VirtualFile, VirtualFileSystem};
+use crate::core::path;
+
+impl VirtualFileSystem {
+    /// Moves a file to the trash, along with the files a `Delete` cascade
+    /// would take with it, and returns the ids of everything moved. A
+    /// `Restrict` link refuses it like it refuses `delete_file`.
+    pub fn trash_file(&mut self, id: &str) -> VfsResult<Vec<String>> {
+        let trashed = self.plan_delete(&[id.to_string()])?;
+        let now = self.now();
+        for id in &trashed {
+            let Some(mut file) = self.files.remove(id) else { continue };
+            self.paths.remove(&file.path);
+            file.deleted_at = Some(now);
+
+            let path = file.path.clone();
+            self.trash.insert(id.clone(), file);
+            self.emit(FileEvent::Trashed { id: id.clone(), path });
+        }
+
+        self.purge_expired();
+        self.flush()?;
+        Ok(trashed)
+    }
+
+    pub fn trash_file_at(&mut self, file_path: &str) -> VfsResult<Vec<String>> {
+        let id = self.resolve_path(file_path)?;
+        self.trash_file(&id)
+    }
+
+    /// Trashed files, most recently deleted first.
+    pub fn list_trash(&self) -> Vec<&VirtualFile> {
+        let mut files: Vec<&VirtualFile> = self.trash.values().collect();
+        files.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then_with(|| a.path.cmp(&b.path)));
+        files
+    }
+
+    /// Puts a trashed file back and returns the path it ended up at: the
+    /// one it was deleted from, with a numbered suffix if that has been
+    /// taken since, or the root directory if a file now stands where one
+    /// of its directories was. Missing directories are created.
+    pub fn restore(&mut self, id: &str) -> VfsResult<String> {
+        let mut file = self.trash.remove(id)
+            .ok_or_else(|| VfsError::not_found(id))?;
+
+        let mut dir = path::parent(&file.path).unwrap_or(path::ROOT).to_string();
+        let mut to_create = path::ancestors(&dir);
+        to_create.push(dir.clone());
+        if to_create.iter().any(|dir| self.paths.contains_key(dir)) {
+            dir = path::ROOT.to_string();
+            to_create.clear();
+        }
+        self.directories.extend(to_create);
+
+        let name = self.unique_child_name(&dir, &file.name);
+        let restored_path = path::join(&dir, &name);
+        file.name = name;
+        file.path = restored_path.clone();
+        file.deleted_at = None;
+        // targets deleted for good while it was in the trash
+        file.links.retain(|link| self.files.contains_key(&link.target) || self.trash.contains_key(&link.target));
+
+        self.paths.insert(restored_path.clone(), id.to_string());
+        self.files.insert(id.to_string(), file);
+        self.emit(FileEvent::Restored { id: id.to_string(), path: restored_path.clone() });
+        self.flush()?;
+        Ok(restored_path)
+    }
+
+    /// Deletes everything in the trash for good and returns how many files
+    /// that was.
+    pub fn empty_trash(&mut self) -> VfsResult<usize> {
+        let mut ids: Vec<String> = self.trash.keys().cloned().collect();
+        ids.sort();
+        self.purge(&ids);
+        self.flush()?;
+        Ok(ids.len())
+    }
+
+    /// Sets how long, in milliseconds, files stay in the trash before they
+    /// are deleted for good; `None` (the default) keeps them until
+    /// `empty_trash`. Files past the age are purged right away, and after
+    /// that whenever a file is trashed or `purge_trash` is called.
+    pub fn set_trash_retention(&mut self, retention: Option<u64>) -> VfsResult<()> {
+        self.trash_retention = retention;
+        self.storage.mark_settings_changed();
+        self.purge_trash().map(|_| ())
+    }
+
+    pub fn trash_retention(&self) -> Option<u64> {
+        self.trash_retention
+    }
+
+    /// Deletes the files that have been in the trash longer than the
+    /// retention age and returns their ids. Hosts call this now and then,
+    /// e.g. on startup.
+    pub fn purge_trash(&mut self) -> VfsResult<Vec<String>> {
+        let purged = self.purge_expired();
+        self.flush()?;
+        Ok(purged)
+    }
+
+    fn purge_expired(&mut self) -> Vec<String> {
+        let Some(retention) = self.trash_retention else {
+            return Vec::new();
+        };
+        let now = self.now();
+        let mut expired: Vec<String> = self.trash.values()
+            .filter(|file| file.deleted_at.is_some_and(|deleted_at| now.saturating_sub(deleted_at) >= retention))
+            .map(|file| file.id.clone())
+            .collect();
+        expired.sort();
+        self.purge(&expired);
+        expired
+    }
+
+    fn purge(&mut self, ids: &[String]) {
+        for id in ids {
+            let Some(file) = self.trash.remove(id) else { continue };
+            for version in &file.versions {
+                self.blobs.release(version.blob_key());
+            }
+            self.emit(FileEvent::Deleted { id: file.id, path: file.path });
+        }
+        self.remove_dangling_links(&ids.iter().cloned().collect::<HashSet<String>>());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::core::clock::ManualClock;
+    use crate::core::{CascadeRule, EventFilter, LinkDirection, LinkKind, MemoryBackend, SupportedFileType};
+
+    #[test]
+    fn test_trash_and_restore() {
+        let mut vfs = VirtualFileSystem::new();
+        let raw = vfs.create_file_at("/data/raw.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+        let chart = vfs.create_file_at("/chart.png", SupportedFileType::Image, vec![1, 2]).unwrap();
+        vfs.link(&chart, &raw, LinkKind::BelongsTo).unwrap();
+        vfs.set_cascade_rule(LinkKind::BelongsTo, CascadeRule::Delete);
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = events.clone();
+        let _subscription = vfs.subscribe(EventFilter::All, move |event| sink.borrow_mut().push(event.clone()));
+
+        let mut trashed = vfs.trash_file(&raw).unwrap();
+        trashed.sort();
+        let mut expected = vec![raw.clone(), chart.clone()];
+        expected.sort();
+        assert_eq!(trashed, expected);
+        assert!(vfs.read_file(&raw).is_err());
+        assert!(!vfs.exists("/data/raw.csv"));
+        assert_eq!(vfs.list_trash().len(), 2);
+        assert!(vfs.list_trash().iter().all(|file| file.deleted_at.is_some()));
+        assert!(matches!(&events.borrow()[0], FileEvent::Trashed { path, .. } if path == "/data/raw.csv"));
+
+        // a new file took the old path, and the directory is gone
+        vfs.remove_dir("/data", true).unwrap();
+        vfs.create_file_at("/chart.png", SupportedFileType::Image, vec![3]).unwrap();
+        assert_eq!(vfs.restore(&raw).unwrap(), "/data/raw.csv");
+        assert_eq!(vfs.restore(&chart).unwrap(), "/chart (1).png");
+        assert_eq!(vfs.get_file_content(&raw).unwrap(), b"a\n1".to_vec());
+        assert_eq!(vfs.neighbors(&chart, LinkDirection::Outgoing).unwrap(), vec![raw.clone()]);
+        assert!(vfs.read_file(&raw).unwrap().deleted_at.is_none());
+        assert!(vfs.list_trash().is_empty());
+        assert!(matches!(events.borrow().last(), Some(FileEvent::Restored { path, .. }) if path == "/chart (1).png"));
+        assert!(vfs.restore(&raw).is_err());
+    }
+
+    #[test]
+    fn test_empty_trash_and_retention() {
+        let clock = ManualClock::new(0);
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_clock(Box::new(clock.clone()));
+        let raw = vfs.create_file_at("/raw.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+        let cleaned = vfs.create_file_at("/cleaned.csv", SupportedFileType::CSV, b"a\n2".to_vec()).unwrap();
+        vfs.link(&cleaned, &raw, LinkKind::DerivedFrom).unwrap();
+
+        // while trashed the link is hidden, but the content is still held
+        vfs.trash_file(&raw).unwrap();
+        assert!(vfs.outgoing_links(&cleaned).unwrap().is_empty());
+        assert!(vfs.lineage(&cleaned, None, LinkDirection::Outgoing).unwrap().is_empty());
+        assert_eq!(vfs.storage_stats().blob_count, 2);
+
+        clock.advance(1_000);
+        vfs.trash_file(&cleaned).unwrap();
+        assert_eq!(vfs.list_trash()[0].id, cleaned);
+
+        vfs.set_trash_retention(Some(1_000)).unwrap();
+        assert_eq!(vfs.list_trash().len(), 1);
+        clock.advance(999);
+        assert!(vfs.purge_trash().unwrap().is_empty());
+        clock.advance(1);
+        assert_eq!(vfs.purge_trash().unwrap(), vec![cleaned.clone()]);
+        assert_eq!(vfs.storage_stats().blob_count, 0);
+
+        let id = vfs.create_file_at("/b.txt", SupportedFileType::Text, b"b".to_vec()).unwrap();
+        vfs.set_trash_retention(None).unwrap();
+        vfs.trash_file(&id).unwrap();
+        assert_eq!(vfs.empty_trash().unwrap(), 1);
+        assert!(vfs.list_trash().is_empty());
+        assert!(vfs.restore(&id).is_err());
+    }
+
+    #[test]
+    fn test_trash_survives_snapshots_and_storage() {
+        let backend = MemoryBackend::default();
+        let mut vfs = VirtualFileSystem::open(Box::new(backend.clone())).unwrap();
+        let id = vfs.create_file_at("/a.csv", SupportedFileType::CSV, b"a\n1".to_vec()).unwrap();
+        vfs.trash_file_at("/a.csv").unwrap();
+        vfs.create_file_at("/a.csv", SupportedFileType::CSV, b"a\n2".to_vec()).unwrap();
+        vfs.set_trash_retention(Some(60_000)).unwrap();
+
+        let mut restored = VirtualFileSystem::new();
+        restored.import_snapshot(&vfs.export_snapshot()).unwrap();
+        assert_eq!(restored.trash_retention(), Some(60_000));
+        assert_eq!(restored.list_files().len(), 1);
+        assert_eq!(restored.restore(&id).unwrap(), "/a (1).csv");
+        assert_eq!(restored.get_file_content(&id).unwrap(), b"a\n1".to_vec());
+
+        let mut reopened = VirtualFileSystem::open(Box::new(backend.clone())).unwrap();
+        assert_eq!(reopened.list_trash().len(), 1);
+        reopened.empty_trash().unwrap();
+        let reopened = VirtualFileSystem::open(Box::new(backend)).unwrap();
+        assert!(reopened.list_trash().is_empty());
+        assert_eq!(reopened.storage_stats().blob_count, 1);
+    }
+}
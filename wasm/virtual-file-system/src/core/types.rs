@@ -7,6 +7,11 @@ pub enum DataType {
     Text,
     Enum,
     Boolean,
+    Date,
+    Time,
+    DateTime,
+    /// A point in time with a UTC offset, or a Unix timestamp.
+    DateTimeTz,
     Unknown,
 }
 
@@ -18,11 +23,24 @@ impl DataType {
             DataType::Text => "TEXT",
             DataType::Boolean => "BOOLEAN",
             DataType::Enum => "ENUM",
+            DataType::Date => "DATE",
+            DataType::Time => "TIME",
+            DataType::DateTime => "TIMESTAMP",
+            DataType::DateTimeTz => "TIMESTAMPTZ",
             DataType::Unknown => "TEXT"
         }
     }
 }
 
+/// Whether a profile was computed from every value or estimated from a
+/// bounded summary of them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum Accuracy {
+    #[default]
+    Exact,
+    Estimated,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ColumnMetadata {
     pub name: String,
@@ -30,27 +48,66 @@ pub struct ColumnMetadata {
     pub sample_values: Vec<String>,
     pub nullable: bool,
     pub unique_count: usize,
-    pub numeric_stats: Option<NumericStats<i64>>,  // Use i64 for Pokemon stats
+    /// `Estimated` when the column had too many distinct values to count
+    /// them all, see `core::csv_metadata`.
+    #[serde(default)]
+    pub accuracy: Accuracy,
+    /// For columns whose values are all numbers, whatever type they were
+    /// given.
+    pub numeric_stats: Option<NumericStats>,
+    /// Lengths in characters of the non-empty values, for every column.
     pub string_stats: Option<StringStats>,
+    /// For the date and time types, see `core::temporal`.
+    #[serde(default)]
+    pub date_stats: Option<DateStats>,
 }
 
+/// Everything but the median and quartiles is exact. Those come from the
+/// value counts while there are few enough distinct values to count, and
+/// from a sample of the column past that, see `quantile_accuracy`.
 #[derive(Serialize, Deserialize, Debug)]
-pub struct NumericStats<T> {
-    pub min: T,
-    pub max: T,
-    pub mean: f64,  // mean stays f64 for precision
+pub struct NumericStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// First and third quartile.
+    pub q1: f64,
+    pub q3: f64,
+    /// Sample variance and standard deviation.
+    pub variance: f64,
+    pub std_dev: f64,
+    pub skewness: f64,
+    /// Excess kurtosis, 0 for a normal distribution.
+    pub kurtosis: f64,
     pub null_count: usize,
+    pub zero_count: usize,
+    pub negative_count: usize,
     pub distinct_count: usize,
+    #[serde(default)]
+    pub quantile_accuracy: Accuracy,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct StringStats {
     pub min_length: usize,
     pub max_length: usize,
+    #[serde(default)]
+    pub mean_length: f64,
     pub null_count: usize,
     pub distinct_count: usize,
 }
 
+/// `format` is the strftime pattern the column's values were read with,
+/// `%s` for Unix seconds and `%s%3f` for milliseconds. `min` and `max`
+/// are ISO 8601, in UTC for `DateTimeTz`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DateStats {
+    pub format: String,
+    pub min: String,
+    pub max: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SqlStatements {
     pub create_table: String,
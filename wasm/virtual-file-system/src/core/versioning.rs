@@ -0,0 +1,221 @@
+// core/versioning.rs
+//
+// Every content change to a file is kept as an immutable version. Versions
+// hold a reference to their blob, so old content stays readable until the
+// version is pruned by the retention limit or the file is deleted.
+use serde::{Serialize, Deserialize};
+
+use crate::core::blob_store::calculate_checksum;
+use crate::core::compression::Compression;
+use crate::core::error::{VfsError, VfsResult};
+use crate::core::events::FileEvent;
+use crate::core::file_system::VirtualFileSystem;
+
+pub const DEFAULT_MAX_VERSIONS: usize = 20;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileVersion {
+    pub version: u32,
+    /// SHA-256 of the content, keyed when it is encrypted, see
+    /// `core::encryption`.
+    pub checksum: String,
+    pub size: usize,
+    /// Size and form the content is stored in, see `core::compression`.
+    #[serde(default)]
+    pub stored_size: usize,
+    #[serde(default)]
+    pub compression: Compression,
+    /// Blob holding the sealed content when the version is encrypted, see
+    /// `core::encryption`.
+    #[serde(default)]
+    pub encrypted_blob: Option<String>,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub message: Option<String>,
+}
+
+impl FileVersion {
+    pub fn new(version: u32, checksum: String, size: usize, message: Option<String>, timestamp: u64) -> Self {
+        FileVersion {
+            version,
+            checksum,
+            size,
+            stored_size: size,
+            compression: Compression::None,
+            encrypted_blob: None,
+            timestamp,
+            message,
+        }
+    }
+
+    /// Key of the blob holding this version's content.
+    pub fn blob_key(&self) -> &str {
+        self.encrypted_blob.as_deref().unwrap_or(&self.checksum)
+    }
+}
+
+impl VirtualFileSystem {
+    /// Replaces the content of a file, recording the change as a new
+    /// version. Returns the new version number.
+    pub fn update_file_with_message(&mut self, id: &str, content: Vec<u8>, message: Option<String>) -> VfsResult<u32> {
+        let now = self.now();
+        let file = self.file(id)?;
+        let (file_type, encrypted) = (file.file_type.clone(), file.encrypted);
+
+        let size = content.len();
+        let checksum = calculate_checksum(&content);
+        let version = file.current_version() + 1;
+        let mut new_version = FileVersion::new(version, checksum, size, message, now);
+        self.store_content(&mut new_version, content, &file_type, encrypted)?;
+
+        let file = self.files.get_mut(id)
+            .ok_or_else(|| VfsError::not_found(id))?;
+        file.checksum = new_version.checksum.clone();
+        file.size = size;
+        file.stored_size = new_version.stored_size;
+        file.compression = new_version.compression;
+        file.versions.push(new_version);
+        file.modified_at = now;
+        file.accessed_at = now;
+        let path = file.path.clone();
+
+        self.prune_versions(id);
+        self.emit(FileEvent::Updated { id: id.to_string(), path, version });
+        self.flush()?;
+        Ok(version)
+    }
+
+    /// Versions of a file, oldest first. The last entry is the current content.
+    pub fn list_versions(&self, id: &str) -> VfsResult<&[FileVersion]> {
+        self.files.get(id)
+            .map(|file| file.versions.as_slice())
+            .ok_or_else(|| VfsError::not_found(id))
+    }
+
+    pub fn read_version(&mut self, id: &str, version: u32) -> VfsResult<Vec<u8>> {
+        self.touch_accessed(id);
+        let checksum = self.find_version(id, version)?.checksum.clone();
+        self.load_content(id, &checksum).map(|content| content.into_owned())
+    }
+
+    /// Makes the content of an older version current again. The revert is
+    /// itself recorded as a new version, so it can be undone too.
+    pub fn revert_to_version(&mut self, id: &str, version: u32, message: Option<String>) -> VfsResult<u32> {
+        let target = self.find_version(id, version)?.clone();
+        let message = message.or_else(|| Some(format!("Reverted to version {}", version)));
+
+        let now = self.now();
+        let file = self.files.get_mut(id)
+            .ok_or_else(|| VfsError::not_found(id))?;
+        self.blobs.retain(target.blob_key());
+
+        let new_version = file.current_version() + 1;
+        file.versions.push(FileVersion {
+            stored_size: target.stored_size,
+            compression: target.compression,
+            encrypted_blob: target.encrypted_blob.clone(),
+            ..FileVersion::new(new_version, target.checksum.clone(), target.size, message, now)
+        });
+        file.checksum = target.checksum;
+        file.size = target.size;
+        file.stored_size = target.stored_size;
+        file.compression = target.compression;
+        file.modified_at = now;
+        file.accessed_at = now;
+        let path = file.path.clone();
+
+        self.prune_versions(id);
+        self.emit(FileEvent::Updated { id: id.to_string(), path, version: new_version });
+        self.flush()?;
+        Ok(new_version)
+    }
+
+    /// Sets how many versions are kept per file (at least one, the current
+    /// content) and prunes existing histories to match.
+    pub fn set_max_versions(&mut self, limit: usize) {
+        self.max_versions = limit.max(1);
+        self.storage.mark_settings_changed();
+
+        let ids: Vec<String> = self.files.keys().cloned().collect();
+        for id in ids {
+            self.prune_versions(&id);
+        }
+    }
+
+    pub fn max_versions(&self) -> usize {
+        self.max_versions
+    }
+
+    fn find_version(&self, id: &str, version: u32) -> VfsResult<&FileVersion> {
+        self.files.get(id)
+            .ok_or_else(|| VfsError::not_found(id))?
+            .versions.iter()
+            .find(|v| v.version == version)
+            .ok_or_else(|| VfsError::VersionNotFound { id: id.to_string(), version })
+    }
+
+    fn prune_versions(&mut self, id: &str) {
+        let Some(file) = self.files.get_mut(id) else { return };
+
+        let excess = file.versions.len().saturating_sub(self.max_versions);
+        for pruned in file.versions.drain(..excess) {
+            self.blobs.release(pruned.blob_key());
+        }
+        if excess > 0 {
+            self.storage.mark_dirty(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{VirtualFileSystem, SupportedFileType};
+
+    #[test]
+    fn test_updates_are_recorded_as_versions() {
+        let mut vfs = VirtualFileSystem::new();
+        let id = vfs.create_file("data.csv".to_string(), SupportedFileType::CSV, b"a,b\n1,2".to_vec()).unwrap();
+        let v2 = vfs.update_file_with_message(&id, b"a,b\n1,3".to_vec(), Some("fix value".to_string())).unwrap();
+
+        let versions = vfs.list_versions(&id).unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].version, 1);
+        assert_eq!(versions[1].version, v2);
+        assert_eq!(versions[1].message.as_deref(), Some("fix value"));
+        assert_eq!(vfs.read_version(&id, 1).unwrap(), b"a,b\n1,2".to_vec());
+        assert!(vfs.read_version(&id, 7).is_err());
+    }
+
+    #[test]
+    fn test_revert_creates_a_new_version() {
+        let mut vfs = VirtualFileSystem::new();
+        let id = vfs.create_file("data.csv".to_string(), SupportedFileType::CSV, vec![1]).unwrap();
+        vfs.update_file(&id, Some(vec![2])).unwrap();
+
+        let version = vfs.revert_to_version(&id, 1, None).unwrap();
+        assert_eq!(version, 3);
+        assert_eq!(vfs.get_file_content(&id).unwrap(), vec![1]);
+        assert_eq!(vfs.read_file(&id).unwrap().size, 1);
+        assert_eq!(vfs.list_versions(&id).unwrap()[2].message.as_deref(), Some("Reverted to version 1"));
+    }
+
+    #[test]
+    fn test_retention_limit_releases_old_content() {
+        let mut vfs = VirtualFileSystem::new();
+        vfs.set_max_versions(2);
+        let id = vfs.create_file("data.csv".to_string(), SupportedFileType::CSV, vec![1]).unwrap();
+        vfs.update_file(&id, Some(vec![2])).unwrap();
+        vfs.update_file(&id, Some(vec![3])).unwrap();
+
+        let versions: Vec<u32> = vfs.list_versions(&id).unwrap().iter().map(|v| v.version).collect();
+        assert_eq!(versions, vec![2, 3]);
+        assert_eq!(vfs.storage_stats().blob_count, 2);
+
+        vfs.set_max_versions(0);
+        assert_eq!(vfs.list_versions(&id).unwrap().len(), 1);
+        assert_eq!(vfs.storage_stats().blob_count, 1);
+
+        vfs.delete_file(&id).unwrap();
+        assert_eq!(vfs.storage_stats().blob_count, 0);
+    }
+}
@@ -9,12 +9,14 @@ pub use core::{
     VirtualFile,
     SupportedFileType,
     FileMetadata,
-    CSVMetadata
+    CSVMetadata,
+    VfsError,
+    CsvError
 };
 
 // Re-export the WASM interfaces when targeting wasm32
 #[cfg(target_arch = "wasm32")]
-pub use wasm::{WasmFileSystem, WasmCSVMetadata};
+pub use wasm::{WasmFileSystem, WasmSubscription, WasmCSVMetadata};
 
 // When in test configuration, expose test utilities
 #[cfg(test)]
@@ -1,12 +1,24 @@
 use wasm_bindgen::prelude::*;
+use serde::Serialize;
 use serde_wasm_bindgen;
-use crate::core::{VirtualFileSystem, SupportedFileType};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::console;
+use crate::core::{VirtualFileSystem, SupportedFileType, TypeDetectionMode, ContentProvider, FileQuery, EventFilter, Subscription, BatchOp, MetadataSchema, MetadataValue, LinkKind, LinkDirection, CascadeRule, Provenance, CompressionPolicy, KdfParams, AsyncStorageBackend, StorageChanges, StoredState, VfsError, VfsResult};
 
 #[wasm_bindgen]
 pub struct WasmFileSystem {
     inner: VirtualFileSystem
 }
 
+impl Default for WasmFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[wasm_bindgen]
 impl WasmFileSystem {
     #[wasm_bindgen(constructor)]
@@ -16,30 +28,113 @@ impl WasmFileSystem {
         }
     }
 
+    /// Opens a file system persisted through a JS storage adapter, e.g. one
+    /// on IndexedDB. See `JsStorageAdapter` for what it has to implement.
+    #[wasm_bindgen]
+    pub async fn open(adapter: JsStorageAdapter) -> Result<WasmFileSystem, JsValue> {
+        let mut adapter = adapter;
+        let inner = VirtualFileSystem::open_async(&mut adapter).await
+            .map_err(JsValue::from)?;
+        Ok(WasmFileSystem { inner })
+    }
+
+    /// Hands the changes made since the last sync to `adapter.apply`. If
+    /// the returned promise rejects, call `mark_all_changed` so the next
+    /// sync writes everything again.
+    #[wasm_bindgen]
+    pub fn sync(&mut self, adapter: JsStorageAdapter) -> Result<js_sys::Promise, JsValue> {
+        let changes = self.inner.take_changes()
+            .map_err(JsValue::from)?;
+        Ok(wasm_bindgen_futures::future_to_promise(async move {
+            let mut adapter = adapter;
+            adapter.apply(&changes).await.map_err(JsValue::from)?;
+            Ok(JsValue::UNDEFINED)
+        }))
+    }
+
+    #[wasm_bindgen]
+    pub fn mark_all_changed(&mut self) {
+        self.inner.mark_all_changed();
+    }
+
     #[wasm_bindgen]
     pub fn create_file(&mut self, name: String, file_type: JsValue, content: Vec<u8>) -> Result<String, JsValue> {
         let file_type: SupportedFileType = serde_wasm_bindgen::from_value(file_type)?;
         self.inner.create_file(name, file_type, content)
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn create_file_at(&mut self, path: &str, file_type: JsValue, content: Vec<u8>) -> Result<String, JsValue> {
+        let file_type: SupportedFileType = serde_wasm_bindgen::from_value(file_type)?;
+        self.inner.create_file_at(path, file_type, content)
+            .map_err(JsValue::from)
     }
 
+    /// `provenance` is `"Upload"`, `"Paste"`, `{ Derived: { sources,
+    /// transform } }` or `{ ArchiveImport: { archive } }`.
     #[wasm_bindgen]
-    pub fn read_file(&self, id: &str) -> Result<JsValue, JsValue> {
+    pub fn create_file_with_provenance(&mut self, path: &str, file_type: JsValue, content: Vec<u8>, provenance: JsValue) -> Result<String, JsValue> {
+        let file_type: SupportedFileType = serde_wasm_bindgen::from_value(file_type)?;
+        let provenance: Provenance = serde_wasm_bindgen::from_value(provenance)?;
+        self.inner.create_file_with_provenance(path, file_type, content, provenance)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_provenance(&mut self, id: &str, provenance: JsValue) -> Result<(), JsValue> {
+        let provenance: Provenance = serde_wasm_bindgen::from_value(provenance)?;
+        self.inner.set_provenance(id, provenance)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn read_file(&mut self, id: &str) -> Result<JsValue, JsValue> {
         self.inner.read_file(id)
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(JsValue::from)
             .and_then(|file| serde_wasm_bindgen::to_value(file).map_err(|e| e.into()))
     }
 
     #[wasm_bindgen]
     pub fn update_file(&mut self, id: &str, new_content: Option<Vec<u8>>) -> Result<(), JsValue> {
         self.inner.update_file(id, new_content)
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn update_file_with_message(&mut self, id: &str, content: Vec<u8>, message: Option<String>) -> Result<u32, JsValue> {
+        self.inner.update_file_with_message(id, content, message)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn list_versions(&self, id: &str) -> Result<JsValue, JsValue> {
+        self.inner.list_versions(id)
+            .map_err(JsValue::from)
+            .and_then(|versions| serde_wasm_bindgen::to_value(versions).map_err(|e| e.into()))
+    }
+
+    #[wasm_bindgen]
+    pub fn read_version(&mut self, id: &str, version: u32) -> Result<Vec<u8>, JsValue> {
+        self.inner.read_version(id, version)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn revert_to_version(&mut self, id: &str, version: u32, message: Option<String>) -> Result<u32, JsValue> {
+        self.inner.revert_to_version(id, version, message)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_max_versions(&mut self, limit: usize) {
+        self.inner.set_max_versions(limit)
     }
 
     #[wasm_bindgen]
     pub fn delete_file(&mut self, id: &str) -> Result<(), JsValue> {
         self.inner.delete_file(id)
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(JsValue::from)
     }
 
     #[wasm_bindgen]
@@ -48,22 +143,574 @@ impl WasmFileSystem {
         serde_wasm_bindgen::to_value(&files).map_err(|e| e.into())
     }
 
+    /// Runs a `FileQuery` given as a plain object and returns
+    /// `{ total, records }`, records carry no content.
     #[wasm_bindgen]
-    pub fn get_file_content(&self, id: &str) -> Result<Vec<u8>, JsValue> {
+    pub fn query(&self, spec: JsValue) -> Result<JsValue, JsValue> {
+        let query: FileQuery = if spec.is_undefined() || spec.is_null() {
+            FileQuery::default()
+        } else {
+            serde_wasm_bindgen::from_value(spec)?
+        };
+        serde_wasm_bindgen::to_value(&self.inner.query(&query)).map_err(|e| e.into())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_file_content(&mut self, id: &str) -> Result<Vec<u8>, JsValue> {
         self.inner.get_file_content(id)
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(JsValue::from)
     }
 
     #[wasm_bindgen]
-    pub fn set_file_metadata(&mut self, id: &str, key: String, value: String) -> Result<(), JsValue> {
+    pub fn storage_stats(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.inner.storage_stats()).map_err(|e| e.into())
+    }
+
+    /// `value` is any JSON compatible value: null, boolean, number,
+    /// string, array or plain object.
+    #[wasm_bindgen]
+    pub fn set_file_metadata(&mut self, id: &str, key: String, value: JsValue) -> Result<(), JsValue> {
+        let value: MetadataValue = serde_wasm_bindgen::from_value(value)?;
         self.inner.set_file_metadata(id, key, value)
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(JsValue::from)
     }
 
     #[wasm_bindgen]
     pub fn get_file_metadata(&self, id: &str, key: &str) -> Result<JsValue, JsValue> {
         self.inner.get_file_metadata(id, key)
-            .map_err(|e| JsValue::from_str(&e))
-            .and_then(|value| serde_wasm_bindgen::to_value(&value).map_err(|e| e.into()))
+            .map_err(JsValue::from)
+            .and_then(|value| to_plain_value(&value))
+    }
+
+    /// Every key of a file as a plain object.
+    #[wasm_bindgen]
+    pub fn get_all_metadata(&self, id: &str) -> Result<JsValue, JsValue> {
+        self.inner.get_all_metadata(id)
+            .map_err(JsValue::from)
+            .and_then(to_plain_value)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_metadata_bulk(&mut self, id: &str, values: JsValue) -> Result<(), JsValue> {
+        let values: HashMap<String, MetadataValue> = serde_wasm_bindgen::from_value(values)?;
+        self.inner.set_metadata_bulk(id, values)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_file_metadata(&mut self, id: &str, key: &str) -> Result<JsValue, JsValue> {
+        self.inner.remove_file_metadata(id, key)
+            .map_err(JsValue::from)
+            .and_then(|value| to_plain_value(&value))
+    }
+
+    /// `schema` is `{ value_type, nullable?, allowed? }`, or null to drop
+    /// the schema for `key`.
+    #[wasm_bindgen]
+    pub fn set_metadata_schema(&mut self, key: &str, schema: JsValue) -> Result<(), JsValue> {
+        let schema: Option<MetadataSchema> = serde_wasm_bindgen::from_value(schema)?;
+        self.inner.set_metadata_schema(key, schema);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn read_file_at(&mut self, path: &str) -> Result<JsValue, JsValue> {
+        self.inner.read_file_at(path)
+            .map_err(JsValue::from)
+            .and_then(|file| serde_wasm_bindgen::to_value(file).map_err(|e| e.into()))
+    }
+
+    #[wasm_bindgen]
+    pub fn resolve_path(&self, path: &str) -> Result<String, JsValue> {
+        self.inner.resolve_path(path)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn exists(&self, path: &str) -> bool {
+        self.inner.exists(path)
+    }
+
+    #[wasm_bindgen]
+    pub fn is_dir(&self, path: &str) -> bool {
+        self.inner.is_dir(path)
+    }
+
+    #[wasm_bindgen]
+    pub fn mkdir(&mut self, path: &str) -> Result<(), JsValue> {
+        self.inner.mkdir(path)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn list_dir(&self, path: &str, recursive: bool) -> Result<JsValue, JsValue> {
+        let entries = if recursive {
+            self.inner.list_dir_recursive(path)
+        } else {
+            self.inner.list_dir(path)
+        };
+        entries
+            .map_err(JsValue::from)
+            .and_then(|entries| serde_wasm_bindgen::to_value(&entries).map_err(|e| e.into()))
+    }
+
+    #[wasm_bindgen]
+    pub fn move_path(&mut self, from: &str, to: &str) -> Result<(), JsValue> {
+        self.inner.move_path(from, to)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn rename(&mut self, path: &str, new_name: &str) -> Result<(), JsValue> {
+        self.inner.rename(path, new_name)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_dir(&mut self, path: &str, recursive: bool) -> Result<(), JsValue> {
+        self.inner.remove_dir(path, recursive)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn delete_file_at(&mut self, path: &str) -> Result<(), JsValue> {
+        self.inner.delete_file_at(path)
+            .map_err(JsValue::from)
+    }
+
+    /// Soft delete, returns the ids moved to the trash so the toolbar can
+    /// offer "Undo delete" by restoring each of them.
+    #[wasm_bindgen]
+    pub fn trash_file(&mut self, id: &str) -> Result<JsValue, JsValue> {
+        self.inner.trash_file(id)
+            .map_err(JsValue::from)
+            .and_then(|ids| serde_wasm_bindgen::to_value(&ids).map_err(|e| e.into()))
+    }
+
+    #[wasm_bindgen]
+    pub fn trash_file_at(&mut self, path: &str) -> Result<JsValue, JsValue> {
+        self.inner.trash_file_at(path)
+            .map_err(JsValue::from)
+            .and_then(|ids| serde_wasm_bindgen::to_value(&ids).map_err(|e| e.into()))
+    }
+
+    #[wasm_bindgen]
+    pub fn list_trash(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.inner.list_trash()).map_err(|e| e.into())
+    }
+
+    /// Returns the path the file was restored at.
+    #[wasm_bindgen]
+    pub fn restore(&mut self, id: &str) -> Result<String, JsValue> {
+        self.inner.restore(id)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn empty_trash(&mut self) -> Result<usize, JsValue> {
+        self.inner.empty_trash()
+            .map_err(JsValue::from)
+    }
+
+    /// `retention_ms` of undefined keeps trashed files until `empty_trash`.
+    #[wasm_bindgen]
+    pub fn set_trash_retention(&mut self, retention_ms: Option<f64>) -> Result<(), JsValue> {
+        self.inner.set_trash_retention(retention_ms.map(|ms| ms as u64))
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn purge_trash(&mut self) -> Result<JsValue, JsValue> {
+        self.inner.purge_trash()
+            .map_err(JsValue::from)
+            .and_then(|ids| serde_wasm_bindgen::to_value(&ids).map_err(|e| e.into()))
+    }
+
+    #[wasm_bindgen]
+    pub fn export_snapshot(&self) -> Vec<u8> {
+        self.inner.export_snapshot()
+    }
+
+    #[wasm_bindgen]
+    pub fn import_snapshot(&mut self, snapshot: &[u8]) -> Result<(), JsValue> {
+        self.inner.import_snapshot(snapshot)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn import_zip(&mut self, archive: &[u8]) -> Result<JsValue, JsValue> {
+        self.inner.import_zip(archive)
+            .map_err(JsValue::from)
+            .and_then(|ids| serde_wasm_bindgen::to_value(&ids).map_err(|e| e.into()))
+    }
+
+    #[wasm_bindgen]
+    pub fn export_zip(&mut self) -> Result<Vec<u8>, JsValue> {
+        self.inner.export_zip()
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn read_range(&mut self, id: &str, offset: usize, len: usize) -> Result<Vec<u8>, JsValue> {
+        self.inner.read_range(id, offset, len)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn append(&mut self, id: &str, bytes: &[u8]) -> Result<u32, JsValue> {
+        self.inner.append(id, bytes)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn write_at(&mut self, id: &str, offset: usize, bytes: &[u8]) -> Result<u32, JsValue> {
+        self.inner.write_at(id, offset, bytes)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn begin_upload(&mut self, path: &str, file_type: JsValue) -> Result<String, JsValue> {
+        let file_type: SupportedFileType = serde_wasm_bindgen::from_value(file_type)?;
+        self.inner.begin_upload(path, file_type)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn push_chunk(&mut self, upload_id: &str, chunk: &[u8]) -> Result<usize, JsValue> {
+        self.inner.push_chunk(upload_id, chunk)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn finish_upload(&mut self, upload_id: &str) -> Result<String, JsValue> {
+        self.inner.finish_upload(upload_id)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn abort_upload(&mut self, upload_id: &str) -> Result<(), JsValue> {
+        self.inner.abort_upload(upload_id)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) -> Result<(), JsValue> {
+        self.inner.set_memory_budget(budget)
+            .map_err(JsValue::from)
+    }
+
+    /// `policy` is `{ codec: "None" | "Deflate" | "Lz4", file_types?, min_size? }`
+    /// and applies to content stored from now on.
+    #[wasm_bindgen]
+    pub fn set_compression_policy(&mut self, policy: JsValue) -> Result<(), JsValue> {
+        let policy: CompressionPolicy = serde_wasm_bindgen::from_value(policy)?;
+        self.inner.set_compression_policy(policy);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn compression_policy(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(self.inner.compression_policy()).map_err(|e| e.into())
+    }
+
+    /// `kdf` is `{ memory_kib, iterations }`, or undefined for the default
+    /// Argon2id cost.
+    #[wasm_bindgen]
+    pub fn enable_encryption(&mut self, passphrase: &str, kdf: JsValue) -> Result<(), JsValue> {
+        let kdf: Option<KdfParams> = serde_wasm_bindgen::from_value(kdf)?;
+        self.inner.enable_encryption(passphrase, kdf.unwrap_or_default())
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn encryption_enabled(&self) -> bool {
+        self.inner.encryption_enabled()
+    }
+
+    #[wasm_bindgen]
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    #[wasm_bindgen]
+    pub fn lock(&mut self) {
+        self.inner.lock();
+    }
+
+    /// Rejects with code `WRONG_PASSPHRASE` if the passphrase doesn't match.
+    #[wasm_bindgen]
+    pub fn unlock(&mut self, passphrase: &str) -> Result<(), JsValue> {
+        self.inner.unlock(passphrase)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_encrypted(&mut self, id: &str, encrypted: bool) -> Result<(), JsValue> {
+        self.inner.set_encrypted(id, encrypted)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_encrypt_new_files(&mut self, encrypt: bool) {
+        self.inner.set_encrypt_new_files(encrypt);
+    }
+
+    #[wasm_bindgen]
+    pub fn content_state(&self, id: &str) -> Result<JsValue, JsValue> {
+        self.inner.content_state(id)
+            .map_err(JsValue::from)
+            .and_then(|state| serde_wasm_bindgen::to_value(&state).map_err(|e| e.into()))
+    }
+
+    #[wasm_bindgen]
+    pub fn rehydrate(&mut self, id: &str, content: Vec<u8>) -> Result<(), JsValue> {
+        self.inner.rehydrate(id, content)
+            .map_err(JsValue::from)
+    }
+
+    /// `load(checksum)` must answer synchronously with a `Uint8Array` or
+    /// `undefined`. IndexedDB is async, so hosts keep a warm cache for
+    /// `load` and fall back to `rehydrate` once a read fails with
+    /// `CONTENT_EVICTED`. `store(checksum, bytes)` is called before bytes
+    /// are dropped from memory; if it throws they are kept.
+    #[wasm_bindgen]
+    pub fn set_content_provider(&mut self, load: js_sys::Function, store: Option<js_sys::Function>) {
+        self.inner.set_content_provider(Some(Box::new(JsContentProvider { load, store })));
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_content_provider(&mut self) {
+        self.inner.set_content_provider(None);
+    }
+
+    #[wasm_bindgen]
+    pub fn add_tag(&mut self, id: &str, tag: &str) -> Result<bool, JsValue> {
+        self.inner.add_tag(id, tag)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_tag(&mut self, id: &str, tag: &str) -> Result<bool, JsValue> {
+        self.inner.remove_tag(id, tag)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn files_with_tag(&self, tag: &str) -> Result<JsValue, JsValue> {
+        let files = self.inner.files_with_tag(tag);
+        serde_wasm_bindgen::to_value(&files).map_err(|e| e.into())
+    }
+
+    /// `kind` is `"DerivedFrom"`, `"BelongsTo"`, `"References"` or
+    /// `{ Custom: name }`.
+    #[wasm_bindgen]
+    pub fn link(&mut self, from: &str, to: &str, kind: JsValue) -> Result<(), JsValue> {
+        let kind: LinkKind = serde_wasm_bindgen::from_value(kind)?;
+        self.inner.link(from, to, kind)
+            .map_err(JsValue::from)
     }
-}
\ No newline at end of file
+
+    #[wasm_bindgen]
+    pub fn unlink(&mut self, from: &str, to: &str, kind: JsValue) -> Result<bool, JsValue> {
+        let kind: LinkKind = serde_wasm_bindgen::from_value(kind)?;
+        self.inner.unlink(from, to, &kind)
+            .map_err(JsValue::from)
+    }
+
+    #[wasm_bindgen]
+    pub fn outgoing_links(&self, id: &str) -> Result<JsValue, JsValue> {
+        self.inner.outgoing_links(id)
+            .map_err(JsValue::from)
+            .and_then(|links| serde_wasm_bindgen::to_value(&links).map_err(|e| e.into()))
+    }
+
+    #[wasm_bindgen]
+    pub fn incoming_links(&self, id: &str) -> Result<JsValue, JsValue> {
+        self.inner.incoming_links(id)
+            .map_err(JsValue::from)
+            .and_then(|links| serde_wasm_bindgen::to_value(&links).map_err(|e| e.into()))
+    }
+
+    /// `direction` is `"Outgoing"` or `"Incoming"`, `kind` may be null to
+    /// follow every kind of link.
+    #[wasm_bindgen]
+    pub fn lineage(&self, id: &str, kind: JsValue, direction: JsValue) -> Result<JsValue, JsValue> {
+        let kind: Option<LinkKind> = serde_wasm_bindgen::from_value(kind)?;
+        let direction: LinkDirection = serde_wasm_bindgen::from_value(direction)?;
+        self.inner.lineage(id, kind.as_ref(), direction)
+            .map_err(JsValue::from)
+            .and_then(|ids| serde_wasm_bindgen::to_value(&ids).map_err(|e| e.into()))
+    }
+
+    #[wasm_bindgen]
+    pub fn set_cascade_rule(&mut self, kind: JsValue, rule: JsValue) -> Result<(), JsValue> {
+        let kind: LinkKind = serde_wasm_bindgen::from_value(kind)?;
+        let rule: CascadeRule = serde_wasm_bindgen::from_value(rule)?;
+        self.inner.set_cascade_rule(kind, rule);
+        Ok(())
+    }
+
+    /// Applies a list of `{ op: "Create" | "Update" | "Delete" |
+    /// "SetMetadata" | "Mkdir" | "Move", ... }` operations atomically.
+    /// Resolves to the created file id per operation (null for the rest).
+    #[wasm_bindgen]
+    pub fn apply_batch(&mut self, ops: JsValue) -> Result<JsValue, JsValue> {
+        let ops: Vec<BatchOp> = serde_wasm_bindgen::from_value(ops)?;
+        self.inner.apply_batch(ops)
+            .map_err(JsValue::from)
+            .and_then(|ids| serde_wasm_bindgen::to_value(&ids).map_err(|e| e.into()))
+    }
+
+    /// Calls `callback(event)` after every matching change. `filter` is
+    /// `"All"`, `{ File: id }` or `{ Path: path }`; null or undefined
+    /// means all events.
+    ///
+    /// Events are delivered in order from a microtask, once the call that
+    /// caused them has returned, so callbacks are free to call back into
+    /// the file system. An exception thrown by a callback is logged to the
+    /// console and doesn't stop delivery. Nothing is delivered after
+    /// `unsubscribe`, including events still queued.
+    #[wasm_bindgen]
+    pub fn subscribe(&mut self, filter: JsValue, callback: js_sys::Function) -> Result<WasmSubscription, JsValue> {
+        let filter: EventFilter = if filter.is_undefined() || filter.is_null() {
+            EventFilter::All
+        } else {
+            serde_wasm_bindgen::from_value(filter)?
+        };
+
+        let active = Rc::new(Cell::new(true));
+        let delivering = active.clone();
+        let inner = self.inner.subscribe(filter, move |event| {
+            let event = match serde_wasm_bindgen::to_value(event) {
+                Ok(event) => event,
+                Err(error) => {
+                    console::error_2(&"Could not convert file system event:".into(), &error.into());
+                    return;
+                }
+            };
+            let (callback, active) = (callback.clone(), delivering.clone());
+            // The file system is still borrowed here, calling into it from
+            // the callback right away would fail.
+            wasm_bindgen_futures::spawn_local(async move {
+                if !active.get() {
+                    return;
+                }
+                if let Err(error) = callback.call1(&JsValue::NULL, &event) {
+                    console::error_2(&"File system event callback threw:".into(), &error);
+                }
+            });
+        });
+        Ok(WasmSubscription { inner, active })
+    }
+
+    #[wasm_bindgen]
+    pub fn set_type_detection(&mut self, mode: JsValue) -> Result<(), JsValue> {
+        let mode: TypeDetectionMode = serde_wasm_bindgen::from_value(mode)?;
+        self.inner.set_type_detection(mode);
+        Ok(())
+    }
+}
+
+// Maps come out as plain objects rather than ES `Map`s, so metadata reads
+// back in the shape it was written.
+fn to_plain_value<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, JsValue> {
+    value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| e.into())
+}
+
+#[wasm_bindgen]
+pub struct WasmSubscription {
+    inner: Subscription,
+    // cleared on unsubscribe, so queued events are dropped
+    active: Rc<Cell<bool>>,
+}
+
+#[wasm_bindgen]
+impl WasmSubscription {
+    #[wasm_bindgen]
+    pub fn unsubscribe(&self) {
+        self.inner.unsubscribe();
+        self.active.set(false);
+    }
+}
+
+struct JsContentProvider {
+    load: js_sys::Function,
+    store: Option<js_sys::Function>,
+}
+
+impl ContentProvider for JsContentProvider {
+    fn store(&mut self, checksum: &str, content: &[u8]) -> VfsResult<()> {
+        if let Some(store) = &self.store {
+            let bytes = js_sys::Uint8Array::from(content);
+            store.call2(&JsValue::NULL, &JsValue::from_str(checksum), &bytes)
+                .map_err(|e| VfsError::Storage { reason: e.as_string().unwrap_or_else(|| format!("{:?}", e)) })?;
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, checksum: &str) -> Option<Vec<u8>> {
+        let value = self.load.call1(&JsValue::NULL, &JsValue::from_str(checksum)).ok()?;
+        if value.is_undefined() || value.is_null() {
+            return None;
+        }
+        Some(js_sys::Uint8Array::new(&value).to_vec())
+    }
+}
+
+#[wasm_bindgen]
+extern "C" {
+    /// Storage implemented in JS. Every method returns a Promise:
+    /// `load()` resolves to `{ records, directories, encryption, settings }`,
+    /// `get_blob(checksum)` to a Uint8Array or null, and `apply(changes)`
+    /// stores a batch of `{ records, removed_records, blobs, removed_blobs,
+    /// directories, encryption, settings }`. `encryption` is the header
+    /// needed to unlock encrypted content and must be kept whenever it is
+    /// set; `settings` replaces the stored settings whenever it is set.
+    pub type JsStorageAdapter;
+
+    #[wasm_bindgen(method, js_name = load)]
+    fn js_load(this: &JsStorageAdapter) -> js_sys::Promise;
+
+    #[wasm_bindgen(method, js_name = get_blob)]
+    fn js_get_blob(this: &JsStorageAdapter, checksum: &str) -> js_sys::Promise;
+
+    #[wasm_bindgen(method, js_name = apply)]
+    fn js_apply(this: &JsStorageAdapter, changes: JsValue) -> js_sys::Promise;
+}
+
+impl AsyncStorageBackend for JsStorageAdapter {
+    async fn load(&mut self) -> VfsResult<StoredState> {
+        let state = settle(self.js_load()).await?;
+        if state.is_undefined() || state.is_null() {
+            return Ok(StoredState::default());
+        }
+        serde_wasm_bindgen::from_value(state)
+            .map_err(|e| VfsError::Storage { reason: e.to_string() })
+    }
+
+    async fn get_blob(&mut self, checksum: &str) -> VfsResult<Option<Vec<u8>>> {
+        let value = settle(self.js_get_blob(checksum)).await?;
+        if value.is_undefined() || value.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(js_sys::Uint8Array::new(&value).to_vec()))
+    }
+
+    async fn apply(&mut self, changes: &StorageChanges) -> VfsResult<()> {
+        let changes = to_plain_value(changes)
+            .map_err(|e| VfsError::Storage { reason: format!("{:?}", e) })?;
+        settle(self.js_apply(changes)).await.map(|_| ())
+    }
+}
+
+async fn settle(promise: js_sys::Promise) -> VfsResult<JsValue> {
+    JsFuture::from(promise).await
+        .map_err(|e| VfsError::Storage { reason: e.as_string().unwrap_or_else(|| format!("{:?}", e)) })
+}
+
+#[wasm_bindgen]
+pub fn detect_file_type(content: &[u8], name: &str) -> Result<JsValue, JsValue> {
+    let detection = crate::core::detect_file_type(content, name);
+    serde_wasm_bindgen::to_value(&detection).map_err(|e| e.into())
+}
@@ -1,7 +1,6 @@
 use wasm_bindgen::prelude::*;
 use web_sys::console;
-use serde::Serialize;
-use crate::core::{CSVMetadata, DataType, ColumnMetadata, SqlStatements, NumericStats, StringStats};
+use crate::core::{CSVMetadata, CsvDialect, CsvError};
 
 #[wasm_bindgen]
 pub struct WasmCSVMetadata {
@@ -16,7 +15,31 @@ impl WasmCSVMetadata {
         
         CSVMetadata::analyze(csv_data)
             .map(|metadata| WasmCSVMetadata { inner: metadata })
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(JsValue::from)
+    }
+
+    /// Profiles with `dialect` instead of the sniffed one; to change one
+    /// field, pass `{ ...WasmCSVMetadata.sniff_dialect(data), delimiter: ";" }`.
+    #[wasm_bindgen]
+    pub fn with_dialect(csv_data: &[u8], dialect: JsValue) -> Result<WasmCSVMetadata, JsValue> {
+        let dialect: CsvDialect = serde_wasm_bindgen::from_value(dialect)?;
+        CSVMetadata::analyze_with_dialect(csv_data, dialect)
+            .map(|metadata| WasmCSVMetadata { inner: metadata })
+            .map_err(JsValue::from)
+    }
+
+    /// `{ delimiter, quote, escape, line_terminator, encoding }` as detected
+    /// from the start of `csv_data`.
+    #[wasm_bindgen]
+    pub fn sniff_dialect(csv_data: &[u8]) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&CsvDialect::sniff(csv_data))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_dialect(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.inner.dialect)
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
     }
 
     #[wasm_bindgen]
@@ -38,7 +61,7 @@ impl WasmCSVMetadata {
     #[wasm_bindgen]
     pub fn get_column_info(&self, column_name: &str) -> Result<JsValue, JsValue> {
         self.inner.columns.get(column_name)
-            .ok_or_else(|| JsValue::from_str("Column not found"))
+            .ok_or_else(|| CsvError::ColumnNotFound { name: column_name.to_string() }.into())
             .and_then(|col| serde_wasm_bindgen::to_value(col)
                 .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e))))
     }
@@ -57,6 +80,8 @@ impl WasmCSVMetadata {
                 .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e))))
     }
 
+    // `toString` on the JS side, where `Display` doesn't reach
+    #[allow(clippy::inherent_to_string)]
     #[wasm_bindgen]
     pub fn to_string(&self) -> String {
         format!("{:#?}", self.inner)
@@ -0,0 +1,30 @@
+use wasm_bindgen::prelude::*;
+use js_sys::{Error, Reflect};
+use crate::core::{VfsError, CsvError};
+
+// Errors cross into JS as real `Error` objects with extra properties:
+//   code    - stable identifier such as "NOT_FOUND" or "CSV_PARSE_ERROR"
+//   details - the structured variant fields, e.g. { NotFound: { id } }
+fn to_js_error<T: serde::Serialize>(code: &str, message: String, details: &T) -> JsValue {
+    let error = Error::new(&message);
+    error.set_name("VfsError");
+
+    let _ = Reflect::set(&error, &"code".into(), &code.into());
+    if let Ok(details) = serde_wasm_bindgen::to_value(details) {
+        let _ = Reflect::set(&error, &"details".into(), &details);
+    }
+
+    error.into()
+}
+
+impl From<VfsError> for JsValue {
+    fn from(error: VfsError) -> Self {
+        to_js_error(error.code(), error.to_string(), &error)
+    }
+}
+
+impl From<CsvError> for JsValue {
+    fn from(error: CsvError) -> Self {
+        to_js_error(error.code(), error.to_string(), &error)
+    }
+}
@@ -1,5 +1,6 @@
 mod bindings;
+mod errors;
 mod csv_metadata_bindings;
 
-pub use self::bindings::WasmFileSystem;
+pub use self::bindings::{WasmFileSystem, WasmSubscription, JsStorageAdapter};
 pub use self::csv_metadata_bindings::WasmCSVMetadata;
\ No newline at end of file